@@ -0,0 +1,181 @@
+use std::{fmt::Display, future::pending, sync::Arc};
+
+use tokio::sync::watch;
+
+use crate::generate_secret;
+
+/// Shared secret clients authenticate with, which can be rotated while the server is running
+#[derive(Clone)]
+pub struct Secret(watch::Sender<Arc<String>>);
+
+impl Secret {
+    pub fn new(secret: String) -> Self {
+        Self(watch::Sender::new(Arc::new(secret)))
+    }
+
+    /// The secret currently accepted
+    pub fn current(&self) -> Arc<String> {
+        self.0.borrow().clone()
+    }
+
+    /// Replace the secret with a new random one, which revokes every token issued before
+    pub fn rotate(&self) -> Arc<String> {
+        let secret = Arc::new(generate_secret());
+        self.0.send_replace(secret.clone());
+
+        secret
+    }
+
+    /// Receive the secret whenever it is rotated
+    pub fn subscribe(&self) -> watch::Receiver<Arc<String>> {
+        self.0.subscribe()
+    }
+
+    /// Check a token against the current secret, in constant time
+    pub fn verify(&self, token: &str) -> bool {
+        constant_time_eq(token.as_bytes(), self.0.borrow().as_bytes())
+    }
+
+    /// Verify a token, returning a revocation that completes when the verified secret is rotated
+    pub fn authenticate(&self, token: &str) -> Option<Revocation> {
+        let mut secret_receiver = self.0.subscribe();
+        let is_valid = constant_time_eq(
+            token.as_bytes(),
+            secret_receiver.borrow_and_update().as_bytes(),
+        );
+
+        is_valid.then_some(Revocation(Some(secret_receiver)))
+    }
+}
+
+/// Compare two byte strings without returning early, so the time taken does not reveal where they differ.
+/// Only the length is leaked, which is the same for every generated secret
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let difference = a
+        .iter()
+        .zip(b)
+        .fold(0u8, |difference, (a, b)| difference | (a ^ b));
+
+    std::hint::black_box(difference) == 0
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BearerError {
+    /// The authorization uses another scheme than `Bearer`
+    Scheme,
+    /// The token contains characters not allowed in a `b64token`
+    Token,
+}
+
+impl Display for BearerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BearerError::Scheme => write!(f, "Authorization must use the Bearer scheme"),
+            BearerError::Token => write!(f, "Bearer token is malformed"),
+        }
+    }
+}
+
+impl std::error::Error for BearerError {}
+
+/// Parse the token from an `Authorization: Bearer <token>` header, as defined in RFC 6750 section 2.1
+pub fn parse_bearer(header: &str) -> Result<&str, BearerError> {
+    let (scheme, token) = header.split_once(' ').ok_or(BearerError::Scheme)?;
+
+    // The scheme is case-insensitive, and may be followed by any number of spaces
+    if !scheme.eq_ignore_ascii_case("Bearer") {
+        return Err(BearerError::Scheme);
+    }
+
+    let token = token.trim_start_matches(' ');
+
+    is_b64token(token)
+        .then_some(token)
+        .ok_or(BearerError::Token)
+}
+
+/// `b64token = 1*( ALPHA / DIGIT / "-" / "." / "_" / "~" / "+" / "/" ) *"="`
+fn is_b64token(token: &str) -> bool {
+    let body = token.trim_end_matches('=');
+
+    let is_valid = !body.is_empty()
+        && body
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"-._~+/".contains(&byte));
+
+    is_valid
+}
+
+/// Completes when the secret a connection authenticated with is rotated, so the connection can be closed
+#[derive(Clone)]
+pub struct Revocation(Option<watch::Receiver<Arc<String>>>);
+
+impl Revocation {
+    /// Never completes, used when authentication is disabled
+    pub fn never() -> Self {
+        Self(None)
+    }
+
+    /// Wait until the secret is rotated
+    pub async fn revoked(&mut self) {
+        let is_rotated = match &mut self.0 {
+            Some(secret_receiver) => secret_receiver.changed().await.is_ok(),
+            None => false,
+        };
+
+        // Without authentication, or when the secret can no longer be rotated, it is never revoked
+        if !is_rotated {
+            pending::<()>().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(!constant_time_eq(b"", b"secret"));
+    }
+
+    #[test]
+    fn test_parse_bearer() {
+        assert_eq!(parse_bearer("Bearer abc123"), Ok("abc123"));
+        assert_eq!(parse_bearer("bearer abc123"), Ok("abc123"));
+        assert_eq!(
+            parse_bearer("BEARER   a-b.c_d~e+f/g=="),
+            Ok("a-b.c_d~e+f/g==")
+        );
+
+        assert_eq!(parse_bearer("abc123"), Err(BearerError::Scheme));
+        assert_eq!(parse_bearer("Basic abc123"), Err(BearerError::Scheme));
+        assert_eq!(parse_bearer("Bearer "), Err(BearerError::Token));
+        assert_eq!(parse_bearer("Bearer abc 123"), Err(BearerError::Token));
+        assert_eq!(parse_bearer("Bearer ab=c"), Err(BearerError::Token));
+        assert_eq!(parse_bearer("Bearer ==="), Err(BearerError::Token));
+    }
+
+    #[tokio::test]
+    async fn test_rotate_secret() {
+        let secret = Secret::new("secret".to_owned());
+
+        assert!(secret.authenticate("wrong").is_none());
+        let mut revocation = secret.authenticate("secret").unwrap();
+
+        let rotated = secret.rotate();
+
+        assert!(!secret.verify("secret"));
+        assert!(secret.verify(&rotated));
+
+        // Completes right away, since the secret was rotated after authenticating
+        revocation.revoked().await;
+    }
+}
@@ -0,0 +1,600 @@
+use std::{fmt::Display, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Local};
+use eventsource_stream::Eventsource;
+use futures::{Stream, StreamExt};
+use reqwest::{
+    header::{CONTENT_TYPE, ETAG},
+    RequestBuilder, Response, StatusCode,
+};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    CertificateError, ClientConfig, DigitallySignedStruct, SignatureScheme,
+};
+use serde::Serialize;
+use tokio::time::sleep;
+
+use crate::{
+    clients::ClientIdentity,
+    http_server::{
+        ExperimentAnswer, PromptAnswer, PromptDisplayed, PromptReference, SequencedState,
+        ServerInfo, UnityState,
+    },
+    problem::{Problem, PROBLEM_JSON},
+    tls::fingerprint,
+};
+
+/// Delay before the first attempt to reconnect, doubled for every failed attempt
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request could not be sent, or the connection failed
+    Http(reqwest::Error),
+    Tls(rustls::Error),
+    /// The server rejected the request
+    Rejected(Problem),
+    /// The server failed without problem details
+    Status(StatusCode),
+    /// A state sent by the server could not be parsed
+    InvalidState(serde_json::Error),
+}
+
+impl ClientError {
+    /// Whether retrying the same request cannot succeed, e.g. because the secret is wrong
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            ClientError::Http(_) => false,
+            ClientError::Tls(_) => true,
+            ClientError::Rejected(problem) => (400..500).contains(&problem.status),
+            ClientError::Status(status) => status.is_client_error(),
+            ClientError::InvalidState(_) => true,
+        }
+    }
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Http(error) => write!(f, "Request failed: {error}"),
+            ClientError::Tls(error) => write!(f, "Invalid TLS configuration: {error}"),
+            ClientError::Rejected(problem) => {
+                write!(
+                    f,
+                    "{} ({}): {}",
+                    problem.title, problem.code, problem.detail
+                )
+            }
+            ClientError::Status(status) => write!(f, "Server responded with {status}"),
+            ClientError::InvalidState(error) => write!(f, "Invalid state: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(error: reqwest::Error) -> Self {
+        ClientError::Http(error)
+    }
+}
+
+/// Client for the API the headset uses, for tools that act as or watch a headset
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    /// Base URL of the server, e.g. `http://127.0.0.1:9001`
+    url: String,
+    secret: Option<String>,
+    identity: ClientIdentity,
+}
+
+impl Client {
+    /// A client for a server using plain HTTP, or HTTPS with a certificate trusted by a certificate authority.
+    /// The secret is the one from the QR code, None if the server does not require authentication
+    pub fn new(url: impl Into<String>, secret: Option<String>) -> Result<Self, ClientError> {
+        let http = reqwest::Client::builder().use_rustls_tls().build()?;
+
+        Ok(Self::with_http_client(url, secret, http))
+    }
+
+    /// A client for a server using a self-signed certificate, which is only trusted if its SHA-256 fingerprint matches,
+    /// like the fingerprint in the QR code
+    pub fn with_fingerprint(
+        url: impl Into<String>,
+        secret: Option<String>,
+        fingerprint: impl Into<String>,
+    ) -> Result<Self, ClientError> {
+        let provider = Arc::new(ring::default_provider());
+        let verifier = PinnedCertificate {
+            fingerprint: fingerprint.into().to_ascii_lowercase(),
+            provider: provider.clone(),
+        };
+
+        let tls_config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(ClientError::Tls)?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth();
+
+        let http = reqwest::Client::builder()
+            .use_preconfigured_tls(tls_config)
+            .build()?;
+
+        Ok(Self::with_http_client(url, secret, http))
+    }
+
+    fn with_http_client(
+        url: impl Into<String>,
+        secret: Option<String>,
+        http: reqwest::Client,
+    ) -> Self {
+        Self {
+            http,
+            url: url.into().trim_end_matches('/').to_owned(),
+            secret,
+            identity: ClientIdentity::default(),
+        }
+    }
+
+    /// Tell the server who is connecting when subscribing, shown in the list of connected headsets
+    pub fn with_identity(mut self, identity: ClientIdentity) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// What the server supports, does not require the secret
+    pub async fn info(&self) -> Result<ServerInfo, ClientError> {
+        let request = self.http.get(format!("{}/info", self.url));
+        let info = self.send(request).await?.json::<ServerInfo>().await?;
+
+        Ok(info)
+    }
+
+    /// The state currently shown, with the sequence number it was broadcast with
+    pub async fn current_state(&self) -> Result<SequencedState, ClientError> {
+        let request = self.http.get(format!("{}/state/current", self.url));
+        let response = self.send(request).await?;
+
+        let sequence = response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .and_then(|etag| etag.trim_matches('"').parse::<u64>().ok())
+            .unwrap_or_default();
+
+        let state = response.json::<UnityState>().await?;
+
+        Ok(SequencedState { sequence, state })
+    }
+
+    /// Every state from the current one on.
+    /// Reconnects when the connection is lost, resuming after the last state received so no state is received twice.
+    /// Failed attempts to reconnect are yielded as errors before retrying, so the caller decides whether to report them.
+    /// Ends after an error that reconnecting cannot fix, e.g. when the secret was rotated
+    pub fn subscribe(&self) -> impl Stream<Item = Result<SequencedState, ClientError>> + 'static {
+        let client = self.clone();
+
+        async_stream::stream! {
+            let mut last_sequence = None;
+            let mut retry_delay = INITIAL_RETRY_DELAY;
+
+            loop {
+                match client.open_subscription(last_sequence).await {
+                    Ok(response) => {
+                        retry_delay = INITIAL_RETRY_DELAY;
+
+                        let mut events = response.bytes_stream().eventsource();
+
+                        while let Some(Ok(event)) = events.next().await {
+                            // Only tells whether the state is sent again, which the sequence numbers already do
+                            if event.event == "resume" {
+                                continue;
+                            }
+
+                            let state = match serde_json::from_str::<UnityState>(&event.data) {
+                                Ok(state) => state,
+                                Err(error) => {
+                                    yield Err(ClientError::InvalidState(error));
+                                    continue;
+                                }
+                            };
+
+                            let sequence = event.id.parse::<u64>().unwrap_or_default();
+                            last_sequence = Some(sequence);
+
+                            yield Ok(SequencedState { sequence, state });
+                        }
+                    }
+                    Err(error) if error.is_permanent() => {
+                        yield Err(error);
+                        break;
+                    }
+                    Err(error) => yield Err(error),
+                }
+
+                sleep(retry_delay).await;
+                retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+            }
+        }
+    }
+
+    async fn open_subscription(&self, last_sequence: Option<u64>) -> Result<Response, ClientError> {
+        let request = self
+            .http
+            .get(format!("{}/state/subscribe", self.url))
+            .query(&self.identity);
+
+        let request = match last_sequence {
+            Some(sequence) => request.header("last-event-id", sequence.to_string()),
+            None => request,
+        };
+
+        self.send(request).await
+    }
+
+    /// Answer the prompt, fails if it is no longer shown or the answer does not fit it
+    pub async fn answer(
+        &self,
+        prompt: &PromptReference,
+        answer: ExperimentAnswer,
+    ) -> Result<(), ClientError> {
+        let body = PromptAnswer {
+            prompt: prompt.clone(),
+            answer,
+        };
+
+        self.post("/experiment/answer", &body).await
+    }
+
+    /// Swap between the two presets of a choice prompt
+    pub async fn swap(&self, prompt: &PromptReference) -> Result<(), ClientError> {
+        self.post("/experiment/swap", prompt).await
+    }
+
+    /// Acknowledge that the prompt is displayed, response times are measured from this
+    pub async fn displayed(
+        &self,
+        prompt: &PromptReference,
+        displayed_at: DateTime<Local>,
+    ) -> Result<(), ClientError> {
+        let body = PromptDisplayed {
+            prompt: prompt.clone(),
+            displayed_at,
+        };
+
+        self.post("/experiment/displayed", &body).await
+    }
+
+    async fn post(&self, path: &str, body: &impl Serialize) -> Result<(), ClientError> {
+        let request = self.http.post(format!("{}{path}", self.url)).json(body);
+        self.send(request).await?;
+
+        Ok(())
+    }
+
+    /// Send a request with the secret, turning error responses into errors
+    async fn send(&self, request: RequestBuilder) -> Result<Response, ClientError> {
+        let request = match &self.secret {
+            Some(secret) => request.bearer_auth(secret),
+            None => request,
+        };
+
+        let response = request.send().await?;
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let is_problem = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .is_some_and(|content_type| content_type == PROBLEM_JSON);
+
+        let error = match is_problem {
+            true => ClientError::Rejected(response.json::<Problem>().await?),
+            false => ClientError::Status(status),
+        };
+
+        Err(error)
+    }
+}
+
+/// Trusts only the certificate with the fingerprint, instead of certificates signed by a certificate authority
+#[derive(Debug)]
+struct PinnedCertificate {
+    fingerprint: String,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        // The certificate is self-signed for `neuroviz.local`, so neither the issuer nor the name can be checked
+        match fingerprint(end_entity) == self.fingerprint {
+            true => Ok(ServerCertVerified::assertion()),
+            false => Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            )),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::copy_bidirectional,
+        net::{TcpListener, TcpStream},
+        sync::{mpsc, watch},
+        time::timeout,
+    };
+
+    use super::*;
+    use crate::{
+        auth::Secret,
+        clients::ClientRegistry,
+        http_server::{
+            ExperimentPrompt, HttpServer, Rejection, UnityEvent, UnityExperimentType,
+            UnityStateSender,
+        },
+        parameters::ParameterValues,
+        problem::ProblemCode,
+        tls::TlsCertificate,
+    };
+
+    fn rating_prompt(reference: &PromptReference) -> UnityState {
+        UnityState::Experiment {
+            prompt: ExperimentPrompt {
+                reference: reference.clone(),
+                experiment_type: UnityExperimentType::Rating,
+                parameters: ParameterValues::default(),
+                transition: None,
+                response_timeout: None,
+                practice: false,
+            },
+        }
+    }
+
+    fn http_server(
+        unity_state_sender: &UnityStateSender,
+        event_sender: mpsc::Sender<UnityEvent>,
+        secret: Option<Secret>,
+    ) -> HttpServer {
+        HttpServer {
+            name: "NeuroViz".to_owned(),
+            state: unity_state_sender.subscribe(),
+            event_sender,
+            secret,
+            clients: ClientRegistry::new(),
+        }
+    }
+
+    async fn spawn_app(http_server: HttpServer) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(http_server.serve(listener));
+
+        format!("http://127.0.0.1:{port}")
+    }
+
+    /// Forwards connections to the address until told to drop them, to simulate a flaky network
+    async fn spawn_proxy(address: String, kill: watch::Receiver<()>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut inbound, _) = listener.accept().await.unwrap();
+                let mut outbound = TcpStream::connect(&address).await.unwrap();
+                let mut kill = kill.clone();
+                kill.mark_unchanged();
+
+                tokio::spawn(async move {
+                    tokio::select! {
+                        _ = copy_bidirectional(&mut inbound, &mut outbound) => {},
+                        _ = kill.changed() => {},
+                    }
+                });
+            }
+        });
+
+        format!("http://127.0.0.1:{port}")
+    }
+
+    /// Test subscribing and answering, and that rejections carry the problem details
+    #[tokio::test]
+    async fn test_subscribe_and_answer() {
+        let prompt = PromptReference::new(0);
+        let unity_state_sender = UnityStateSender::new(rating_prompt(&prompt));
+        let (event_sender, mut event_receiver) = mpsc::channel(100);
+        let secret = Secret::new("secret".to_owned());
+
+        // Accepts ratings and rejects choices, like the owner of a rating experiment would
+        tokio::spawn(async move {
+            while let Some(event) = event_receiver.recv().await {
+                let UnityEvent::Answer(prompt_answer, responder) = event else {
+                    continue;
+                };
+
+                match prompt_answer.answer {
+                    ExperimentAnswer::Choice => responder.respond(Err(Rejection::WrongState(
+                        "Not in a choice experiment".to_owned(),
+                    ))),
+                    ExperimentAnswer::Rating { .. } => responder.respond(Ok(())),
+                }
+            }
+        });
+
+        let url = spawn_app(http_server(
+            &unity_state_sender,
+            event_sender,
+            Some(secret.clone()),
+        ))
+        .await;
+
+        let client = Client::new(&url, Some(secret.current().to_string())).unwrap();
+        let mut states = Box::pin(client.subscribe());
+
+        let first = states.next().await.unwrap().unwrap();
+        assert_eq!(first.state, rating_prompt(&prompt));
+
+        client
+            .answer(&prompt, ExperimentAnswer::Rating { value: 4 })
+            .await
+            .unwrap();
+
+        let Err(ClientError::Rejected(problem)) =
+            client.answer(&prompt, ExperimentAnswer::Choice).await
+        else {
+            panic!("Choice should be rejected");
+        };
+        assert_eq!(problem.code, ProblemCode::WrongState);
+
+        unity_state_sender.send(UnityState::Idle);
+        let next = states.next().await.unwrap().unwrap();
+        assert_eq!(next.sequence, first.sequence + 1);
+        assert_eq!(next.state, UnityState::Idle);
+
+        assert_eq!(client.current_state().await.unwrap(), next);
+
+        // A wrong secret cannot be fixed by retrying, so the stream ends
+        let client = Client::new(&url, Some("wrong".to_owned())).unwrap();
+        let mut states = Box::pin(client.subscribe());
+        let error = states.next().await.unwrap().unwrap_err();
+        assert!(error.is_permanent());
+        assert!(states.next().await.is_none());
+    }
+
+    /// Test that the subscription reconnects when the connection drops, without repeating states
+    #[tokio::test]
+    async fn test_reconnect() {
+        let unity_state_sender = UnityStateSender::new(UnityState::Idle);
+        let (event_sender, _event_receiver) = mpsc::channel(100);
+
+        let url = spawn_app(http_server(&unity_state_sender, event_sender, None)).await;
+        let (kill_sender, kill_receiver) = watch::channel(());
+        let proxy_url =
+            spawn_proxy(url.trim_start_matches("http://").to_owned(), kill_receiver).await;
+
+        let client = Client::new(proxy_url, None).unwrap();
+        let mut states = Box::pin(client.subscribe());
+
+        let first = states.next().await.unwrap().unwrap();
+        assert_eq!(first.sequence, 0);
+
+        kill_sender.send(()).unwrap();
+        let prompt = PromptReference::new(0);
+        unity_state_sender.send(rating_prompt(&prompt));
+
+        let next = timeout(Duration::from_secs(5), states.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(next.sequence, 1);
+        assert_eq!(next.state, rating_prompt(&prompt));
+    }
+
+    /// Test that failed attempts to connect are yielded as errors, and that the subscription keeps retrying
+    #[tokio::test]
+    async fn test_retry_errors() {
+        // Nothing listens on the port once the listener is dropped
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let client = Client::new(url, None).unwrap();
+        let mut states = Box::pin(client.subscribe());
+
+        for _ in 0..2 {
+            let error = states.next().await.unwrap().unwrap_err();
+            assert!(!error.is_permanent());
+        }
+    }
+
+    /// Test that only the certificate with the pinned fingerprint is trusted
+    #[tokio::test]
+    async fn test_pinned_certificate() {
+        let unity_state_sender = UnityStateSender::new(UnityState::Idle);
+        let (event_sender, _event_receiver) = mpsc::channel(100);
+
+        let folder =
+            std::env::temp_dir().join(format!("neuroviz-client-{}", crate::generate_secret()));
+        let certificate = TlsCertificate::load_or_generate(&folder).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let http_server = http_server(&unity_state_sender, event_sender, None);
+
+        tokio::spawn({
+            let certificate = certificate.clone();
+            async move { http_server.serve_tls(listener, &certificate).await.unwrap() }
+        });
+
+        let url = format!("https://127.0.0.1:{port}");
+
+        let client = Client::with_fingerprint(&url, None, certificate.fingerprint()).unwrap();
+        assert_eq!(
+            client.current_state().await.unwrap().state,
+            UnityState::Idle
+        );
+
+        let client = Client::with_fingerprint(&url, None, "0".repeat(64)).unwrap();
+        assert!(matches!(
+            client.current_state().await,
+            Err(ClientError::Http(_))
+        ));
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    fmt::Display,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::sync::watch;
+
+/// Identifies a single connection, unique for the lifetime of the server
+#[derive(Deserialize, Serialize, Type, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ClientId(u32);
+
+impl Display for ClientId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// What a client tells about itself when subscribing, every field is optional
+#[derive(Deserialize, Serialize, Type, Clone, Debug, Default, PartialEq)]
+pub struct ClientIdentity {
+    /// Stable identifier of the device, e.g. the Android device id
+    pub device_id: Option<String>,
+    /// Version of the headset app
+    pub app_version: Option<String>,
+    /// Model of the device, e.g. `XREAL Air 2 Ultra`
+    pub model: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Type, Clone, Debug, PartialEq)]
+pub struct ConnectedClient {
+    pub id: ClientId,
+    pub identity: ClientIdentity,
+    pub connected_at: DateTime<Local>,
+    /// Last time data was exchanged with the client
+    pub last_seen: DateTime<Local>,
+    pub remote_address: Option<String>,
+}
+
+/// Keeps track of every client currently subscribed to the server
+#[derive(Clone)]
+pub struct ClientRegistry {
+    clients: watch::Sender<Vec<ConnectedClient>>,
+    next_id: Arc<AtomicU32>,
+}
+
+impl Default for ClientRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientRegistry {
+    pub fn new() -> Self {
+        Self {
+            clients: watch::Sender::new(Vec::new()),
+            next_id: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    /// The clients currently connected, ordered by connect time
+    pub fn clients(&self) -> Vec<ConnectedClient> {
+        self.clients.borrow().clone()
+    }
+
+    /// Receive the list of clients whenever a client connects, disconnects or is seen
+    pub fn subscribe(&self) -> watch::Receiver<Vec<ConnectedClient>> {
+        self.clients.subscribe()
+    }
+
+    /// Register a new client, which stays registered until the returned guard is dropped
+    pub fn connect(
+        &self,
+        identity: ClientIdentity,
+        remote_address: Option<SocketAddr>,
+    ) -> ClientGuard {
+        let now = Local::now();
+
+        let client = ConnectedClient {
+            id: ClientId(self.next_id.fetch_add(1, Ordering::Relaxed)),
+            identity,
+            connected_at: now,
+            last_seen: now,
+            remote_address: remote_address.map(|address| address.to_string()),
+        };
+
+        self.clients
+            .send_modify(|clients| clients.push(client.clone()));
+
+        ClientGuard {
+            registry: self.clone(),
+            client,
+        }
+    }
+
+    fn touch(&self, id: ClientId) {
+        self.clients.send_modify(|clients| {
+            if let Some(client) = clients.iter_mut().find(|client| client.id == id) {
+                client.last_seen = Local::now();
+            }
+        });
+    }
+
+    fn disconnect(&self, id: ClientId) {
+        self.clients
+            .send_modify(|clients| clients.retain(|client| client.id != id));
+    }
+}
+
+/// Registration of a connected client, removes the client from the registry when dropped
+pub struct ClientGuard {
+    registry: ClientRegistry,
+    client: ConnectedClient,
+}
+
+impl ClientGuard {
+    /// The client as it was when it connected
+    pub fn client(&self) -> &ConnectedClient {
+        &self.client
+    }
+
+    /// Update the last seen time of the client to now
+    pub fn touch(&self) {
+        self.registry.touch(self.client.id);
+    }
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.registry.disconnect(self.client.id);
+    }
+}
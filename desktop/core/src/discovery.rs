@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+
+use crate::http_server::PROTOCOL_VERSION;
+
+/// DNS-SD service type the headset browses for
+pub const SERVICE_TYPE: &str = "_neuroviz._tcp.local.";
+
+/// What a controller advertises about itself, so the headset can list controllers and connect to one
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceAdvertisement {
+    /// Human readable name the headset shows in the list of controllers, e.g. `NeuroViz on Lab PC`
+    pub instance_name: String,
+    pub port: u16,
+    /// Whether the server requires the secret from the QR code
+    pub requires_auth: bool,
+    /// Whether the server serves HTTPS
+    pub tls: bool,
+}
+
+impl ServiceAdvertisement {
+    /// A name based on the name of this device, e.g. `NeuroViz on Lab PC`
+    pub fn default_instance_name() -> String {
+        let instance_name = match whoami::fallible::devicename() {
+            Ok(device_name) => format!("NeuroViz on {device_name}"),
+            Err(_) => "NeuroViz".to_owned(),
+        };
+
+        instance_name
+    }
+
+    /// The TXT records of the service
+    pub fn txt_properties(&self) -> HashMap<String, String> {
+        HashMap::from([
+            ("protocol".to_owned(), PROTOCOL_VERSION.to_string()),
+            (
+                "auth".to_owned(),
+                match self.requires_auth {
+                    true => "bearer".to_owned(),
+                    false => "none".to_owned(),
+                },
+            ),
+            ("name".to_owned(), self.instance_name.clone()),
+            (
+                "scheme".to_owned(),
+                match self.tls {
+                    true => "https".to_owned(),
+                    false => "http".to_owned(),
+                },
+            ),
+        ])
+    }
+
+    /// Host name derived from the instance name, since the service needs a host name in the `.local.` domain
+    fn host_name(&self) -> String {
+        let label = self
+            .instance_name
+            .chars()
+            .map(|char| match char.is_ascii_alphanumeric() {
+                true => char.to_ascii_lowercase(),
+                false => '-',
+            })
+            .collect::<String>();
+
+        let label = match label.trim_matches('-') {
+            "" => "neuroviz",
+            label => label,
+        };
+
+        format!("{label}.local.")
+    }
+}
+
+/// Advertises the server on every network interface while alive, and withdraws the advertisement when dropped
+pub struct Advertiser {
+    daemon: ServiceDaemon,
+    fullname: String,
+    instance_name: String,
+}
+
+impl Advertiser {
+    pub fn start(advertisement: ServiceAdvertisement) -> Result<Self, mdns_sd::Error> {
+        let daemon = ServiceDaemon::new()?;
+
+        // No addresses are given, so every address of every interface is announced, and kept up to date
+        let service_info = ServiceInfo::new(
+            SERVICE_TYPE,
+            &advertisement.instance_name,
+            &advertisement.host_name(),
+            (),
+            advertisement.port,
+            advertisement.txt_properties(),
+        )?
+        .enable_addr_auto();
+
+        let fullname = service_info.get_fullname().to_owned();
+        daemon.register(service_info)?;
+
+        Ok(Self {
+            daemon,
+            fullname,
+            instance_name: advertisement.instance_name,
+        })
+    }
+
+    /// The name the server is advertised with
+    pub fn instance_name(&self) -> &str {
+        &self.instance_name
+    }
+}
+
+impl Drop for Advertiser {
+    fn drop(&mut self) {
+        // Tell the headsets the server is gone, instead of waiting for the record to expire
+        let _ = self.daemon.unregister(&self.fullname);
+        let _ = self.daemon.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_txt_properties() {
+        let advertisement = ServiceAdvertisement {
+            instance_name: "NeuroViz on Lab PC".to_owned(),
+            port: 9001,
+            requires_auth: true,
+            tls: false,
+        };
+
+        let properties = advertisement.txt_properties();
+
+        assert_eq!(properties["protocol"], PROTOCOL_VERSION.to_string());
+        assert_eq!(properties["auth"], "bearer");
+        assert_eq!(properties["name"], "NeuroViz on Lab PC");
+        assert_eq!(properties["scheme"], "http");
+
+        assert_eq!(advertisement.host_name(), "neuroviz-on-lab-pc.local.");
+    }
+}
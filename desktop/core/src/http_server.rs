@@ -1,23 +1,50 @@
 use axum::{
-    extract::{Json, Query, Request, State},
-    http::{header::AUTHORIZATION, StatusCode},
-    middleware::{self, Next},
-    response::{
-        sse::{Event, KeepAlive},
-        Response, Sse,
+    extract::{
+        connect_info::Connected,
+        rejection::JsonRejection,
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, FromRequestParts, Json, Query, Request, State,
+    },
+    http::{
+        header::{AUTHORIZATION, ETAG, IF_NONE_MATCH, WWW_AUTHENTICATE},
+        request::Parts,
+        HeaderMap, Method, StatusCode,
     },
+    middleware::{self, Next},
+    response::{sse::Event, IntoResponse, Response, Sse},
     routing::{get, post},
+    serve::IncomingStream,
     Router,
 };
-use futures::{Stream, StreamExt};
+use chrono::{DateTime, Local};
+use futures::{SinkExt, Stream, StreamExt};
+use rand::{distr::Alphanumeric, Rng};
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use std::{convert::Infallible, sync::Arc, time::Duration};
-use tokio::sync::{mpsc, watch};
+use std::{
+    convert::Infallible, fmt::Display, net::SocketAddr, ops::RangeInclusive, time::Duration,
+};
+use strum::{EnumIter, IntoEnumIterator};
+use tokio::{
+    net::TcpListener,
+    select,
+    sync::{mpsc, oneshot, watch},
+    time::{interval, timeout},
+};
 
-use crate::{extensions::WatchReceiverExt, parameters::ParameterValues};
+use tokio_stream::wrappers::WatchStream;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+use crate::{
+    auth::{parse_bearer, Revocation, Secret},
+    clients::{ClientGuard, ClientId, ClientIdentity, ClientRegistry, ConnectedClient},
+    extensions::WatchReceiverExt,
+    parameters::{Parameter, ParameterValues},
+    problem::{Problem, ProblemCode},
+    tls::{TlsCertificate, TlsListener},
+    transition::Transition,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, EnumIter)]
 pub enum UnityExperimentType {
     #[serde(rename = "choice")]
     Choice,
@@ -25,10 +52,158 @@ pub enum UnityExperimentType {
     Rating,
 }
 
+/// Unique identifier of a single prompt
+#[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq, Eq, Hash)]
+pub struct PromptId(String);
+
+impl PromptId {
+    /// Generate a new random prompt id with 16 characters
+    pub fn random() -> Self {
+        let id = rand::rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect::<String>();
+
+        PromptId(id)
+    }
+}
+
+impl Display for PromptId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Identifies which prompt an answer, swap or display acknowledgement is meant for
+#[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq, Eq)]
+pub struct PromptReference {
+    pub prompt_id: PromptId,
+    /// Index of the trial in the experiment, starting at 0
+    pub trial_index: u32,
+}
+
+impl Display for PromptReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "prompt `{}` (trial {})",
+            self.prompt_id, self.trial_index
+        )
+    }
+}
+
+impl PromptReference {
+    pub fn new(trial_index: u32) -> Self {
+        Self {
+            prompt_id: PromptId::random(),
+            trial_index,
+        }
+    }
+
+    /// Check that this refers to the prompt currently shown, if any
+    pub fn check(&self, current: Option<&PromptReference>) -> Result<(), PromptMismatch> {
+        match current {
+            Some(current) if current == self => Ok(()),
+            Some(current) => Err(PromptMismatch::Stale {
+                expected: current.clone(),
+                got: self.clone(),
+            }),
+            None => Err(PromptMismatch::NoPrompt { got: self.clone() }),
+        }
+    }
+}
+
+/// A request referred to a prompt that is not currently shown, e.g. a double tap or a retried request
+#[derive(Debug, Clone, PartialEq)]
+pub enum PromptMismatch {
+    NoPrompt {
+        got: PromptReference,
+    },
+    Stale {
+        expected: PromptReference,
+        got: PromptReference,
+    },
+}
+
+impl Display for PromptMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PromptMismatch::NoPrompt { got } => write!(f, "No prompt is shown, got {got}"),
+            PromptMismatch::Stale { expected, got } => {
+                write!(f, "Expected {expected}, got {got}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PromptMismatch {}
+
+/// Why the owner of the state did not accept an event from the headset
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+    /// The event refers to a prompt that is not currently shown
+    Prompt(PromptMismatch),
+    /// The event does not fit the current state, e.g. a rating during a choice experiment
+    WrongState(String),
+    /// The event itself is not valid, e.g. a rating outside the scale
+    Invalid(String),
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejection::Prompt(mismatch) => write!(f, "{mismatch}"),
+            Rejection::WrongState(detail) => write!(f, "{detail}"),
+            Rejection::Invalid(detail) => write!(f, "{detail}"),
+        }
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+impl From<PromptMismatch> for Rejection {
+    fn from(mismatch: PromptMismatch) -> Self {
+        Rejection::Prompt(mismatch)
+    }
+}
+
+/// Lets the owner of the state report back whether an event was accepted, to the headset that sent it
+#[derive(Debug)]
+pub struct Responder(Option<oneshot::Sender<Result<(), Rejection>>>);
+
+impl Responder {
+    /// A responder, and the receiver the reply arrives on
+    pub fn new() -> (Self, oneshot::Receiver<Result<(), Rejection>>) {
+        let (sender, receiver) = oneshot::channel();
+
+        (Self(Some(sender)), receiver)
+    }
+
+    pub fn respond(self, result: Result<(), Rejection>) {
+        if let Some(sender) = self.0 {
+            // The request may have been cancelled, then nobody is interested in the result
+            let _ = sender.send(result);
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ExperimentPrompt {
+    #[serde(flatten)]
+    pub reference: PromptReference,
     pub experiment_type: UnityExperimentType,
     pub parameters: ParameterValues,
+    /// How to animate to new parameters while this prompt is shown, e.g. when swapping between A and B
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transition: Option<Transition>,
+    /// Seconds the observer has to answer after the prompt is displayed, None when there is no deadline.
+    /// The deadline starts over when the experiment is resumed after a pause
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_timeout: Option<f64>,
+    /// Whether the prompt is part of the practice, whose answers are kept apart from the others
+    #[serde(default)]
+    pub practice: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -38,12 +213,77 @@ pub enum UnityState {
     Idle,
 
     #[serde(rename = "live")]
-    Live { parameters: ParameterValues },
+    Live {
+        parameters: ParameterValues,
+        /// How to animate from the previously shown parameters, None means switching instantly
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        transition: Option<Transition>,
+    },
 
     #[serde(rename = "experiment")]
     Experiment { prompt: ExperimentPrompt },
 }
 
+/// A Unity state together with the sequence number it was broadcast with
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SequencedState {
+    /// Increases by one for every state that is broadcast
+    pub sequence: u64,
+    #[serde(flatten)]
+    pub state: UnityState,
+}
+
+/// Broadcasts Unity states to the HTTP server, numbering every state that is sent
+#[derive(Debug, Clone)]
+pub struct UnityStateSender(watch::Sender<SequencedState>);
+
+impl UnityStateSender {
+    pub fn new(state: UnityState) -> Self {
+        Self(watch::Sender::new(SequencedState { sequence: 0, state }))
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<SequencedState> {
+        self.0.subscribe()
+    }
+
+    /// The state that was broadcast last
+    pub fn current(&self) -> UnityState {
+        self.0.borrow().state.clone()
+    }
+
+    /// Broadcast a state with a new sequence number
+    pub fn send(&self, state: UnityState) {
+        self.0.send_modify(|current| {
+            current.sequence += 1;
+            current.state = state;
+        });
+    }
+
+    /// Broadcast a state only if it differs from the current state, returns true if it was sent
+    pub fn send_if_changed(&self, state: UnityState) -> bool {
+        self.0.send_if_modified(|current| {
+            if current.state == state {
+                return false;
+            }
+
+            current.sequence += 1;
+            current.state = state;
+
+            true
+        })
+    }
+}
+
+impl UnityState {
+    /// The prompt currently shown, if any
+    pub fn prompt_reference(&self) -> Option<&PromptReference> {
+        match self {
+            UnityState::Experiment { prompt } => Some(&prompt.reference),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq)]
 #[serde(tag = "experiment_type")]
 pub enum ExperimentAnswer {
@@ -54,161 +294,678 @@ pub enum ExperimentAnswer {
     Rating { value: u8 },
 }
 
+/// The values a rating can have
+pub const RATING_SCALE: RangeInclusive<u8> = 1..=5;
+
+impl ExperimentAnswer {
+    /// Check that the answer is valid regardless of the state, e.g. that a rating is on the scale
+    pub fn validate(&self) -> Result<(), Rejection> {
+        match self {
+            ExperimentAnswer::Rating { value } if !RATING_SCALE.contains(value) => {
+                Err(Rejection::Invalid(format!(
+                    "Rating {value} is outside the scale of {} to {}",
+                    RATING_SCALE.start(),
+                    RATING_SCALE.end()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// An answer to a specific prompt
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PromptAnswer {
+    #[serde(flatten)]
+    pub prompt: PromptReference,
+    #[serde(flatten)]
+    pub answer: ExperimentAnswer,
+}
+
+/// Sent by Unity once the current prompt is visible to the user
+#[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq)]
+pub struct PromptDisplayed {
+    #[serde(flatten)]
+    pub prompt: PromptReference,
+    /// When the prompt was displayed, using the clock of the headset
+    pub displayed_at: DateTime<Local>,
+}
+
+/// Messages sent from Unity over the WebSocket connection
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind")]
+pub enum UnityMessage {
+    #[serde(rename = "answer")]
+    Answer {
+        #[serde(flatten)]
+        prompt: PromptReference,
+        answer: ExperimentAnswer,
+    },
+
+    #[serde(rename = "swap")]
+    Swap(PromptReference),
+
+    #[serde(rename = "displayed")]
+    Displayed(PromptDisplayed),
+}
+
+impl UnityMessage {
+    /// The prompt the message is meant for
+    pub fn prompt(&self) -> &PromptReference {
+        match self {
+            UnityMessage::Answer { prompt, .. } => prompt,
+            UnityMessage::Swap(prompt) => prompt,
+            UnityMessage::Displayed(displayed) => &displayed.prompt,
+        }
+    }
+}
+
+/// Messages sent to Unity over the WebSocket connection
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ServerMessage {
+    /// A state with its sequence number, like the SSE event id and the ETag of `/state/current`
+    State(SequencedState),
+    /// A message from Unity was rejected, with the same problem details the HTTP routes respond with
+    Problem(Problem),
+}
+
+impl ServerMessage {
+    fn to_message(&self) -> Message {
+        Message::Text(serde_json::to_string(self).unwrap().into())
+    }
+}
+
+impl UnityMessage {
+    /// Convert to an event, with a responder the result is reported to
+    pub fn into_event(self, responder: Responder) -> UnityEvent {
+        match self {
+            UnityMessage::Answer { prompt, answer } => {
+                UnityEvent::Answer(PromptAnswer { prompt, answer }, responder)
+            }
+            UnityMessage::Swap(prompt) => UnityEvent::SwapPreset(prompt, responder),
+            UnityMessage::Displayed(displayed) => UnityEvent::Displayed(displayed, responder),
+        }
+    }
+}
+
+/// Events from the headset. Requests come with a responder, which must be told whether the request was accepted
 #[derive(Debug)]
 pub enum UnityEvent {
-    Connected,
-    Disconnected,
-    SwapPreset,
-    Answer(ExperimentAnswer),
+    Connected(ConnectedClient),
+    Disconnected(ClientId),
+    SwapPreset(PromptReference, Responder),
+    Answer(PromptAnswer, Responder),
+    Displayed(PromptDisplayed, Responder),
 }
 
+/// How long a request waits for the owner of the state to accept or reject it
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Version of the headset protocol, increased whenever a change breaks existing headsets
+pub const PROTOCOL_VERSION: u32 = 1;
+
 #[derive(Clone)]
 pub struct HttpServer {
-    pub state: watch::Receiver<UnityState>,
+    /// Name of the server, shown to the headset in `/info`
+    pub name: String,
+    pub state: watch::Receiver<SequencedState>,
     pub event_sender: mpsc::Sender<UnityEvent>,
-    /// Secret key for authentication, use None to disable authentication
-    pub secret: Option<Arc<String>>,
+    /// Secret key for authentication, use None to disable authentication.
+    /// Rotating it closes every connection authenticated with the previous secret
+    pub secret: Option<Secret>,
+    /// Clients currently subscribed to state updates
+    pub clients: ClientRegistry,
 }
 
 impl HttpServer {
     pub fn app(self) -> Router {
         let state = self;
 
-        let app = Router::new()
+        Router::new()
             .route("/state/current", get(current_state))
             .route("/state/subscribe", get(subscribe_state))
+            .route("/ws", get(websocket))
             .route("/experiment/swap", post(swap_preset))
             .route("/experiment/answer", post(answer_choice_experiment))
+            .route("/experiment/displayed", post(prompt_displayed))
             .route_layer(middleware::from_fn_with_state(state.clone(), auth))
-            .with_state(state);
+            // Routes after the auth layer are open, so clients can check compatibility before authenticating
+            .route("/info", get(server_info))
+            .with_state(state)
+    }
 
-        app
+    /// Serve the app on the listener, keeping track of the remote address of each client
+    pub async fn serve(self, listener: TcpListener) -> std::io::Result<()> {
+        let app = self.app();
+
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<ClientAddress>(),
+        )
+        .await
+    }
+
+    /// Serve the app over HTTPS with a self-signed certificate, which clients pin by its fingerprint
+    pub async fn serve_tls(
+        self,
+        listener: TcpListener,
+        certificate: &TlsCertificate,
+    ) -> std::io::Result<()> {
+        let app = self.app();
+        let listener = TlsListener::new(listener, certificate)?;
+
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<ClientAddress>(),
+        )
+        .await
+    }
+}
+
+/// Address a connection was accepted from, for both plain and TLS connections
+#[derive(Clone, Copy)]
+struct ClientAddress(SocketAddr);
+
+impl Connected<IncomingStream<'_, TcpListener>> for ClientAddress {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        ClientAddress(*stream.remote_addr())
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for ClientAddress {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        ClientAddress(*stream.remote_addr())
+    }
+}
+
+/// Remote address of the client, only known when served with [`HttpServer::serve`] or [`HttpServer::serve_tls`]
+struct RemoteAddress(Option<SocketAddr>);
+
+impl<S: Send + Sync> FromRequestParts<S> for RemoteAddress {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let address = parts
+            .extensions
+            .get::<ConnectInfo<ClientAddress>>()
+            .map(|ConnectInfo(ClientAddress(address))| *address);
+
+        Ok(RemoteAddress(address))
     }
 }
 
 #[derive(Deserialize)]
 struct AuthQuery {
-    secret: Option<String>,
+    /// Token in the URI, for clients that cannot set headers, as defined in RFC 6750 section 2.3
+    access_token: Option<String>,
 }
 
-/// Authentication middleware using secret
+/// Routes that accept the token in the URI, because EventSource and WebSocket clients cannot always set headers.
+/// Other routes only accept the header, so the secret stays out of URLs and access logs
+const QUERY_TOKEN_ROUTES: [&str; 2] = ["/state/subscribe", "/ws"];
+
+/// Why a request was not authenticated, the error codes are defined in RFC 6750 section 3.1
+enum AuthError {
+    /// No token was sent
+    Missing,
+    /// The token is malformed, or was sent in more than one way
+    InvalidRequest(String),
+    /// The token does not match the secret, e.g. because the secret was rotated
+    InvalidToken,
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let (challenge, problem) = match self {
+            AuthError::Missing => (
+                r#"Bearer realm="neuroviz""#.to_owned(),
+                Problem::new(ProblemCode::MissingToken, "Missing bearer token"),
+            ),
+            AuthError::InvalidRequest(description) => (
+                format!(
+                    r#"Bearer realm="neuroviz", error="invalid_request", error_description="{description}""#
+                ),
+                Problem::new(ProblemCode::InvalidRequest, description),
+            ),
+            AuthError::InvalidToken => (
+                r#"Bearer realm="neuroviz", error="invalid_token""#.to_owned(),
+                Problem::new(
+                    ProblemCode::InvalidToken,
+                    "Token does not match the secret, scan the QR code again",
+                ),
+            ),
+        };
+
+        ([(WWW_AUTHENTICATE, challenge)], problem).into_response()
+    }
+}
+
+/// Authentication middleware using a bearer token, which must match the secret
 async fn auth(
     State(http_server): State<HttpServer>,
     Query(auth_query): Query<AuthQuery>,
-    req: Request,
+    mut req: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
-    let Some(secret) = http_server.secret else {
+) -> Result<Response, AuthError> {
+    let Some(secret) = &http_server.secret else {
         return Ok(next.run(req).await);
     };
 
     let auth_header = req
         .headers()
         .get(AUTHORIZATION)
-        .and_then(|header| header.to_str().ok());
+        .map(|header| header.to_str())
+        .transpose()
+        .map_err(|_| AuthError::InvalidRequest("Authorization header is not ASCII".to_owned()))?;
 
-    // let Some(auth_header) = auth_header else {
-    //     return Err(StatusCode::UNAUTHORIZED);
-    // };
+    // A token in the URI of another route is ignored, as if no token was sent
+    let accepts_query_token =
+        req.method() == Method::GET && QUERY_TOKEN_ROUTES.contains(&req.uri().path());
+    let query_token = auth_query
+        .access_token
+        .as_deref()
+        .filter(|_| accepts_query_token);
 
-    let is_valid_token = match (auth_header, auth_query.secret) {
-        (Some(header_secret), _) => header_secret == *secret,
-        (None, Some(query_secret)) => query_secret == *secret,
-        (None, None) => false,
+    let token = match (auth_header, query_token) {
+        (Some(header), None) => {
+            parse_bearer(header).map_err(|error| AuthError::InvalidRequest(error.to_string()))?
+        }
+        (None, Some(query_token)) => query_token,
+        (Some(_), Some(_)) => {
+            return Err(AuthError::InvalidRequest(
+                "Token must only be sent in one way".to_owned(),
+            ))
+        }
+        (None, None) => return Err(AuthError::Missing),
     };
 
-    match is_valid_token {
-        true => Ok(next.run(req).await),
-        false => Err(StatusCode::UNAUTHORIZED),
+    let revocation = secret.authenticate(token).ok_or(AuthError::InvalidToken)?;
+
+    // Long-lived connections use this to close when the secret is rotated
+    req.extensions_mut().insert(revocation);
+
+    Ok(next.run(req).await)
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Revocation {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Requests only lack a revocation when authentication is disabled
+        let revocation = parts
+            .extensions
+            .remove::<Revocation>()
+            .unwrap_or_else(Revocation::never);
+
+        Ok(revocation)
+    }
+}
+
+impl HttpServer {
+    /// Check that a request refers to the prompt currently shown, logging it if not
+    fn check_prompt(&self, prompt: &PromptReference) -> Result<(), PromptMismatch> {
+        let result = prompt.check(self.state.borrow().state.prompt_reference());
+
+        if let Err(mismatch) = &result {
+            eprintln!("Rejected request for a prompt that is not shown: {mismatch}");
+        }
+
+        result
+    }
+
+    /// Send a request from the headset to the owner of the state, and wait until it is accepted or rejected
+    async fn dispatch(
+        &self,
+        prompt: &PromptReference,
+        event: impl FnOnce(Responder) -> UnityEvent,
+    ) -> Result<(), Problem> {
+        self.check_prompt(prompt)?;
+
+        let (responder, reply) = Responder::new();
+
+        self.event_sender
+            .send(event(responder))
+            .await
+            .map_err(|_| {
+                Problem::new(
+                    ProblemCode::Unavailable,
+                    "Events from the headset are not handled",
+                )
+            })?;
+
+        match timeout(REPLY_TIMEOUT, reply).await {
+            Ok(Ok(result)) => result.map_err(Problem::from),
+            Ok(Err(_)) => Err(Problem::new(
+                ProblemCode::Unavailable,
+                "The request was dropped without being handled",
+            )),
+            Err(_) => Err(Problem::new(
+                ProblemCode::Unavailable,
+                "Timed out waiting for the request to be handled",
+            )),
+        }
+    }
+
+    /// Handle a message from the WebSocket like the matching HTTP route, and wait until it is accepted or rejected
+    async fn handle_message(&self, text: &str) -> Result<(), Problem> {
+        let message = serde_json::from_str::<UnityMessage>(text)
+            .map_err(|error| Problem::new(ProblemCode::InvalidBody, error.to_string()))?;
+
+        if let UnityMessage::Answer { answer, .. } = &message {
+            answer.validate()?;
+        }
+
+        let prompt = message.prompt().clone();
+
+        self.dispatch(&prompt, |responder| message.into_event(responder))
+            .await
     }
 }
 
 // Answer experiment
 async fn answer_choice_experiment(
     State(http_server): State<HttpServer>,
-    Json(payload): Json<ExperimentAnswer>,
-) {
+    payload: Result<Json<PromptAnswer>, JsonRejection>,
+) -> Result<(), Problem> {
+    let Json(payload) = payload?;
+
+    payload.answer.validate()?;
+
+    let prompt = payload.prompt.clone();
+
     http_server
-        .event_sender
-        .send(UnityEvent::Answer(payload))
+        .dispatch(&prompt, |responder| UnityEvent::Answer(payload, responder))
         .await
-        .unwrap();
 }
 
 // Swap preset
-async fn swap_preset(State(http_server): State<HttpServer>) {
+async fn swap_preset(
+    State(http_server): State<HttpServer>,
+    payload: Result<Json<PromptReference>, JsonRejection>,
+) -> Result<(), Problem> {
+    let Json(payload) = payload?;
+    let prompt = payload.clone();
+
     http_server
-        .event_sender
-        .send(UnityEvent::SwapPreset)
+        .dispatch(&prompt, |responder| {
+            UnityEvent::SwapPreset(payload, responder)
+        })
         .await
-        .unwrap();
 }
 
-// Get current state
-async fn current_state(State(http_server): State<HttpServer>) -> Json<UnityState> {
+// Acknowledge that the current prompt is displayed
+async fn prompt_displayed(
+    State(http_server): State<HttpServer>,
+    payload: Result<Json<PromptDisplayed>, JsonRejection>,
+) -> Result<(), Problem> {
+    let Json(payload) = payload?;
+    let prompt = payload.prompt.clone();
+
+    http_server
+        .dispatch(&prompt, |responder| {
+            UnityEvent::Displayed(payload, responder)
+        })
+        .await
+}
+
+/// What a server supports, so clients can check that they are compatible before connecting
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ServerInfo {
+    pub protocol_version: u32,
+    pub name: String,
+    /// Every parameter in the schema the server uses, a client must not assume any other parameters
+    pub parameters: Vec<Parameter>,
+    /// The kinds of experiment prompts the server can send, and thus which answers it accepts
+    pub answer_types: Vec<UnityExperimentType>,
+    /// Whether the other routes require the secret from the QR code
+    pub requires_auth: bool,
+}
+
+// Get what the server supports, does not require authentication
+async fn server_info(State(http_server): State<HttpServer>) -> Json<ServerInfo> {
+    Json(ServerInfo {
+        protocol_version: PROTOCOL_VERSION,
+        name: http_server.name,
+        parameters: Parameter::all().collect(),
+        answer_types: UnityExperimentType::iter().collect(),
+        requires_auth: http_server.secret.is_some(),
+    })
+}
+
+// Get current state, with the sequence number as ETag so clients can poll with If-None-Match
+async fn current_state(State(http_server): State<HttpServer>, headers: HeaderMap) -> Response {
     // Get the current state
-    let current_state = http_server.state.borrow().clone();
+    let current = http_server.state.borrow().clone();
+    let etag = format!("\"{}\"", current.sequence);
+
+    let is_unchanged = headers
+        .get(IF_NONE_MATCH)
+        .and_then(|header| header.to_str().ok())
+        .is_some_and(|header| {
+            header
+                .split(',')
+                .any(|tag| tag.trim() == "*" || tag.trim() == etag)
+        });
+
+    if is_unchanged {
+        return (StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response();
+    }
 
     // Return the current state as JSON
-    Json(current_state)
+    ([(ETAG, etag)], Json(current.state)).into_response()
+}
+
+/// Sent as the first event to clients that reconnect with a `Last-Event-ID`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ResumeStatus {
+    /// True if the client already has the current state, it is then not sent again
+    pub up_to_date: bool,
+    /// The sequence number of the current state
+    pub sequence: u64,
+}
+
+/// Registers a client while a connection is open, and notifies when it connects and disconnects
+struct ConnectionGuard {
+    client: ClientGuard,
+    event_sender: mpsc::Sender<UnityEvent>,
+}
+
+impl ConnectionGuard {
+    fn new(
+        http_server: &HttpServer,
+        identity: ClientIdentity,
+        remote_address: Option<SocketAddr>,
+    ) -> Self {
+        let client = http_server.clients.connect(identity, remote_address);
+
+        let _ = http_server
+            .event_sender
+            .try_send(UnityEvent::Connected(client.client().clone()));
+
+        Self {
+            client,
+            event_sender: http_server.event_sender.clone(),
+        }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let event_sender = self.event_sender.clone();
+        let client_id = self.client.client().id;
+
+        tokio::spawn(async move {
+            let _ = event_sender.send(UnityEvent::Disconnected(client_id)).await;
+        });
+    }
 }
 
 /// Subscribe to state updates as an SSE stream
 async fn subscribe_state(
     State(http_server): State<HttpServer>,
+    Query(identity): Query<ClientIdentity>,
+    RemoteAddress(remote_address): RemoteAddress,
+    mut revocation: Revocation,
+    headers: HeaderMap,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
-    let _ = http_server.event_sender.try_send(UnityEvent::Connected);
+    let connection = ConnectionGuard::new(&http_server, identity, remote_address);
 
-    // Guard to ensure we notify when the stream is closed
-    struct Guard {
-        event_sender: mpsc::Sender<UnityEvent>,
-    }
+    // Sequence number of the last state the client received before reconnecting
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.trim().parse::<u64>().ok());
 
-    impl Drop for Guard {
-        fn drop(&mut self) {
-            let event_sender = self.event_sender.clone();
+    let mut state_receiver = http_server.state;
+    let sequence = state_receiver.borrow_and_update().sequence;
 
-            tokio::spawn(async move {
-                event_sender.send(UnityEvent::Disconnected).await.unwrap();
-            });
-        }
-    }
+    let resume_status = last_event_id.map(|last_event_id| ResumeStatus {
+        up_to_date: last_event_id == sequence,
+        sequence,
+    });
 
-    let mut state_stream = http_server.state.into_stream();
+    // Clients that are up to date only need the changes from now on
+    let is_up_to_date = resume_status
+        .as_ref()
+        .is_some_and(|resume_status| resume_status.up_to_date);
+
+    let mut state_stream = match is_up_to_date {
+        true => WatchStream::from_changes(state_receiver),
+        false => WatchStream::new(state_receiver),
+    };
 
     let stream = async_stream::stream! {
-        let _guard = Guard {
-            event_sender: http_server.event_sender.clone(),
-        };
+        if let Some(resume_status) = resume_status {
+            yield Ok(Event::default().event("resume").json_data(resume_status).unwrap());
+        }
+
+        // Send a keep-alive every second, which also lets us know the client is still there
+        let mut keep_alive = interval(Duration::from_secs(1));
+
+        loop {
+            let event = select! {
+                state = state_stream.next() => match state {
+                    // Send the state as an SSE event, with the sequence number as id
+                    Some(SequencedState { sequence, state }) => Event::default()
+                        .id(sequence.to_string())
+                        .json_data(state)
+                        .unwrap(),
+                    None => break,
+                },
+                _ = keep_alive.tick() => Event::default().comment("keep-alive-text"),
+                // The client must reconnect with the new secret
+                _ = revocation.revoked() => break,
+            };
+
+            connection.client.touch();
 
-        while let Some(state) = state_stream.next().await {
-            // Send the state as an SSE event
-            yield Ok(Event::default()
-                .json_data(state)
-                .unwrap());
+            yield Ok(event);
         }
     };
 
-    // Create an SSE stream with a keep-alive interval of 1 second
-    Sse::new(stream).keep_alive(
-        KeepAlive::new()
-            .interval(Duration::from_secs(1))
-            .text("keep-alive-text"),
-    )
+    Sse::new(stream)
+}
+
+/// Upgrade to a WebSocket, which sends state updates and receives answers and swaps on one connection
+async fn websocket(
+    State(http_server): State<HttpServer>,
+    Query(identity): Query<ClientIdentity>,
+    RemoteAddress(remote_address): RemoteAddress,
+    revocation: Revocation,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| {
+        handle_websocket(http_server, identity, remote_address, revocation, socket)
+    })
+}
+
+async fn handle_websocket(
+    http_server: HttpServer,
+    identity: ClientIdentity,
+    remote_address: Option<SocketAddr>,
+    mut revocation: Revocation,
+    socket: WebSocket,
+) {
+    // The connection lives as long as this function, Disconnected is sent when it returns
+    let connection = ConnectionGuard::new(&http_server, identity, remote_address);
+
+    let (mut socket_sender, mut socket_receiver) = socket.split();
+    let mut state_stream = http_server.state.clone().into_stream();
+
+    // Rejected messages are reported back on the socket
+    let (problem_sender, mut problem_receiver) = mpsc::channel::<Problem>(16);
+
+    // Send the state whenever it changes, and ping regularly to detect dropped connections
+    let send_states = async {
+        let mut keep_alive = interval(Duration::from_secs(1));
+
+        loop {
+            let message = select! {
+                state = state_stream.next() => match state {
+                    Some(state) => ServerMessage::State(state).to_message(),
+                    None => break,
+                },
+                Some(problem) = problem_receiver.recv() => ServerMessage::Problem(problem).to_message(),
+                _ = keep_alive.tick() => Message::Ping(Default::default()),
+                // Close with a reason, so the client knows it must reconnect with the new secret
+                _ = revocation.revoked() => {
+                    let _ = socket_sender
+                        .send(Message::Close(Some(CloseFrame {
+                            code: close_code::POLICY,
+                            reason: "Secret was rotated".into(),
+                        })))
+                        .await;
+                    break;
+                }
+            };
+
+            if socket_sender.send(message).await.is_err() {
+                break;
+            }
+        }
+    };
+
+    // Forward messages from Unity as events, one at a time so they are handled in order
+    let receive_messages = async {
+        while let Some(Ok(message)) = socket_receiver.next().await {
+            // Any message, including pongs, means the client is still there
+            connection.client.touch();
+
+            let text = match message {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+
+            // Accepted messages are not acknowledged, the headset sees the result in the next state
+            if let Err(problem) = http_server.handle_message(&text).await {
+                if problem_sender.send(problem).await.is_err() {
+                    break;
+                }
+            }
+        }
+    };
+
+    // Stop when either side is done, e.g. when the socket is closed
+    select! {
+        _ = send_states => (),
+        _ = receive_messages => (),
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use axum::http::header::CONTENT_TYPE;
     use eventsource_stream::Eventsource;
-    use tokio::{
-        net::TcpListener,
-        sync::{mpsc, watch},
-    };
+    use tokio::{net::TcpListener, sync::mpsc, time::sleep};
+    use tokio_tungstenite::{connect_async, tungstenite, tungstenite::client::IntoClientRequest};
 
     use super::*;
+    use crate::client::{Client, ClientError};
 
     // A helper function that spawns our axum application in the background
-    async fn spawn_app(host: impl Into<String>, app: Router) -> String {
+    async fn spawn_app(host: impl Into<String>, http_server: HttpServer) -> String {
         let host = host.into();
         // Bind to localhost at the port 0, which will let the OS assign an available port to us
         let listener = TcpListener::bind(format!("{}:0", host)).await.unwrap();
@@ -216,7 +973,7 @@ mod tests {
         let port = listener.local_addr().unwrap().port();
 
         tokio::spawn(async {
-            axum::serve(listener, app).await.unwrap();
+            http_server.serve(listener).await.unwrap();
         });
         // Returns address (e.g. http://127.0.0.1{random_port})
         format!("http://{}:{}", host, port)
@@ -225,28 +982,23 @@ mod tests {
     /// Test the `/state/current` endpoint, which should return the current state
     #[tokio::test]
     async fn test_current_state() {
-        let (_, unity_state_receiver) = watch::channel(UnityState::Idle);
+        let unity_state_receiver = UnityStateSender::new(UnityState::Idle).subscribe();
         let (unity_event_sender, _) = mpsc::channel(100);
 
-        let secret = Arc::new("secret".to_owned());
+        let secret = Secret::new("secret".to_owned());
 
         let http_server = HttpServer {
+            name: "NeuroViz".to_owned(),
             state: unity_state_receiver,
             event_sender: unity_event_sender,
             secret: Some(secret.clone()),
+            clients: ClientRegistry::new(),
         };
 
-        let listening_url = spawn_app("127.0.0.1", http_server.app()).await;
+        let listening_url = spawn_app("127.0.0.1", http_server.clone()).await;
 
-        let app_state = reqwest::Client::new()
-            .get(format!("{}/state/current", listening_url))
-            .header(AUTHORIZATION, (*secret).clone())
-            .send()
-            .await
-            .unwrap()
-            .json::<UnityState>()
-            .await
-            .unwrap();
+        let client = Client::new(listening_url, Some(secret.current().to_string())).unwrap();
+        let app_state = client.current_state().await.unwrap().state;
 
         assert_eq!(app_state, UnityState::Idle);
     }
@@ -254,53 +1006,659 @@ mod tests {
     /// Test the `/state/subscribe` endpoint, which should return a stream of state updates
     #[tokio::test]
     async fn test_subscribe_state() {
-        let (unity_state_sender, unity_state_receiver) = watch::channel(UnityState::Idle);
+        let unity_state_sender = UnityStateSender::new(UnityState::Idle);
+        let unity_state_receiver = unity_state_sender.subscribe();
         let (unity_event_sender, _unity_event_reciever) = mpsc::channel(100);
 
-        let secret = Arc::new("secret".to_owned());
+        let secret = Secret::new("secret".to_owned());
 
         let http_server = HttpServer {
+            name: "NeuroViz".to_owned(),
             state: unity_state_receiver,
             event_sender: unity_event_sender,
             secret: Some(secret.clone()),
+            clients: ClientRegistry::new(),
         };
 
-        let listening_url = spawn_app("127.0.0.1", http_server.app()).await;
+        let listening_url = spawn_app("127.0.0.1", http_server.clone()).await;
 
-        let mut event_stream = reqwest::Client::new()
-            .get(format!("{}/state/subscribe", listening_url))
-            .header(AUTHORIZATION, (*secret).clone())
-            .send()
-            .await
+        let client = Client::new(listening_url, Some(secret.current().to_string()))
             .unwrap()
-            .bytes_stream()
-            .eventsource();
-
-        let mut get_next_state = async || {
-            let event = event_stream.next().await.unwrap().unwrap();
-            let data = serde_json::from_str::<UnityState>(&event.data).unwrap();
+            .with_identity(ClientIdentity {
+                device_id: Some("headset-1".to_owned()),
+                app_version: Some("1.2.0".to_owned()),
+                model: None,
+            });
+        let mut state_stream = Box::pin(client.subscribe());
 
-            data
-        };
+        let mut get_next_state = async || state_stream.next().await.unwrap().unwrap().state;
 
         assert_eq!(get_next_state().await, UnityState::Idle);
 
+        // The client should be registered with its identity and address
+        let clients = http_server.clients.clients();
+        assert_eq!(clients.len(), 1);
+        assert_eq!(
+            clients[0].identity,
+            ClientIdentity {
+                device_id: Some("headset-1".to_owned()),
+                app_version: Some("1.2.0".to_owned()),
+                model: None,
+            }
+        );
+        assert!(clients[0]
+            .remote_address
+            .as_ref()
+            .is_some_and(|address| address.starts_with("127.0.0.1")));
+
         // Send a live state, check if the event stream receives it
         let live = UnityState::Live {
             parameters: ParameterValues::default(),
+            transition: None,
         };
-        unity_state_sender.send(live.clone()).unwrap();
+        unity_state_sender.send(live.clone());
         assert_eq!(get_next_state().await, live);
 
         // Send an experiment state, check if the event stream receives it
         let experiment = UnityState::Experiment {
             prompt: ExperimentPrompt {
+                reference: PromptReference::new(0),
                 experiment_type: UnityExperimentType::Choice,
                 parameters: ParameterValues::default(),
+                transition: None,
+                response_timeout: None,
+                practice: false,
             },
         };
 
-        unity_state_sender.send(experiment.clone()).unwrap();
+        unity_state_sender.send(experiment.clone());
         assert_eq!(get_next_state().await, experiment);
+
+        // Closing the stream should remove the client
+        drop(state_stream);
+        sleep(Duration::from_millis(100)).await;
+        assert!(http_server.clients.clients().is_empty());
+    }
+
+    /// Test that states are numbered, and that clients can resume and poll conditionally
+    #[tokio::test]
+    async fn test_state_sequence() {
+        let unity_state_sender = UnityStateSender::new(UnityState::Idle);
+        let (unity_event_sender, _unity_event_receiver) = mpsc::channel(100);
+
+        let http_server = HttpServer {
+            name: "NeuroViz".to_owned(),
+            state: unity_state_sender.subscribe(),
+            event_sender: unity_event_sender,
+            secret: None,
+            clients: ClientRegistry::new(),
+        };
+
+        let listening_url = spawn_app("127.0.0.1", http_server).await;
+        let client = reqwest::Client::new();
+
+        // The current state is tagged with its sequence number
+        let response = client
+            .get(format!("{}/state/current", listening_url))
+            .send()
+            .await
+            .unwrap();
+        let etag = response.headers()[ETAG].to_str().unwrap().to_owned();
+        assert_eq!(etag, "\"0\"");
+
+        let get_current_state = async |etag: &str| {
+            client
+                .get(format!("{}/state/current", listening_url))
+                .header(IF_NONE_MATCH, etag)
+                .send()
+                .await
+                .unwrap()
+        };
+
+        // Polling with an up to date ETag returns Not Modified
+        assert_eq!(
+            get_current_state(&etag).await.status(),
+            StatusCode::NOT_MODIFIED
+        );
+
+        // After a change, the new state is returned with a new ETag
+        unity_state_sender.send(UnityState::Live {
+            parameters: ParameterValues::default(),
+            transition: None,
+        });
+
+        let response = get_current_state(&etag).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ETAG], "\"1\"");
+
+        let subscribe = async |last_event_id: &str| {
+            client
+                .get(format!("{}/state/subscribe", listening_url))
+                .header("Last-Event-ID", last_event_id)
+                .send()
+                .await
+                .unwrap()
+                .bytes_stream()
+                .eventsource()
+        };
+
+        // A client that is up to date is told so, and only receives new states
+        let mut up_to_date_stream = subscribe("1").await;
+        let resume = up_to_date_stream.next().await.unwrap().unwrap();
+        assert_eq!(resume.event, "resume");
+        assert_eq!(
+            serde_json::from_str::<ResumeStatus>(&resume.data).unwrap(),
+            ResumeStatus {
+                up_to_date: true,
+                sequence: 1,
+            }
+        );
+
+        unity_state_sender.send(UnityState::Idle);
+
+        let event = up_to_date_stream.next().await.unwrap().unwrap();
+        assert_eq!(event.id, "2");
+        assert_eq!(
+            serde_json::from_str::<UnityState>(&event.data).unwrap(),
+            UnityState::Idle
+        );
+
+        // A client that missed states is told so, and receives the current state
+        let mut outdated_stream = subscribe("0").await;
+        let resume = outdated_stream.next().await.unwrap().unwrap();
+        assert_eq!(
+            serde_json::from_str::<ResumeStatus>(&resume.data).unwrap(),
+            ResumeStatus {
+                up_to_date: false,
+                sequence: 2,
+            }
+        );
+
+        let event = outdated_stream.next().await.unwrap().unwrap();
+        assert_eq!(event.id, "2");
+    }
+
+    /// Test the `/experiment/answer` endpoint, which should reject answers to prompts that are not shown
+    /// or that do not fit the state, with problem details
+    #[tokio::test]
+    async fn test_answer_prompt() {
+        let prompt = PromptReference::new(2);
+
+        let unity_state_sender = UnityStateSender::new(UnityState::Experiment {
+            prompt: ExperimentPrompt {
+                reference: prompt.clone(),
+                experiment_type: UnityExperimentType::Rating,
+                parameters: ParameterValues::default(),
+                transition: None,
+                response_timeout: None,
+                practice: false,
+            },
+        });
+        let unity_state_receiver = unity_state_sender.subscribe();
+        let (unity_event_sender, mut unity_event_receiver) = mpsc::channel(100);
+        let (answer_sender, mut answer_receiver) = mpsc::channel(100);
+
+        // Accepts ratings and rejects choices, like the owner of a rating experiment would
+        let event_handler = tokio::spawn(async move {
+            while let Some(event) = unity_event_receiver.recv().await {
+                let UnityEvent::Answer(prompt_answer, responder) = event else {
+                    continue;
+                };
+
+                match prompt_answer.answer {
+                    ExperimentAnswer::Choice => responder.respond(Err(Rejection::WrongState(
+                        "Not in a choice experiment".to_owned(),
+                    ))),
+                    ExperimentAnswer::Rating { .. } => {
+                        responder.respond(Ok(()));
+                        answer_sender.send(prompt_answer).await.unwrap();
+                    }
+                }
+            }
+        });
+
+        let http_server = HttpServer {
+            name: "NeuroViz".to_owned(),
+            state: unity_state_receiver,
+            event_sender: unity_event_sender,
+            secret: None,
+            clients: ClientRegistry::new(),
+        };
+
+        let listening_url = spawn_app("127.0.0.1", http_server).await;
+
+        let client = Client::new(&listening_url, None).unwrap();
+
+        let answer = async |prompt: &PromptReference, answer: ExperimentAnswer| match client
+            .answer(prompt, answer)
+            .await
+        {
+            Ok(()) => (StatusCode::OK, None),
+            Err(ClientError::Rejected(problem)) => (
+                StatusCode::from_u16(problem.status).unwrap(),
+                Some(problem.code),
+            ),
+            Err(error) => panic!("Expected problem details, got {error}"),
+        };
+        let rating = ExperimentAnswer::Rating { value: 4 };
+
+        // An answer for another prompt is rejected
+        let stale = PromptReference::new(1);
+        assert_eq!(
+            answer(&stale, rating.clone()).await,
+            (StatusCode::CONFLICT, Some(ProblemCode::PromptMismatch))
+        );
+
+        // A rating outside the scale is rejected before reaching the owner of the state
+        assert_eq!(
+            answer(&prompt, ExperimentAnswer::Rating { value: 9 }).await,
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Some(ProblemCode::InvalidAnswer)
+            )
+        );
+
+        // The owner of the state rejects a choice during a rating experiment
+        assert_eq!(
+            answer(&prompt, ExperimentAnswer::Choice).await,
+            (StatusCode::CONFLICT, Some(ProblemCode::WrongState))
+        );
+
+        // An answer for the current prompt is forwarded and accepted
+        assert_eq!(
+            answer(&prompt, rating.clone()).await,
+            (StatusCode::OK, None)
+        );
+        assert_eq!(answer_receiver.recv().await.unwrap().prompt, prompt);
+
+        // A malformed body is rejected
+        let response = reqwest::Client::new()
+            .post(format!("{}/experiment/answer", listening_url))
+            .header(CONTENT_TYPE, "application/json")
+            .body("{")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.json::<Problem>().await.unwrap().code,
+            ProblemCode::InvalidBody
+        );
+
+        // When nothing handles the events any more, answers fail instead of panicking
+        event_handler.abort();
+        let _ = event_handler.await;
+        assert_eq!(
+            answer(&prompt, rating.clone()).await,
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Some(ProblemCode::Unavailable)
+            )
+        );
+
+        // A repeated answer after the prompt is gone is rejected
+        unity_state_sender.send(UnityState::Idle);
+        assert_eq!(
+            answer(&prompt, rating).await,
+            (StatusCode::CONFLICT, Some(ProblemCode::PromptMismatch))
+        );
+        assert!(answer_receiver.try_recv().is_err());
+    }
+
+    /// Test the `/ws` endpoint, which should send state updates and receive answers and swaps
+    #[tokio::test]
+    async fn test_websocket() {
+        let unity_state_sender = UnityStateSender::new(UnityState::Idle);
+        let unity_state_receiver = unity_state_sender.subscribe();
+        let (unity_event_sender, mut unity_event_receiver) = mpsc::channel(100);
+
+        let secret = Secret::new("secret".to_owned());
+
+        let http_server = HttpServer {
+            name: "NeuroViz".to_owned(),
+            state: unity_state_receiver,
+            event_sender: unity_event_sender,
+            secret: Some(secret.clone()),
+            clients: ClientRegistry::new(),
+        };
+
+        let listening_url = spawn_app("127.0.0.1", http_server.clone()).await;
+
+        let mut request = format!("{}/ws", listening_url.replace("http", "ws"))
+            .into_client_request()
+            .unwrap();
+        request.headers_mut().insert(
+            AUTHORIZATION,
+            format!("Bearer {}", secret.current()).parse().unwrap(),
+        );
+
+        let (socket, _) = connect_async(request).await.unwrap();
+        let (mut socket_sender, mut socket_receiver) = socket.split();
+
+        assert!(matches!(
+            unity_event_receiver.recv().await,
+            Some(UnityEvent::Connected(_))
+        ));
+
+        let (event_log_sender, mut event_log_receiver) = mpsc::unbounded_channel();
+
+        let mut get_next_message = async || loop {
+            match socket_receiver.next().await.unwrap().unwrap() {
+                tungstenite::Message::Text(text) => {
+                    break serde_json::from_str::<ServerMessage>(&text).unwrap()
+                }
+                _ => continue,
+            }
+        };
+
+        // States are sent with their sequence number
+        assert_eq!(
+            get_next_message().await,
+            ServerMessage::State(SequencedState {
+                sequence: 0,
+                state: UnityState::Idle
+            })
+        );
+
+        // Send an experiment state, check if the socket receives it
+        let prompt = PromptReference::new(0);
+        let experiment = UnityState::Experiment {
+            prompt: ExperimentPrompt {
+                reference: prompt.clone(),
+                experiment_type: UnityExperimentType::Rating,
+                parameters: ParameterValues::default(),
+                transition: None,
+                response_timeout: None,
+                practice: false,
+            },
+        };
+        unity_state_sender.send(experiment.clone());
+        assert_eq!(
+            get_next_message().await,
+            ServerMessage::State(SequencedState {
+                sequence: 1,
+                state: experiment
+            })
+        );
+
+        // Accept every event, except answers with a rating of 2, and log which events arrived
+        tokio::spawn(async move {
+            while let Some(event) = unity_event_receiver.recv().await {
+                let event = match event {
+                    UnityEvent::Connected(_) => continue,
+                    UnityEvent::Disconnected(_) => "disconnected",
+                    UnityEvent::SwapPreset(_, responder) => {
+                        responder.respond(Ok(()));
+                        "swap"
+                    }
+                    UnityEvent::Answer(answer, responder) => {
+                        if answer.answer == (ExperimentAnswer::Rating { value: 2 }) {
+                            responder.respond(Err(Rejection::WrongState("Rejected".to_owned())));
+                            continue;
+                        }
+
+                        responder.respond(Ok(()));
+                        "answer"
+                    }
+                    UnityEvent::Displayed(_, responder) => {
+                        responder.respond(Ok(()));
+                        "displayed"
+                    }
+                };
+
+                event_log_sender.send(event).unwrap();
+            }
+        });
+
+        // Send a swap, answers and an acknowledgement, check that the accepted ones are forwarded as events.
+        // The invalid message, the answer to a stale prompt and the rejected answer should be reported back
+        let messages = [
+            serde_json::to_string(&UnityMessage::Swap(prompt.clone())).unwrap(),
+            "not json".to_owned(),
+            serde_json::to_string(&UnityMessage::Answer {
+                prompt: PromptReference::new(0),
+                answer: ExperimentAnswer::Rating { value: 1 },
+            })
+            .unwrap(),
+            serde_json::to_string(&UnityMessage::Answer {
+                prompt: prompt.clone(),
+                answer: ExperimentAnswer::Rating { value: 2 },
+            })
+            .unwrap(),
+            serde_json::to_string(&UnityMessage::Answer {
+                prompt: prompt.clone(),
+                answer: ExperimentAnswer::Rating { value: 3 },
+            })
+            .unwrap(),
+            serde_json::to_string(&UnityMessage::Displayed(PromptDisplayed {
+                prompt: prompt.clone(),
+                displayed_at: Local::now(),
+            }))
+            .unwrap(),
+        ];
+
+        for message in messages {
+            socket_sender
+                .send(tungstenite::Message::Text(message.into()))
+                .await
+                .unwrap();
+        }
+
+        for code in [
+            ProblemCode::InvalidBody,
+            ProblemCode::PromptMismatch,
+            ProblemCode::WrongState,
+        ] {
+            match get_next_message().await {
+                ServerMessage::Problem(problem) => assert_eq!(problem.code, code),
+                message => panic!("Expected a problem, got {message:?}"),
+            }
+        }
+
+        assert_eq!(event_log_receiver.recv().await, Some("swap"));
+        assert_eq!(event_log_receiver.recv().await, Some("answer"));
+        assert_eq!(event_log_receiver.recv().await, Some("displayed"));
+
+        // Closing the socket should disconnect
+        socket_sender.close().await.unwrap();
+
+        assert_eq!(event_log_receiver.recv().await, Some("disconnected"));
+    }
+
+    /// Test that tokens are checked as bearer tokens, and that rotating the secret closes connections
+    #[tokio::test]
+    async fn test_auth() {
+        let unity_state_sender = UnityStateSender::new(UnityState::Idle);
+        let (unity_event_sender, _unity_event_receiver) = mpsc::channel(100);
+
+        let secret = Secret::new("secret".to_owned());
+
+        let http_server = HttpServer {
+            name: "NeuroViz".to_owned(),
+            state: unity_state_sender.subscribe(),
+            event_sender: unity_event_sender,
+            secret: Some(secret.clone()),
+            clients: ClientRegistry::new(),
+        };
+
+        let listening_url = spawn_app("127.0.0.1", http_server.clone()).await;
+        let client = reqwest::Client::new();
+
+        let get_current = |authorization: Option<&str>, query: &str| {
+            let mut request = client.get(format!("{listening_url}/state/current{query}"));
+            if let Some(authorization) = authorization {
+                request = request.header(AUTHORIZATION, authorization);
+            }
+            request.send()
+        };
+
+        let response = get_current(Some("Bearer secret"), "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Only the subscription accepts the token in the URI
+        let subscribe = |authorization: Option<&str>, query: &str| {
+            let mut request = client.get(format!("{listening_url}/state/subscribe{query}"));
+            if let Some(authorization) = authorization {
+                request = request.header(AUTHORIZATION, authorization);
+            }
+            request.send()
+        };
+
+        let response = subscribe(None, "?access_token=secret").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        drop(response);
+
+        let response = get_current(None, "?access_token=secret").await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = client
+            .post(format!(
+                "{listening_url}/experiment/answer?access_token=secret"
+            ))
+            .json(&serde_json::json!({
+                "prompt_id": "prompt",
+                "trial_index": 0,
+                "experiment_type": "choice",
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // The token without the scheme is malformed
+        let response = get_current(Some("secret"), "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // The token may only be sent one way
+        let response = subscribe(Some("Bearer secret"), "?access_token=secret")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = get_current(None, "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[WWW_AUTHENTICATE],
+            r#"Bearer realm="neuroviz""#
+        );
+
+        let mut event_stream = client
+            .get(format!("{listening_url}/state/subscribe"))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap()
+            .bytes_stream()
+            .eventsource();
+
+        // Initial state
+        event_stream.next().await.unwrap().unwrap();
+
+        let rotated = secret.rotate();
+
+        // The subscription authenticated with the old secret is closed
+        while let Some(Ok(_)) = event_stream.next().await {}
+
+        let response = get_current(Some("Bearer secret"), "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[WWW_AUTHENTICATE],
+            r#"Bearer realm="neuroviz", error="invalid_token""#
+        );
+
+        let response = get_current(Some(&format!("Bearer {rotated}")), "")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    /// Test serving over HTTPS, where the client pins the certificate by its fingerprint
+    #[tokio::test]
+    async fn test_serve_tls() {
+        let unity_state_sender = UnityStateSender::new(UnityState::Idle);
+        let (unity_event_sender, _unity_event_receiver) = mpsc::channel(100);
+
+        let folder =
+            std::env::temp_dir().join(format!("neuroviz-tls-{}", crate::generate_secret()));
+        let certificate = TlsCertificate::load_or_generate(&folder).unwrap();
+
+        let http_server = HttpServer {
+            name: "NeuroViz".to_owned(),
+            state: unity_state_sender.subscribe(),
+            event_sender: unity_event_sender,
+            secret: None,
+            clients: ClientRegistry::new(),
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn({
+            let certificate = certificate.clone();
+            async move { http_server.serve_tls(listener, &certificate).await.unwrap() }
+        });
+
+        // The certificate is self-signed, so it is verified by the fingerprint instead
+        let response = reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .tls_info(true)
+            .build()
+            .unwrap()
+            .get(format!("https://127.0.0.1:{port}/state/current"))
+            .send()
+            .await
+            .unwrap();
+
+        let tls_info = response
+            .extensions()
+            .get::<reqwest::tls::TlsInfo>()
+            .unwrap();
+        let peer_certificate = tls_info.peer_certificate().unwrap();
+        assert_eq!(
+            crate::tls::fingerprint(peer_certificate),
+            certificate.fingerprint()
+        );
+
+        assert_eq!(
+            response.json::<UnityState>().await.unwrap(),
+            UnityState::Idle
+        );
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    /// Test the `/info` endpoint, which should be available without authentication
+    #[tokio::test]
+    async fn test_server_info() {
+        let unity_state_sender = UnityStateSender::new(UnityState::Idle);
+        let (unity_event_sender, _unity_event_receiver) = mpsc::channel(100);
+
+        let http_server = HttpServer {
+            name: "NeuroViz on Lab PC".to_owned(),
+            state: unity_state_sender.subscribe(),
+            event_sender: unity_event_sender,
+            secret: Some(Secret::new("secret".to_owned())),
+            clients: ClientRegistry::new(),
+        };
+
+        let listening_url = spawn_app("127.0.0.1", http_server).await;
+
+        let server_info = reqwest::get(format!("{listening_url}/info"))
+            .await
+            .unwrap()
+            .json::<ServerInfo>()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            server_info,
+            ServerInfo {
+                protocol_version: PROTOCOL_VERSION,
+                name: "NeuroViz on Lab PC".to_owned(),
+                parameters: Parameter::all().collect(),
+                answer_types: vec![UnityExperimentType::Choice, UnityExperimentType::Rating],
+                requires_auth: true,
+            }
+        );
     }
 }
@@ -1,16 +1,23 @@
 use rand::{distr::Alphanumeric, Rng};
 
+pub mod auth;
+// Also compiled for the tests, which use the client to talk to the server
+#[cfg(any(feature = "client", test))]
+pub mod client;
+pub mod clients;
+pub mod discovery;
 pub mod extensions;
 pub mod http_server;
 pub mod parameters;
+pub mod problem;
+pub mod tls;
+pub mod transition;
 
 /// Generate random secret with 32 characters
 pub fn generate_secret() -> String {
-    let secret = rand::rng()
+    rand::rng()
         .sample_iter(&Alphanumeric)
         .take(32)
         .map(char::from)
-        .collect::<String>();
-
-    secret
+        .collect::<String>()
 }
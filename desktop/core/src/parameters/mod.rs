@@ -1,50 +1,151 @@
-use crate::define_parameters;
-
-mod parameter_macro;
-
-define_parameters! {
-  Transparency => {
-      key: transparency,
-      name: "Transparency",
-      min: 0.0,
-      max: 1.0,
-      default: 0.0
-  },
-  Glow => {
-      key: glow,
-      name: "Glow",
-      min: 0.0,
-      max: 1.0,
-      default: 0.0
-  },
-  Smoothness => {
-      key: smoothness,
-      name: "Smoothness",
-      min: 0.0,
-      max: 1.0,
-      default: 0.5
-  },
-  Emission => {
-      key: emission,
-      name: "Emission",
-      min: 0.0,
-      max: 1.0,
-      default: 0.0
-  },
-  LightIntensity => {
-      key: light_intensity,
-      name: "Light Intensity",
-      min: 0.0,
-      max: 2.0,
-      default: 1.0
-  },
-  LightTemperature => {
-      key: light_temperature,
-      name: "Light Temperature",
-      min: 1500.0,
-      max: 20000.0,
-      default: 6500.0
-  },
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
+
+use serde::{Deserialize, Deserializer, Serialize};
+use specta::Type;
+
+mod schema;
+mod validation;
+
+pub use schema::{ParameterSchema, ParameterSchemaError};
+pub use validation::{ParameterError, ValidationMode};
+
+/// Identifies a parameter, e.g. `transparency`
+#[derive(Deserialize, Serialize, Type, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ParameterKey(String);
+
+impl ParameterKey {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for ParameterKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<&str> for ParameterKey {
+    fn from(key: &str) -> Self {
+        Self(key.to_owned())
+    }
+}
+
+impl From<String> for ParameterKey {
+    fn from(key: String) -> Self {
+        Self(key)
+    }
+}
+
+impl Borrow<str> for ParameterKey {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Deserialize, Serialize, Type, Clone, Debug, PartialEq)]
+pub struct Parameter {
+    pub key: ParameterKey,
+    pub name: String,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+}
+
+impl Parameter {
+    /// All parameters in the schema currently in use
+    pub fn all() -> impl Iterator<Item = Parameter> {
+        ParameterSchema::global().parameters.clone().into_iter()
+    }
+}
+
+/// Values for a set of parameters, keyed by parameter key.
+///
+/// When deserializing, parameters missing from the input are set to the default
+/// from the schema currently in use, so files written before a parameter was added still load.
+#[derive(Serialize, Type, Clone, Debug, PartialEq)]
+pub struct ParameterValues(BTreeMap<ParameterKey, f32>);
+
+impl ParameterValues {
+    pub fn get(&self, key: &str) -> Option<f32> {
+        self.0.get(key).copied()
+    }
+
+    pub fn set(&mut self, key: impl Into<ParameterKey>, value: f32) {
+        self.0.insert(key.into(), value);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ParameterKey, f32)> {
+        self.0.iter().map(|(key, value)| (key, *value))
+    }
+
+    /// Linearly interpolate towards `to`, where `t` is between 0 and 1.
+    ///
+    /// Values are kept within the range of their parameter in the schema currently in use,
+    /// parameters missing from one side keep the value from the other side.
+    pub fn interpolate(&self, to: &ParameterValues, t: f32) -> ParameterValues {
+        let schema = ParameterSchema::global();
+        let t = t.clamp(0.0, 1.0);
+
+        let keys = self.0.keys().chain(to.0.keys()).collect::<BTreeSet<_>>();
+        let mut interpolated = BTreeMap::new();
+
+        for key in keys {
+            let value = match (self.0.get(key), to.0.get(key)) {
+                (Some(from), Some(to)) => from * (1.0 - t) + to * t,
+                (Some(value), None) | (None, Some(value)) => *value,
+                (None, None) => continue,
+            };
+
+            let value = match schema.get(key.as_str()) {
+                Some(parameter) => value.clamp(parameter.min, parameter.max),
+                None => value,
+            };
+
+            interpolated.insert(key.clone(), value);
+        }
+
+        ParameterValues(interpolated)
+    }
+}
+
+impl Default for ParameterValues {
+    fn default() -> Self {
+        ParameterSchema::global().default_values()
+    }
+}
+
+impl<'de> Deserialize<'de> for ParameterValues {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut values = ParameterValues(BTreeMap::deserialize(deserializer)?);
+
+        ParameterSchema::global().fill_defaults(&mut values);
+
+        Ok(values)
+    }
+}
+
+impl<K: Into<ParameterKey>> FromIterator<(K, f32)> for ParameterValues {
+    fn from_iter<I: IntoIterator<Item = (K, f32)>>(iter: I) -> Self {
+        Self(
+            iter.into_iter()
+                .map(|(key, value)| (key.into(), value))
+                .collect(),
+        )
+    }
+}
+
+impl<K: Into<ParameterKey>, const N: usize> From<[(K, f32); N]> for ParameterValues {
+    fn from(values: [(K, f32); N]) -> Self {
+        values.into_iter().collect()
+    }
 }
 
 #[cfg(test)]
@@ -59,10 +160,7 @@ mod tests {
         let mut all_parameter_values = HashMap::new();
 
         for param in Parameter::all() {
-            let json_key = serde_json::to_string(&param.key).unwrap();
-            let unquoted_key = json_key.trim_matches('"');
-
-            all_parameter_values.insert(unquoted_key.to_owned(), param.default);
+            all_parameter_values.insert(param.key.to_string(), param.default);
         }
 
         // Serialize HashMap to json and serialize back as ParameterValues
@@ -72,4 +170,101 @@ mod tests {
 
         assert_eq!(all_parameter_values, correct_parameter_values);
     }
+
+    /// Tests that parameters missing from a file are set to the schema default
+    #[test]
+    fn test_missing_parameters_use_default() {
+        let parameters: ParameterValues = serde_json::from_str(r#"{ "glow": 0.25 }"#).unwrap();
+
+        assert_eq!(parameters.get("glow"), Some(0.25));
+        assert_eq!(parameters.get("light_temperature"), Some(6500.0));
+        assert_eq!(parameters.iter().count(), Parameter::all().count());
+    }
+
+    /// Tests that a custom schema is loaded, and that invalid schemas are rejected
+    #[test]
+    fn test_schema_from_json() {
+        let schema = ParameterSchema::from_json(
+            r#"{ "parameters": [
+                { "key": "glow", "name": "Glow", "min": 0.0, "max": 1.0, "default": 0.0 },
+                { "key": "emission_color", "name": "Emission Color", "min": 0.0, "max": 360.0, "default": 120.0 }
+            ] }"#,
+        )
+        .unwrap();
+
+        let defaults = schema.default_values();
+        assert_eq!(defaults.get("emission_color"), Some(120.0));
+        assert_eq!(defaults.get("transparency"), None);
+
+        let duplicate = ParameterSchema::from_json(
+            r#"{ "parameters": [
+                { "key": "glow", "name": "Glow", "min": 0.0, "max": 1.0, "default": 0.0 },
+                { "key": "glow", "name": "Glow", "min": 0.0, "max": 1.0, "default": 0.0 }
+            ] }"#,
+        );
+        assert!(matches!(
+            duplicate,
+            Err(ParameterSchemaError::DuplicateKey(_))
+        ));
+
+        let default_out_of_range = ParameterSchema::from_json(
+            r#"{ "parameters": [
+                { "key": "glow", "name": "Glow", "min": 0.0, "max": 1.0, "default": 2.0 }
+            ] }"#,
+        );
+        assert!(matches!(
+            default_out_of_range,
+            Err(ParameterSchemaError::DefaultOutOfRange(_))
+        ));
+    }
+
+    /// Tests that invalid values are rejected or clamped depending on the validation mode
+    #[test]
+    fn test_validate() {
+        let schema_json = |validation: &str| {
+            format!(
+                r#"{{ "validation": "{validation}", "parameters": [
+                    {{ "key": "glow", "name": "Glow", "min": 0.0, "max": 1.0, "default": 0.5 }},
+                    {{ "key": "light_temperature", "name": "Light Temperature", "min": 1500.0, "max": 20000.0, "default": 6500.0 }}
+                ] }}"#
+            )
+        };
+
+        let reject = ParameterSchema::from_json(&schema_json("reject")).unwrap();
+        let clamp = ParameterSchema::from_json(&schema_json("clamp")).unwrap();
+
+        let mut valid = ParameterValues::from([("glow", 1.0), ("light_temperature", 1500.0)]);
+        assert_eq!(reject.validate(&mut valid), Ok(()));
+
+        let mut out_of_range = ParameterValues::from([("glow", 0.2), ("light_temperature", 0.0)]);
+        assert_eq!(
+            reject.validate(&mut out_of_range.clone()),
+            Err(ParameterError::OutOfRange {
+                key: "light_temperature".into(),
+                value: 0.0,
+                min: 1500.0,
+                max: 20000.0,
+            })
+        );
+        assert_eq!(clamp.validate(&mut out_of_range), Ok(()));
+        assert_eq!(out_of_range.get("light_temperature"), Some(1500.0));
+
+        let mut not_finite =
+            ParameterValues::from([("glow", f32::NAN), ("light_temperature", f32::INFINITY)]);
+        assert!(matches!(
+            reject.validate(&mut not_finite.clone()),
+            Err(ParameterError::NotFinite { .. })
+        ));
+        assert_eq!(clamp.validate(&mut not_finite), Ok(()));
+        assert_eq!(not_finite.get("glow"), Some(0.5));
+        assert_eq!(not_finite.get("light_temperature"), Some(20000.0));
+
+        let mut unknown = ParameterValues::from([("glow", 0.5), ("sparkle", 1.0)]);
+        assert_eq!(
+            reject.validate(&mut unknown.clone()),
+            Err(ParameterError::UnknownKey("sparkle".into()))
+        );
+        assert_eq!(clamp.validate(&mut unknown), Ok(()));
+        assert_eq!(unknown.get("sparkle"), None);
+    }
 }
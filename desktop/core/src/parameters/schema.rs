@@ -0,0 +1,139 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use super::{Parameter, ParameterKey, ParameterValues, ValidationMode};
+
+/// The schema that is compiled into the binary, used when no schema file is provided
+const BUILTIN_SCHEMA: &str = include_str!("default_schema.json");
+
+/// The schema currently in use by the process
+static GLOBAL_SCHEMA: LazyLock<RwLock<Arc<ParameterSchema>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(ParameterSchema::builtin())));
+
+#[derive(Debug)]
+pub enum ParameterSchemaError {
+    Parse(serde_json::Error),
+    DuplicateKey(ParameterKey),
+    InvalidRange(ParameterKey),
+    DefaultOutOfRange(ParameterKey),
+}
+
+impl Display for ParameterSchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParameterSchemaError::Parse(error) => write!(f, "Invalid parameter schema: {error}"),
+            ParameterSchemaError::DuplicateKey(key) => {
+                write!(f, "Parameter `{key}` is defined more than once")
+            }
+            ParameterSchemaError::InvalidRange(key) => {
+                write!(
+                    f,
+                    "Parameter `{key}` must have a finite range with min <= max"
+                )
+            }
+            ParameterSchemaError::DefaultOutOfRange(key) => {
+                write!(f, "Parameter `{key}` has a default outside of its range")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParameterSchemaError {}
+
+/// The set of parameters the headset understands, usually loaded from a schema file at startup
+#[derive(Deserialize, Serialize, Type, Clone, Debug, PartialEq)]
+pub struct ParameterSchema {
+    pub parameters: Vec<Parameter>,
+
+    /// How invalid parameter values are handled when they enter the system
+    #[serde(default)]
+    pub validation: ValidationMode,
+}
+
+impl ParameterSchema {
+    /// The schema compiled into the binary
+    pub fn builtin() -> Self {
+        Self::from_json(BUILTIN_SCHEMA).expect("Built-in parameter schema is valid")
+    }
+
+    /// Parse and check a schema from JSON
+    pub fn from_json(json: &str) -> Result<Self, ParameterSchemaError> {
+        let schema =
+            serde_json::from_str::<ParameterSchema>(json).map_err(ParameterSchemaError::Parse)?;
+
+        schema.check()?;
+
+        Ok(schema)
+    }
+
+    /// Checks that keys are unique and that every range and default is sensible
+    fn check(&self) -> Result<(), ParameterSchemaError> {
+        let mut seen_keys = HashSet::new();
+
+        for parameter in &self.parameters {
+            if !seen_keys.insert(&parameter.key) {
+                return Err(ParameterSchemaError::DuplicateKey(parameter.key.clone()));
+            }
+
+            let is_valid_range = parameter.min.is_finite()
+                && parameter.max.is_finite()
+                && parameter.min <= parameter.max;
+
+            if !is_valid_range {
+                return Err(ParameterSchemaError::InvalidRange(parameter.key.clone()));
+            }
+
+            if !(parameter.min..=parameter.max).contains(&parameter.default) {
+                return Err(ParameterSchemaError::DefaultOutOfRange(
+                    parameter.key.clone(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The schema currently in use
+    pub fn global() -> Arc<ParameterSchema> {
+        GLOBAL_SCHEMA
+            .read()
+            .expect("Parameter schema lock poisoned")
+            .clone()
+    }
+
+    /// Replace the schema in use, should be done at startup before any parameters are loaded
+    pub fn set_global(schema: ParameterSchema) {
+        *GLOBAL_SCHEMA
+            .write()
+            .expect("Parameter schema lock poisoned") = Arc::new(schema);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Parameter> {
+        self.parameters
+            .iter()
+            .find(|parameter| parameter.key.as_str() == key)
+    }
+
+    /// Parameter values where every parameter is set to its default
+    pub fn default_values(&self) -> ParameterValues {
+        self.parameters
+            .iter()
+            .map(|parameter| (parameter.key.clone(), parameter.default))
+            .collect()
+    }
+
+    /// Set every parameter missing from `values` to its default
+    pub fn fill_defaults(&self, values: &mut ParameterValues) {
+        for parameter in &self.parameters {
+            if values.get(parameter.key.as_str()).is_none() {
+                values.set(parameter.key.clone(), parameter.default);
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use super::{ParameterKey, ParameterSchema, ParameterValues};
+
+/// How parameter values that are unknown, out of range or not finite are handled
+#[derive(Deserialize, Serialize, Type, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationMode {
+    /// Return an error naming the offending parameter
+    #[default]
+    Reject,
+
+    /// Clamp values into range, reset non-finite values to the default and drop unknown parameters
+    Clamp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParameterError {
+    UnknownKey(ParameterKey),
+    NotFinite {
+        key: ParameterKey,
+        value: f32,
+    },
+    OutOfRange {
+        key: ParameterKey,
+        value: f32,
+        min: f32,
+        max: f32,
+    },
+}
+
+impl Display for ParameterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParameterError::UnknownKey(key) => write!(f, "Unknown parameter `{key}`"),
+            ParameterError::NotFinite { key, value } => {
+                write!(f, "Parameter `{key}` must be a finite number, got {value}")
+            }
+            ParameterError::OutOfRange {
+                key,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "Parameter `{key}` must be between {min} and {max}, got {value}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParameterError {}
+
+impl ParameterSchema {
+    /// Check that every value is known, finite and within range.
+    ///
+    /// Depending on the validation mode of the schema, invalid values are either rejected or fixed in place.
+    pub fn validate(&self, values: &mut ParameterValues) -> Result<(), ParameterError> {
+        let unknown_keys = values
+            .0
+            .keys()
+            .filter(|key| self.get(key.as_str()).is_none())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for key in unknown_keys {
+            match self.validation {
+                ValidationMode::Reject => return Err(ParameterError::UnknownKey(key)),
+                ValidationMode::Clamp => {
+                    values.0.remove(&key);
+                }
+            }
+        }
+
+        for parameter in &self.parameters {
+            let Some(value) = values.0.get_mut(&parameter.key) else {
+                continue;
+            };
+
+            let error = if !value.is_finite() {
+                ParameterError::NotFinite {
+                    key: parameter.key.clone(),
+                    value: *value,
+                }
+            } else if !(parameter.min..=parameter.max).contains(value) {
+                ParameterError::OutOfRange {
+                    key: parameter.key.clone(),
+                    value: *value,
+                    min: parameter.min,
+                    max: parameter.max,
+                }
+            } else {
+                continue;
+            };
+
+            match self.validation {
+                ValidationMode::Reject => return Err(error),
+                ValidationMode::Clamp if value.is_nan() => *value = parameter.default,
+                ValidationMode::Clamp => *value = value.clamp(parameter.min, parameter.max),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ParameterValues {
+    /// Validate the values against the schema currently in use, see [`ParameterSchema::validate`]
+    pub fn validate(&mut self) -> Result<(), ParameterError> {
+        ParameterSchema::global().validate(self)
+    }
+}
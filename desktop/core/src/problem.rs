@@ -0,0 +1,124 @@
+use axum::{
+    extract::rejection::JsonRejection,
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::http_server::{PromptMismatch, Rejection};
+
+/// Media type of problem details, as defined in RFC 9457
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Machine readable reason a request failed, which clients can match on instead of the status or text
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ProblemCode {
+    /// No bearer token was sent
+    MissingToken,
+    /// The bearer token does not match the secret, e.g. because the secret was rotated
+    InvalidToken,
+    /// The token is malformed, or was sent in more than one way
+    InvalidRequest,
+    /// The body could not be parsed
+    InvalidBody,
+    /// The request refers to a prompt that is not currently shown
+    PromptMismatch,
+    /// The request does not fit the current state, e.g. a rating during a choice experiment
+    WrongState,
+    /// The answer is not valid, e.g. a rating outside the scale
+    InvalidAnswer,
+    /// Nothing is handling events from the headset, e.g. because the app is shutting down
+    Unavailable,
+}
+
+impl ProblemCode {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ProblemCode::MissingToken | ProblemCode::InvalidToken => StatusCode::UNAUTHORIZED,
+            ProblemCode::InvalidRequest | ProblemCode::InvalidBody => StatusCode::BAD_REQUEST,
+            ProblemCode::PromptMismatch | ProblemCode::WrongState => StatusCode::CONFLICT,
+            ProblemCode::InvalidAnswer => StatusCode::UNPROCESSABLE_ENTITY,
+            ProblemCode::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// Short summary, which is the same for every problem with this code
+    pub fn title(&self) -> &'static str {
+        match self {
+            ProblemCode::MissingToken => "Missing bearer token",
+            ProblemCode::InvalidToken => "Invalid bearer token",
+            ProblemCode::InvalidRequest => "Invalid authentication request",
+            ProblemCode::InvalidBody => "Invalid request body",
+            ProblemCode::PromptMismatch => "Prompt is not shown",
+            ProblemCode::WrongState => "Not possible in the current state",
+            ProblemCode::InvalidAnswer => "Invalid answer",
+            ProblemCode::Unavailable => "Server is not handling requests",
+        }
+    }
+}
+
+/// Problem details sent as the body of every error response, as defined in RFC 9457
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Problem {
+    /// URI identifying the kind of problem, e.g. `urn:neuroviz:problem:wrong_state`
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    /// Explanation specific to this occurrence of the problem
+    pub detail: String,
+    pub code: ProblemCode,
+}
+
+impl Problem {
+    pub fn new(code: ProblemCode, detail: impl Into<String>) -> Self {
+        Self {
+            problem_type: format!("urn:neuroviz:problem:{code}"),
+            title: code.title().to_owned(),
+            status: code.status().as_u16(),
+            detail: detail.into(),
+            code,
+        }
+    }
+
+    /// Use another status than the default of the code
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status.as_u16();
+        self
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let body = serde_json::to_string(&self).unwrap();
+
+        (status, [(CONTENT_TYPE, PROBLEM_JSON)], body).into_response()
+    }
+}
+
+impl From<PromptMismatch> for Problem {
+    fn from(mismatch: PromptMismatch) -> Self {
+        Problem::new(ProblemCode::PromptMismatch, mismatch.to_string())
+    }
+}
+
+impl From<Rejection> for Problem {
+    fn from(rejection: Rejection) -> Self {
+        match rejection {
+            Rejection::Prompt(mismatch) => mismatch.into(),
+            Rejection::WrongState(detail) => Problem::new(ProblemCode::WrongState, detail),
+            Rejection::Invalid(detail) => Problem::new(ProblemCode::InvalidAnswer, detail),
+        }
+    }
+}
+
+impl From<JsonRejection> for Problem {
+    fn from(rejection: JsonRejection) -> Self {
+        // Keeps the status axum chose, e.g. 415 for a missing content type
+        Problem::new(ProblemCode::InvalidBody, rejection.body_text())
+            .with_status(rejection.status())
+    }
+}
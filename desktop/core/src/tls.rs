@@ -0,0 +1,213 @@
+use std::{
+    fmt::Display,
+    fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use axum::serve::Listener;
+use rustls::{
+    crypto::ring,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    ServerConfig,
+};
+use sha2::{Digest, Sha256};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    select,
+    sync::mpsc,
+    time::timeout,
+};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+const CERTIFICATE_FILE: &str = "certificate.pem";
+const PRIVATE_KEY_FILE: &str = "private_key.pem";
+
+/// Clients that do not finish the TLS handshake in time are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum TlsError {
+    /// The certificate or private key could not be read or written
+    Io(PathBuf, io::Error),
+    /// The certificate or private key file is not valid PEM
+    Pem(PathBuf, rustls::pki_types::pem::Error),
+    Generate(rcgen::Error),
+    /// The certificate was rejected by rustls, e.g. because the private key does not match
+    Config(rustls::Error),
+}
+
+impl Display for TlsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsError::Io(path, error) => write!(f, "Could not access {}: {error}", path.display()),
+            TlsError::Pem(path, error) => write!(f, "Invalid PEM in {}: {error}", path.display()),
+            TlsError::Generate(error) => write!(f, "Could not generate certificate: {error}"),
+            TlsError::Config(error) => write!(f, "Invalid certificate: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+impl From<TlsError> for io::Error {
+    fn from(error: TlsError) -> Self {
+        io::Error::other(error)
+    }
+}
+
+/// A self-signed certificate for serving HTTPS.
+/// Since no certificate authority vouches for it, headsets pin it by its fingerprint, which is shared with the QR code
+#[derive(Clone)]
+pub struct TlsCertificate {
+    server_config: Arc<ServerConfig>,
+    fingerprint: String,
+}
+
+impl TlsCertificate {
+    /// Load the certificate from the folder, generating and saving a new one the first time
+    pub fn load_or_generate(folder: impl AsRef<Path>) -> Result<Self, TlsError> {
+        let folder = folder.as_ref();
+        let certificate_path = folder.join(CERTIFICATE_FILE);
+        let private_key_path = folder.join(PRIVATE_KEY_FILE);
+
+        if !certificate_path.exists() || !private_key_path.exists() {
+            let certified_key = rcgen::generate_simple_self_signed(["neuroviz.local".to_owned()])
+                .map_err(TlsError::Generate)?;
+
+            fs::create_dir_all(folder).map_err(|error| TlsError::Io(folder.to_owned(), error))?;
+            fs::write(&certificate_path, certified_key.cert.pem())
+                .map_err(|error| TlsError::Io(certificate_path.clone(), error))?;
+            fs::write(&private_key_path, certified_key.key_pair.serialize_pem())
+                .map_err(|error| TlsError::Io(private_key_path.clone(), error))?;
+        }
+
+        let certificate = CertificateDer::from_pem_file(&certificate_path)
+            .map_err(|error| TlsError::Pem(certificate_path, error))?;
+        let private_key = PrivateKeyDer::from_pem_file(&private_key_path)
+            .map_err(|error| TlsError::Pem(private_key_path, error))?;
+
+        Self::new(certificate, private_key)
+    }
+
+    pub fn new(
+        certificate: CertificateDer<'static>,
+        private_key: PrivateKeyDer<'static>,
+    ) -> Result<Self, TlsError> {
+        let fingerprint = fingerprint(&certificate);
+
+        let server_config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(TlsError::Config)?
+            .with_no_client_auth()
+            .with_single_cert(vec![certificate], private_key)
+            .map_err(TlsError::Config)?;
+
+        Ok(Self {
+            server_config: Arc::new(server_config),
+            fingerprint,
+        })
+    }
+
+    /// SHA-256 fingerprint of the certificate as lowercase hex, which the headset compares against
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+}
+
+/// SHA-256 of the DER encoded certificate as lowercase hex
+pub fn fingerprint(certificate: &[u8]) -> String {
+    let fingerprint = Sha256::digest(certificate)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+
+    fingerprint
+}
+
+/// Accepts TLS connections, doing handshakes in the background so a slow client does not block others
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    connections: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    pub fn new(listener: TcpListener, certificate: &TlsCertificate) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let acceptor = TlsAcceptor::from(certificate.server_config.clone());
+        let (sender, connections) = mpsc::channel(16);
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, address) = select! {
+                    connection = listener.accept() => match connection {
+                        Ok(connection) => connection,
+                        Err(error) => {
+                            eprintln!("Failed to accept connection: {error}");
+                            continue;
+                        }
+                    },
+                    // Stop accepting when the listener is dropped
+                    _ = sender.closed() => break,
+                };
+
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+
+                tokio::spawn(async move {
+                    match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send((stream, address)).await;
+                        }
+                        Ok(Err(error)) => eprintln!("TLS handshake with {address} failed: {error}"),
+                        Err(_) => eprintln!("TLS handshake with {address} timed out"),
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            local_addr,
+            connections,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            // The accept task only stops when the listener is dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_or_generate() {
+        let folder =
+            std::env::temp_dir().join(format!("neuroviz-tls-{}", crate::generate_secret()));
+
+        let generated = TlsCertificate::load_or_generate(&folder).unwrap();
+        let loaded = TlsCertificate::load_or_generate(&folder).unwrap();
+
+        // The certificate is persisted, so the fingerprint in the QR code stays valid
+        assert_eq!(generated.fingerprint(), loaded.fingerprint());
+        assert_eq!(generated.fingerprint().len(), 64);
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use specta::Type;
+use strum::EnumString;
+
+use crate::parameters::ParameterValues;
+
+/// Easing curve used when animating between two sets of parameters
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Type, PartialEq, Eq, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// Map linear progress between 0 and 1 to eased progress
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut if t < 0.5 => 2.0 * t * t,
+            Easing::EaseInOut => -1.0 + (4.0 - 2.0 * t) * t,
+        }
+    }
+}
+
+/// How the headset should animate from the parameters it currently shows to new parameters
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq)]
+pub struct Transition {
+    /// Duration of the transition in seconds
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub duration: f64,
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+/// A transition duration that is negative, NaN or infinite
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InvalidDuration(pub f64);
+
+impl Display for InvalidDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Transition duration must be a finite number of seconds that is not negative, got {}",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidDuration {}
+
+fn validate_duration(duration: f64) -> Result<f64, InvalidDuration> {
+    if duration.is_finite() && duration >= 0.0 {
+        Ok(duration)
+    } else {
+        Err(InvalidDuration(duration))
+    }
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let duration = f64::deserialize(deserializer)?;
+
+    validate_duration(duration).map_err(serde::de::Error::custom)
+}
+
+impl Transition {
+    /// A transition, rejecting durations that are negative, NaN or infinite
+    pub fn new(duration: f64, easing: Easing) -> Result<Self, InvalidDuration> {
+        Ok(Self {
+            duration: validate_duration(duration)?,
+            easing,
+        })
+    }
+
+    /// Check the duration, for transitions that were not made with `new` or deserialized
+    pub fn validate(&self) -> Result<(), InvalidDuration> {
+        validate_duration(self.duration)?;
+
+        Ok(())
+    }
+
+    /// Eased progress of the transition after `elapsed` seconds, between 0 and 1
+    pub fn progress(&self, elapsed: f64) -> f32 {
+        if self.duration <= 0.0 {
+            return 1.0;
+        }
+
+        self.easing.apply((elapsed / self.duration) as f32)
+    }
+
+    /// The parameters to show `elapsed` seconds into the transition from `from` to `to`
+    pub fn interpolate(
+        &self,
+        from: &ParameterValues,
+        to: &ParameterValues,
+        elapsed: f64,
+    ) -> ParameterValues {
+        from.interpolate(to, self.progress(elapsed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that eased progress starts at 0, ends at 1 and is clamped outside the duration
+    #[test]
+    fn test_progress() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ] {
+            let transition = Transition {
+                duration: 2.0,
+                easing,
+            };
+
+            assert_eq!(transition.progress(0.0), 0.0);
+            assert_eq!(transition.progress(2.0), 1.0);
+            assert_eq!(transition.progress(5.0), 1.0);
+            assert_eq!(transition.progress(-1.0), 0.0);
+        }
+
+        let linear = Transition {
+            duration: 2.0,
+            easing: Easing::Linear,
+        };
+        assert_eq!(linear.progress(1.0), 0.5);
+
+        let instant = Transition {
+            duration: 0.0,
+            easing: Easing::Linear,
+        };
+        assert_eq!(instant.progress(0.0), 1.0);
+    }
+
+    /// Tests that durations which are negative, NaN or infinite are rejected
+    #[test]
+    fn test_invalid_duration() {
+        assert!(Transition::new(0.0, Easing::Linear).is_ok());
+        assert!(Transition::new(1.5, Easing::EaseIn).is_ok());
+
+        for duration in [-1.0, f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(Transition::new(duration, Easing::Linear).is_err());
+        }
+
+        assert!(serde_json::from_str::<Transition>(r#"{"duration": 0.5}"#).is_ok());
+        assert!(serde_json::from_str::<Transition>(r#"{"duration": -0.5}"#).is_err());
+
+        let mut transition = Transition::new(0.5, Easing::Linear).unwrap();
+        assert!(transition.validate().is_ok());
+        transition.duration = f64::NAN;
+        assert!(transition.validate().is_err());
+    }
+
+    /// Tests interpolating between two sets of parameters
+    #[test]
+    fn test_interpolate() {
+        let from = ParameterValues::from([("glow", 0.0), ("light_temperature", 2000.0)]);
+        let to = ParameterValues::from([("glow", 1.0), ("light_temperature", 4000.0)]);
+
+        let transition = Transition {
+            duration: 1.0,
+            easing: Easing::Linear,
+        };
+
+        let halfway = transition.interpolate(&from, &to, 0.5);
+        assert_eq!(halfway.get("glow"), Some(0.5));
+        assert_eq!(halfway.get("light_temperature"), Some(3000.0));
+
+        assert_eq!(transition.interpolate(&from, &to, 1.0), to);
+    }
+}
@@ -1,13 +1,27 @@
-from typing import Optional, TypedDict
+from typing import Dict, List, Literal, Optional, TypedDict
 
-class ParameterDict(TypedDict):
-    """Dictionary containing visualization parameters"""
-    transparency: float
-    glow: float
-    smoothness: float
-    emission: float
-    light_intensity: float
-    light_temperature: float
+ParameterDict = Dict[str, float]
+"""
+Dictionary containing visualization parameters, keyed by parameter key.
+
+The available keys are defined by the parameter schema, see `parameters()`.
+Parameters that are left out are set to their default value.
+"""
+
+class Parameter(TypedDict):
+    """Metadata for a single visualization parameter"""
+    key: str
+    name: str
+    min: float
+    max: float
+    default: float
+
+class Transition(TypedDict, total=False):
+    """How the headset animates from the currently shown parameters to new parameters"""
+    duration: float
+    """Duration of the transition in seconds, required"""
+    easing: Literal["linear", "ease_in", "ease_out", "ease_in_out"]
+    """Easing curve, defaults to linear"""
 
 class NeuroViz:
     """
@@ -16,40 +30,63 @@ class NeuroViz:
     Attributes:
         ip: The local IP address the server is running on
         port: The port the server is listening on
-        secret: Optional security token used for authentication
+        secret: Optional security token used for authentication, changes when rotated
+        fingerprint: SHA-256 fingerprint of the TLS certificate, None when serving plain HTTP
+        service_name: Name the server is advertised with on the local network, None when not advertised
     """
     ip: str
     port: int
     secret: Optional[str]
+    fingerprint: Optional[str]
+    service_name: Optional[str]
 
-    def __init__(self, port: int, use_secret: bool) -> None:
+    def __init__(
+        self,
+        port: int,
+        use_secret: bool,
+        use_tls: bool = False,
+        tls_folder: Optional[str] = None,
+        advertise: bool = True,
+        service_name: Optional[str] = None,
+    ) -> None:
         """
         Creates a new NeuroViz instance which starts an HTTP server for visualization.
 
         Args:
             port: The port to run the HTTP server on
             use_secret: Whether to generate a secret token for secure connections
+            use_tls: Whether to serve HTTPS with a self-signed certificate, which the glasses pin using the QR code
+            tls_folder: Where the certificate is kept between runs, defaults to `.neuroviz/tls` in the working directory
+            advertise: Whether to advertise the server on the local network as a `_neuroviz._tcp` service,
+                so glasses can find it without scanning the QR code
+            service_name: Name to advertise, defaults to a name based on the name of this computer
 
         The server will run until the instance is garbage collected.
         """
         ...
 
-    def set_live_parameters(self, parameters: ParameterDict) -> None:
+    def set_live_parameters(self, parameters: ParameterDict, transition: Optional[Transition] = None) -> None:
         """
         Update the visualization parameters.
 
         Args:
             parameters: Dictionary containing parameter keys and values.
+            transition: How to animate to the new parameters, switches instantly if not given.
+
+        Raises:
+            ValueError: If a parameter is unknown, not finite or outside its range,
+                unless the parameter schema is configured to clamp values.
         """
         ...
 
-    def prompt_choice(self, a: ParameterDict, b: ParameterDict) -> ParameterDict:
+    def prompt_choice(self, a: ParameterDict, b: ParameterDict, swap_transition: Optional[Transition] = None) -> ParameterDict:
         """
         Prompt the user with a choice between two parameter sets.
 
         Args:
             a: First parameter set for comparison
             b: Second parameter set for comparison
+            swap_transition: How to morph between a and b when the user swaps, switches instantly if not given
 
         Returns:
             The parameter set that was chosen (either a or b)
@@ -74,6 +111,19 @@ class NeuroViz:
         """
         ...
 
+    def rotate_secret(self) -> str:
+        """
+        Replace the secret with a new one, and print the new QR code.
+        Glasses connected with the old secret are disconnected until they scan the new QR code.
+
+        Returns:
+            The new secret
+
+        Raises:
+            RuntimeError: If the instance was created without a secret
+        """
+        ...
+
 def default_parameters() -> ParameterDict:
     """
     Returns the default parameter values.
@@ -82,3 +132,26 @@ def default_parameters() -> ParameterDict:
         A ParameterDict with default visualization parameters.
     """
     ...
+
+def parameters() -> List[Parameter]:
+    """
+    Returns the parameters defined by the parameter schema in use.
+
+    Returns:
+        A list with the key, name, range and default of every parameter.
+    """
+    ...
+
+def load_parameter_schema(path: str) -> None:
+    """
+    Load a parameter schema from a JSON file, replacing the built-in schema.
+
+    Should be called before creating any parameter dictionaries, so that new
+    parameters supported by the headset can be used without a new release.
+    The `validation` field of the schema decides whether invalid parameter
+    values are rejected with a ValueError (`"reject"`) or clamped (`"clamp"`).
+
+    Args:
+        path: Path to the schema file, in the same format as `parameters.json` in the desktop app's data folder
+    """
+    ...
@@ -1,16 +1,21 @@
-use std::{sync::Arc, time::Duration};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use ::neuroviz::{
+    auth::Secret,
+    clients::ClientRegistry,
+    discovery::{Advertiser, ServiceAdvertisement},
     generate_secret,
     http_server::{
-        ExperimentAnswer, ExperimentPrompt, HttpServer, UnityEvent, UnityExperimentType, UnityState,
+        ExperimentAnswer, ExperimentPrompt, HttpServer, PromptAnswer, PromptReference, Rejection,
+        SequencedState, UnityEvent, UnityExperimentType, UnityState, UnityStateSender,
     },
-    parameters::{ParameterKey, ParameterValues},
+    parameters::{Parameter, ParameterSchema, ParameterValues},
+    tls::TlsCertificate,
+    transition::{Easing, Transition},
 };
 use anyhow::{Context, anyhow, bail};
 use local_ip_address::local_ip;
-use pyo3::{prelude::*, types::PyDict};
-use strum::IntoEnumIterator;
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyDict};
 use tokio::{
     net::TcpListener,
     runtime::Runtime,
@@ -22,41 +27,71 @@ use tokio_util::sync::CancellationToken;
 
 /// Runs the HTTP server, and also transforms the app state into a Unity state
 pub async fn http_server_task(
+    name: String,
     listener: TcpListener,
-    unity_state_receiver: watch::Receiver<UnityState>,
+    unity_state_receiver: watch::Receiver<SequencedState>,
     unity_event_sender: mpsc::Sender<UnityEvent>,
-    secret: Option<String>,
+    secret: Option<Secret>,
+    certificate: Option<TlsCertificate>,
 ) -> PyResult<()> {
     let http_server = HttpServer {
+        name,
         state: unity_state_receiver,
         event_sender: unity_event_sender,
-        secret: secret.map(Arc::new),
+        secret,
+        clients: ClientRegistry::new(),
     };
 
-    let app = http_server.app();
-    axum::serve(listener, app).await?;
+    match certificate {
+        Some(certificate) => http_server.serve_tls(listener, &certificate).await?,
+        None => http_server.serve(listener).await?,
+    }
+
     Ok(())
 }
 
 fn dict_to_parameters<'py>(dict: Bound<'py, PyDict>) -> PyResult<ParameterValues> {
-    let extract =
-        |key: &str| -> PyResult<f32> { dict.get_item(key)?.context("required")?.extract() };
-
-    let transparency = extract("transparency")?;
-    let glow = extract("glow")?;
-    let smoothness = extract("smoothness")?;
-    let emission = extract("emission")?;
-    let light_intensity = extract("light_intensity")?;
-    let light_temperature = extract("light_temperature")?;
-
-    Ok(ParameterValues {
-        transparency,
-        glow,
-        smoothness,
-        emission,
-        light_intensity,
-        light_temperature,
-    })
+    let schema = ParameterSchema::global();
+
+    // Start from the defaults, so parameters that are not given keep their default value
+    let mut parameters = schema.default_values();
+
+    for (key, value) in dict.iter() {
+        let key = key.extract::<String>()?;
+
+        let value = value
+            .extract::<f32>()
+            .with_context(|| format!("Parameter `{key}` must be a number"))?;
+
+        parameters.set(key, value);
+    }
+
+    // Reject or clamp unknown, out of range and non-finite values
+    schema
+        .validate(&mut parameters)
+        .map_err(|error| PyValueError::new_err(error.to_string()))?;
+
+    Ok(parameters)
+}
+
+fn dict_to_transition<'py>(dict: Bound<'py, PyDict>) -> PyResult<Transition> {
+    let duration = dict
+        .get_item("duration")?
+        .context("Transition requires a duration")?
+        .extract::<f64>()?;
+
+    let easing = match dict.get_item("easing")? {
+        Some(easing) => {
+            let easing = easing.extract::<String>()?;
+
+            easing
+                .parse::<Easing>()
+                .map_err(|_| PyValueError::new_err(format!("Unknown easing `{easing}`")))?
+        }
+        None => Easing::default(),
+    };
+
+    Transition::new(duration, easing).map_err(|error| PyValueError::new_err(error.to_string()))
 }
 
 fn parameters_to_dict<'py>(
@@ -65,8 +100,8 @@ fn parameters_to_dict<'py>(
 ) -> PyResult<Bound<'py, PyDict>> {
     let dict = PyDict::new(py);
 
-    for parameter_key in ParameterKey::iter() {
-        dict.set_item(parameter_key.to_string(), parameters.get(parameter_key))?;
+    for (key, value) in parameters.iter() {
+        dict.set_item(key.as_str(), value)?;
     }
 
     Ok(dict)
@@ -76,34 +111,97 @@ fn parameters_to_dict<'py>(
 struct NeuroViz {
     runtime: Arc<Runtime>,
     cancellation_token: CancellationToken,
-    unity_state_sender: watch::Sender<UnityState>,
+    unity_state_sender: UnityStateSender,
     unity_event_receiver: mpsc::Receiver<UnityEvent>,
+    /// Index of the next prompt, answers must refer to the prompt they answer
+    trial_index: u32,
 
     #[pyo3(get)]
     ip: String,
     #[pyo3(get)]
     port: u16,
+    secret: Option<Secret>,
+    /// SHA-256 fingerprint of the TLS certificate, None when serving plain HTTP
     #[pyo3(get)]
-    secret: Option<String>,
+    fingerprint: Option<String>,
+    /// Advertises the server on the local network until dropped
+    advertiser: Option<Advertiser>,
+}
+
+/// Print the QR code the glasses scan to connect
+fn print_connection_qr(
+    ip: &str,
+    port: u16,
+    secret: Option<&str>,
+    fingerprint: Option<&str>,
+) -> PyResult<()> {
+    let qr_payload = serde_json::json!({
+        "ip": ip,
+        "port": port,
+        "secret": secret.unwrap_or("None"),
+        "fingerprint": fingerprint,
+    });
+
+    println!("Connect glasses using QR code:");
+    qr2term::print_qr(qr_payload.to_string()).context("Print QR code")?;
+
+    Ok(())
 }
 
 #[pymethods]
 impl NeuroViz {
     #[new]
-    fn new(port: u16, use_secret: bool) -> PyResult<Self> {
-        let (unity_state_sender, unity_state_receiver) = watch::channel(UnityState::Idle);
+    #[pyo3(signature = (port, use_secret, use_tls=false, tls_folder=None, advertise=true, service_name=None))]
+    fn new(
+        port: u16,
+        use_secret: bool,
+        use_tls: bool,
+        tls_folder: Option<PathBuf>,
+        advertise: bool,
+        service_name: Option<String>,
+    ) -> PyResult<Self> {
+        let unity_state_sender = UnityStateSender::new(UnityState::Idle);
+        let unity_state_receiver = unity_state_sender.subscribe();
         let (unity_event_sender, unity_event_receiver) = mpsc::channel(100);
 
-        let secret = use_secret.then(generate_secret);
+        let secret = use_secret.then(|| Secret::new(generate_secret()));
+
+        // The certificate is kept between runs, so glasses can keep pinning the same fingerprint
+        let certificate = use_tls
+            .then(|| {
+                let tls_folder = tls_folder.unwrap_or_else(|| PathBuf::from(".neuroviz/tls"));
+                TlsCertificate::load_or_generate(tls_folder).context("Load TLS certificate")
+            })
+            .transpose()?;
+        let fingerprint = certificate
+            .as_ref()
+            .map(|certificate| certificate.fingerprint().to_owned());
 
         let ip = local_ip().expect("Get IPv4 address").to_string();
 
-        let secret_str = secret.as_ref().map(|s| s.as_str()).unwrap_or("None");
-        let qr_payload = format!(r#"{{ "ip": "{ip}", "port": {port}, "secret": "{secret_str}" }}"#);
+        // Name shown to glasses, both when discovering and in `/info`
+        let name = service_name.unwrap_or_else(ServiceAdvertisement::default_instance_name);
+
+        let advertiser = advertise
+            .then(|| {
+                Advertiser::start(ServiceAdvertisement {
+                    instance_name: name.clone(),
+                    port,
+                    requires_auth: secret.is_some(),
+                    tls: certificate.is_some(),
+                })
+                .context("Advertise server on the network")
+            })
+            .transpose()?;
 
         println!("Starting server on port {port}");
-        println!("Connect glasses using QR code:");
-        qr2term::print_qr(qr_payload).context("Print QR code")?;
+        let current_secret = secret.as_ref().map(Secret::current);
+        print_connection_qr(
+            &ip,
+            port,
+            current_secret.as_deref().map(String::as_str),
+            fingerprint.as_deref(),
+        )?;
 
         let runtime = Arc::new(Runtime::new().context("Create runtime")?);
         let cancellation_token = CancellationToken::new();
@@ -125,10 +223,12 @@ impl NeuroViz {
                 // runtime.block_on(async {
                 let task = async {
                     let http_server = http_server_task(
+                        name,
                         listener,
                         unity_state_receiver,
                         unity_event_sender,
                         secret,
+                        certificate,
                     );
 
                     http_server.await?;
@@ -148,53 +248,71 @@ impl NeuroViz {
             cancellation_token,
             unity_state_sender,
             unity_event_receiver,
+            trial_index: 0,
             ip,
             port,
             secret,
+            fingerprint,
+            advertiser,
         })
     }
 
-    fn set_live_parameters<'py>(&mut self, parameters: Bound<'py, PyDict>) -> PyResult<()> {
+    #[pyo3(signature = (parameters, transition=None))]
+    fn set_live_parameters<'py>(
+        &mut self,
+        parameters: Bound<'py, PyDict>,
+        transition: Option<Bound<'py, PyDict>>,
+    ) -> PyResult<()> {
         let parameters = dict_to_parameters(parameters)?;
+        let transition = transition.map(dict_to_transition).transpose()?;
 
-        self.unity_state_sender
-            .send(UnityState::Live { parameters })
-            .context("Broadcast live parameters")?;
+        self.unity_state_sender.send(UnityState::Live {
+            parameters,
+            transition,
+        });
 
         Ok(())
     }
 
+    #[pyo3(signature = (a, b, swap_transition=None))]
     fn prompt_choice<'py>(
         &mut self,
         py: Python<'py>,
         a: Bound<'py, PyDict>,
         b: Bound<'py, PyDict>,
+        swap_transition: Option<Bound<'py, PyDict>>,
     ) -> PyResult<Bound<'py, PyDict>> {
         let runtime = self.runtime.clone();
         let cancellation_token = self.cancellation_token.clone();
 
         let parsed_a = dict_to_parameters(a.clone())?;
         let parsed_b = dict_to_parameters(b.clone())?;
+        let swap_transition = swap_transition.map(dict_to_transition).transpose()?;
+
+        let prompt = self.next_prompt();
 
         let unity_state_sender = self.unity_state_sender.clone();
         let mut is_preset_a = true;
 
         let current_preset = move |is_preset_a: bool| match is_preset_a {
-            true => parsed_a,
-            false => parsed_b,
+            true => parsed_a.clone(),
+            false => parsed_b.clone(),
         };
 
-        let show_presets = |unity_state_sender: &watch::Sender<UnityState>,
-                            parameters: ParameterValues|
-         -> PyResult<()> {
-            unity_state_sender
-                .send(UnityState::Experiment {
-                    prompt: ExperimentPrompt {
-                        experiment_type: UnityExperimentType::Choice,
-                        parameters,
-                    },
-                })
-                .context("Broadcast prompt choice")?;
+        let shown_prompt = prompt.clone();
+        let show_presets = move |unity_state_sender: &UnityStateSender,
+                                 parameters: ParameterValues|
+              -> PyResult<()> {
+            unity_state_sender.send(UnityState::Experiment {
+                prompt: ExperimentPrompt {
+                    reference: shown_prompt.clone(),
+                    experiment_type: UnityExperimentType::Choice,
+                    parameters,
+                    transition: swap_transition,
+                    response_timeout: None,
+                    practice: false,
+                },
+            });
 
             Ok(())
         };
@@ -204,14 +322,39 @@ impl NeuroViz {
         let task = async move {
             while let Some(event) = self.unity_event_receiver.recv().await {
                 match event {
-                    UnityEvent::SwapPreset => {
+                    UnityEvent::SwapPreset(swap_prompt, responder) => {
+                        if let Err(rejection) = check_current_prompt(&swap_prompt, &prompt) {
+                            responder.respond(Err(rejection));
+                            continue;
+                        }
+
                         is_preset_a = !is_preset_a;
 
                         show_presets(&unity_state_sender, current_preset(is_preset_a))?;
+                        responder.respond(Ok(()));
                     }
 
-                    UnityEvent::Answer(ExperimentAnswer::Choice) => {
-                        unity_state_sender.send(UnityState::Idle)?;
+                    UnityEvent::Answer(
+                        PromptAnswer {
+                            prompt: answer_prompt,
+                            answer,
+                        },
+                        responder,
+                    ) => {
+                        if let Err(rejection) = check_current_prompt(&answer_prompt, &prompt) {
+                            responder.respond(Err(rejection));
+                            continue;
+                        }
+
+                        if answer != ExperimentAnswer::Choice {
+                            responder.respond(Err(Rejection::WrongState(
+                                "A rating was answered during a choice prompt".to_owned(),
+                            )));
+                            continue;
+                        }
+
+                        responder.respond(Ok(()));
+                        unity_state_sender.send(UnityState::Idle);
 
                         return Ok(match is_preset_a {
                             true => a,
@@ -219,6 +362,10 @@ impl NeuroViz {
                         });
                     }
 
+                    UnityEvent::Displayed(displayed, responder) => {
+                        responder.respond(check_current_prompt(&displayed.prompt, &prompt));
+                    }
+
                     _ => {}
                 }
             }
@@ -257,26 +404,59 @@ impl NeuroViz {
 
         let parsed_parameters = dict_to_parameters(parameters)?;
 
+        let prompt = self.next_prompt();
+
         let unity_state_sender = self.unity_state_sender.clone();
 
-        unity_state_sender
-            .send(UnityState::Experiment {
-                prompt: ExperimentPrompt {
-                    experiment_type: UnityExperimentType::Rating,
-                    parameters: parsed_parameters,
-                },
-            })
-            .context("Broadcast prompt choice")?;
+        unity_state_sender.send(UnityState::Experiment {
+            prompt: ExperimentPrompt {
+                reference: prompt.clone(),
+                experiment_type: UnityExperimentType::Rating,
+                parameters: parsed_parameters,
+                transition: None,
+                response_timeout: None,
+                practice: false,
+            },
+        });
 
         let task = async move {
             while let Some(event) = self.unity_event_receiver.recv().await {
                 match event {
-                    UnityEvent::Answer(ExperimentAnswer::Rating { value }) => {
-                        unity_state_sender.send(UnityState::Idle)?;
+                    UnityEvent::Answer(
+                        PromptAnswer {
+                            prompt: answer_prompt,
+                            answer,
+                        },
+                        responder,
+                    ) => {
+                        if let Err(rejection) = check_current_prompt(&answer_prompt, &prompt) {
+                            responder.respond(Err(rejection));
+                            continue;
+                        }
+
+                        let ExperimentAnswer::Rating { value } = answer else {
+                            responder.respond(Err(Rejection::WrongState(
+                                "A choice was answered during a rating prompt".to_owned(),
+                            )));
+                            continue;
+                        };
+
+                        responder.respond(Ok(()));
+                        unity_state_sender.send(UnityState::Idle);
 
                         return Ok(value);
                     }
 
+                    UnityEvent::SwapPreset(_, responder) => {
+                        responder.respond(Err(Rejection::WrongState(
+                            "Presets can only be swapped during a choice prompt".to_owned(),
+                        )));
+                    }
+
+                    UnityEvent::Displayed(displayed, responder) => {
+                        responder.respond(check_current_prompt(&displayed.prompt, &prompt));
+                    }
+
                     _ => {}
                 }
             }
@@ -306,12 +486,64 @@ impl NeuroViz {
     }
 
     fn set_idle(&mut self) -> PyResult<()> {
-        self.unity_state_sender
-            .send(UnityState::Idle)
-            .context("Send idle state")?;
+        self.unity_state_sender.send(UnityState::Idle);
 
         Ok(())
     }
+
+    /// Name the server is advertised with on the local network, None when not advertised
+    #[getter]
+    fn service_name(&self) -> Option<String> {
+        self.advertiser
+            .as_ref()
+            .map(|advertiser| advertiser.instance_name().to_owned())
+    }
+
+    #[getter]
+    fn secret(&self) -> Option<String> {
+        self.secret
+            .as_ref()
+            .map(|secret| (*secret.current()).clone())
+    }
+
+    /// Replace the secret with a new one, disconnecting the glasses until they scan the new QR code
+    fn rotate_secret(&self) -> PyResult<String> {
+        let secret = self
+            .secret
+            .as_ref()
+            .ok_or_else(|| anyhow!("Authentication is disabled, there is no secret to rotate"))?
+            .rotate();
+
+        print_connection_qr(
+            &self.ip,
+            self.port,
+            Some(&secret),
+            self.fingerprint.as_deref(),
+        )?;
+
+        Ok((*secret).clone())
+    }
+}
+
+impl NeuroViz {
+    /// Create a reference for a new prompt, and advance the trial index
+    fn next_prompt(&mut self) -> PromptReference {
+        let prompt = PromptReference::new(self.trial_index);
+        self.trial_index += 1;
+
+        prompt
+    }
+}
+
+/// Check that an event refers to the prompt being shown, logging it if not
+fn check_current_prompt(
+    prompt: &PromptReference,
+    current: &PromptReference,
+) -> Result<(), Rejection> {
+    prompt.check(Some(current)).map_err(|mismatch| {
+        eprintln!("Rejected event for a prompt that is not shown: {mismatch}");
+        Rejection::from(mismatch)
+    })
 }
 
 impl Drop for NeuroViz {
@@ -320,25 +552,9 @@ impl Drop for NeuroViz {
     }
 }
 
-#[derive(FromPyObject)]
-pub struct ParsedParameterDict {
-    pub transparency: f32,
-    pub glow: f32,
-    pub smoothness: f32,
-    pub emission: f32,
-    pub light_intensity: f32,
-    pub light_temperature: f32,
-}
-
+/// Type hint for parameter dictionaries, the keys are defined by the parameter schema
 #[pyclass]
-pub struct ParameterDict {
-    pub transparency: f32,
-    pub glow: f32,
-    pub smoothness: f32,
-    pub emission: f32,
-    pub light_intensity: f32,
-    pub light_temperature: f32,
-}
+pub struct ParameterDict {}
 
 #[pyfunction]
 fn default_parameters<'py>(py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
@@ -347,10 +563,39 @@ fn default_parameters<'py>(py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
     parameters_to_dict(parameters, py)
 }
 
+#[pyfunction]
+fn parameters<'py>(py: Python<'py>) -> PyResult<Vec<Bound<'py, PyDict>>> {
+    Parameter::all()
+        .map(|parameter| {
+            let dict = PyDict::new(py);
+
+            dict.set_item("key", parameter.key.as_str())?;
+            dict.set_item("name", parameter.name)?;
+            dict.set_item("min", parameter.min)?;
+            dict.set_item("max", parameter.max)?;
+            dict.set_item("default", parameter.default)?;
+
+            Ok(dict)
+        })
+        .collect()
+}
+
+#[pyfunction]
+fn load_parameter_schema(path: &str) -> PyResult<()> {
+    let json = std::fs::read_to_string(path).context("Read parameter schema")?;
+    let schema = ParameterSchema::from_json(&json).context("Parse parameter schema")?;
+
+    ParameterSchema::set_global(schema);
+
+    Ok(())
+}
+
 #[pymodule]
 fn neuroviz(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<NeuroViz>()?;
     m.add_class::<ParameterDict>()?;
     m.add_function(wrap_pyfunction!(default_parameters, m)?)?;
+    m.add_function(wrap_pyfunction!(parameters, m)?)?;
+    m.add_function(wrap_pyfunction!(load_parameter_schema, m)?)?;
     Ok(())
 }
@@ -0,0 +1,389 @@
+//! A headless headset, which connects to a NeuroViz server like the Unity app does and answers prompts by a policy.
+//! Used to test experiment flows without wearing the glasses, both from integration tests and the command line.
+
+use std::{fmt::Display, ops::RangeInclusive, time::Duration};
+
+use chrono::Local;
+use futures::StreamExt;
+use neuroviz::{
+    client::{Client, ClientError},
+    clients::ClientIdentity,
+    http_server::{
+        ExperimentAnswer, ExperimentPrompt, PromptId, PromptReference, UnityExperimentType,
+        UnityState,
+    },
+    parameters::ParameterValues,
+    problem::ProblemCode,
+};
+use policy::{Choice, Decision, Policy, PolicyError, Stimulus};
+use rand::Rng;
+use tokio::time::sleep;
+
+pub mod policy;
+
+#[derive(Debug)]
+pub enum SimulatorError {
+    /// A request to the server failed or was rejected
+    Client(ClientError),
+    /// The prompt was replaced while it was being answered, e.g. because the experiment was stopped
+    PromptReplaced(PromptReference),
+    Policy(PolicyError),
+}
+
+impl Display for SimulatorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimulatorError::Client(error) => write!(f, "{error}"),
+            SimulatorError::PromptReplaced(prompt) => {
+                write!(f, "{prompt} was replaced before it was answered")
+            }
+            SimulatorError::Policy(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for SimulatorError {}
+
+impl From<ClientError> for SimulatorError {
+    fn from(error: ClientError) -> Self {
+        SimulatorError::Client(error)
+    }
+}
+
+impl From<PolicyError> for SimulatorError {
+    fn from(error: PolicyError) -> Self {
+        SimulatorError::Policy(error)
+    }
+}
+
+/// How long the simulated participant takes, so timings look like those of a person
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delays {
+    /// From receiving a prompt until acknowledging that it is displayed
+    pub display: Duration,
+    /// Time spent looking at a preset before swapping or answering, picked at random in the range
+    pub response: RangeInclusive<Duration>,
+}
+
+impl Delays {
+    /// No delays, for tests
+    pub fn none() -> Self {
+        Self {
+            display: Duration::ZERO,
+            response: Duration::ZERO..=Duration::ZERO,
+        }
+    }
+}
+
+/// A prompt the simulator answered
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnsweredPrompt {
+    pub prompt: PromptReference,
+    pub decision: Decision,
+}
+
+/// A simulated headset, which follows the state of the server and answers every prompt it is shown
+pub struct Simulator {
+    /// Base URL of the server, e.g. `http://127.0.0.1:9001`
+    pub url: String,
+    /// Secret from the QR code, None if the server does not require authentication
+    pub secret: Option<String>,
+    pub policy: Policy,
+    pub delays: Delays,
+    /// Stop after answering this many prompts, None to run until the server rejects the connection
+    pub max_answers: Option<usize>,
+    /// Shown in the list of connected headsets
+    pub identity: ClientIdentity,
+    /// Fingerprint from the QR code, for servers using a self-signed certificate
+    pub fingerprint: Option<String>,
+}
+
+impl Simulator {
+    pub fn new(url: impl Into<String>, secret: Option<String>, policy: Policy) -> Self {
+        Self {
+            url: url.into(),
+            secret,
+            policy,
+            delays: Delays::none(),
+            max_answers: None,
+            identity: ClientIdentity {
+                device_id: Some("simulator".to_owned()),
+                app_version: Some(env!("CARGO_PKG_VERSION").to_owned()),
+                model: Some("NeuroViz simulator".to_owned()),
+            },
+            fingerprint: None,
+        }
+    }
+
+    /// Answer prompts until the server rejects the connection or enough prompts are answered, returns the answered prompts
+    pub async fn run(mut self) -> Result<Vec<AnsweredPrompt>, SimulatorError> {
+        let client = match self.fingerprint.take() {
+            Some(fingerprint) => {
+                Client::with_fingerprint(&self.url, self.secret.clone(), fingerprint)?
+            }
+            None => Client::new(&self.url, self.secret.clone())?,
+        };
+        let client = client.with_identity(self.identity.clone());
+
+        // Reconnects by itself, so the simulator keeps answering when the connection drops
+        let mut states = std::pin::pin!(client.subscribe());
+
+        let mut answered_prompts = Vec::new();
+        let mut last_prompt_id: Option<PromptId> = None;
+
+        while let Some(state) = states.next().await {
+            let state = match state {
+                Ok(state) => state,
+                Err(error) if !error.is_permanent() => {
+                    eprintln!("Could not connect, retrying: {error}");
+                    continue;
+                }
+                Err(error) => return Err(error.into()),
+            };
+
+            let UnityState::Experiment { prompt } = state.state else {
+                continue;
+            };
+
+            // The state is sent again when swapping, but every prompt is only answered once
+            if last_prompt_id.as_ref() == Some(&prompt.reference.prompt_id) {
+                continue;
+            }
+            last_prompt_id = Some(prompt.reference.prompt_id.clone());
+
+            let reference = prompt.reference.clone();
+
+            let decision = match self.answer(&client, prompt).await {
+                Ok(decision) => decision,
+                // Another prompt is shown, e.g. because the experiment was stopped while answering
+                Err(SimulatorError::PromptReplaced(_)) => {
+                    eprintln!("Skipped {reference}, it was replaced while answering");
+                    continue;
+                }
+                Err(SimulatorError::Client(ClientError::Rejected(problem)))
+                    if problem.code == ProblemCode::PromptMismatch =>
+                {
+                    eprintln!("Skipped {reference}: {}", problem.detail);
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+
+            println!("Answered {reference} with {decision}");
+
+            answered_prompts.push(AnsweredPrompt {
+                prompt: reference,
+                decision,
+            });
+
+            if self
+                .max_answers
+                .is_some_and(|max_answers| answered_prompts.len() >= max_answers)
+            {
+                break;
+            }
+        }
+
+        Ok(answered_prompts)
+    }
+
+    /// Look at the prompt like a participant would, and answer it by the policy
+    async fn answer(
+        &mut self,
+        client: &Client,
+        prompt: ExperimentPrompt,
+    ) -> Result<Decision, SimulatorError> {
+        let reference = prompt.reference;
+
+        sleep(self.delays.display).await;
+        client.displayed(&reference, Local::now()).await?;
+
+        self.think().await;
+
+        let decision = match prompt.experiment_type {
+            UnityExperimentType::Choice => {
+                // Swap to see both presets before choosing
+                let a = prompt.parameters;
+                client.swap(&reference).await?;
+                let b = current_parameters(client, &reference).await?;
+                self.think().await;
+
+                let decision = self
+                    .policy
+                    .decide(reference.trial_index, Stimulus::Choice { a: &a, b: &b })?;
+
+                // The preset that is shown when answering is the one chosen
+                if decision == Decision::Choice(Choice::A) {
+                    client.swap(&reference).await?;
+                    self.think().await;
+                }
+
+                decision
+            }
+            UnityExperimentType::Rating => self.policy.decide(
+                reference.trial_index,
+                Stimulus::Rating {
+                    parameters: &prompt.parameters,
+                },
+            )?,
+        };
+
+        let answer = match decision {
+            Decision::Choice(_) => ExperimentAnswer::Choice,
+            Decision::Rating(value) => ExperimentAnswer::Rating { value },
+        };
+
+        client.answer(&reference, answer).await?;
+
+        Ok(decision)
+    }
+
+    /// Wait as long as the participant looks at a preset
+    async fn think(&self) {
+        let delay = match self.delays.response.is_empty() {
+            true => *self.delays.response.start(),
+            false => rand::rng().random_range(self.delays.response.clone()),
+        };
+
+        sleep(delay).await;
+    }
+}
+
+/// Parameters currently shown for the prompt, e.g. after swapping
+async fn current_parameters(
+    client: &Client,
+    prompt: &PromptReference,
+) -> Result<ParameterValues, SimulatorError> {
+    let state = client.current_state().await?.state;
+
+    match state {
+        UnityState::Experiment { prompt: current } if current.reference == *prompt => {
+            Ok(current.parameters)
+        }
+        _ => Err(SimulatorError::PromptReplaced(prompt.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use neuroviz::{
+        auth::Secret,
+        clients::ClientRegistry,
+        http_server::{HttpServer, UnityEvent, UnityStateSender},
+    };
+    use tokio::{net::TcpListener, sync::mpsc};
+
+    use super::*;
+
+    /// Show the choices one after another like the desktop app does, returns the chosen parameters
+    async fn run_choice_experiment(
+        unity_state_sender: UnityStateSender,
+        mut unity_event_receiver: mpsc::Receiver<UnityEvent>,
+        choices: Vec<(ParameterValues, ParameterValues)>,
+    ) -> Vec<ParameterValues> {
+        let mut chosen = Vec::new();
+
+        for (trial_index, (a, b)) in choices.into_iter().enumerate() {
+            let reference = PromptReference::new(trial_index as u32);
+            let mut is_preset_a = true;
+
+            let show = |is_preset_a: bool| UnityState::Experiment {
+                prompt: ExperimentPrompt {
+                    reference: reference.clone(),
+                    experiment_type: UnityExperimentType::Choice,
+                    parameters: match is_preset_a {
+                        true => a.clone(),
+                        false => b.clone(),
+                    },
+                    transition: None,
+                    response_timeout: None,
+                    practice: false,
+                },
+            };
+
+            unity_state_sender.send(show(is_preset_a));
+
+            // The server only forwards requests for the prompt that is shown
+            while let Some(event) = unity_event_receiver.recv().await {
+                match event {
+                    UnityEvent::SwapPreset(_, responder) => {
+                        is_preset_a = !is_preset_a;
+                        unity_state_sender.send(show(is_preset_a));
+                        responder.respond(Ok(()));
+                    }
+                    UnityEvent::Displayed(_, responder) => responder.respond(Ok(())),
+                    UnityEvent::Answer(_, responder) => {
+                        responder.respond(Ok(()));
+                        chosen.push(match is_preset_a {
+                            true => a.clone(),
+                            false => b.clone(),
+                        });
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        unity_state_sender.send(UnityState::Idle);
+
+        chosen
+    }
+
+    /// Test a simulated experiment end to end, the simulator should pick the preset with the higher glow
+    #[tokio::test]
+    async fn test_prefer_higher() {
+        let unity_state_sender = UnityStateSender::new(UnityState::Idle);
+        let (unity_event_sender, unity_event_receiver) = mpsc::channel(100);
+
+        let secret = Secret::new("secret".to_owned());
+
+        let http_server = HttpServer {
+            name: "NeuroViz".to_owned(),
+            state: unity_state_sender.subscribe(),
+            event_sender: unity_event_sender,
+            secret: Some(secret.clone()),
+            clients: ClientRegistry::new(),
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async {
+            http_server.serve(listener).await.unwrap();
+        });
+
+        let mut simulator = Simulator::new(
+            url,
+            Some((*secret.current()).clone()),
+            "prefer-higher:glow".parse().unwrap(),
+        );
+        simulator.max_answers = Some(2);
+        let simulator = tokio::spawn(simulator.run());
+
+        let low = ParameterValues::from([("glow", 0.2)]);
+        let high = ParameterValues::from([("glow", 0.8)]);
+
+        let chosen = run_choice_experiment(
+            unity_state_sender,
+            unity_event_receiver,
+            vec![(low.clone(), high.clone()), (high.clone(), low)],
+        )
+        .await;
+
+        assert_eq!(chosen, vec![high.clone(), high]);
+
+        let decisions = simulator
+            .await
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .map(|answered| answered.decision)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            decisions,
+            vec![Decision::Choice(Choice::B), Decision::Choice(Choice::A)]
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use std::{process::ExitCode, time::Duration};
+
+use clap::Parser;
+use neuroviz_simulator::{policy::Policy, Delays, Simulator};
+
+/// Headless headset, which connects to a NeuroViz server and answers the prompts it is shown
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Base URL of the server
+    #[arg(long, default_value = "http://127.0.0.1:9001")]
+    url: String,
+
+    /// Secret from the QR code, leave out if the server does not require authentication
+    #[arg(long)]
+    secret: Option<String>,
+
+    /// How to answer: always-a, always-b, random[:<seed>], prefer-higher:<parameter> or script:<path>.
+    /// A script has one answer per trial, `a`, `b` or a rating
+    #[arg(long, default_value = "random")]
+    policy: Policy,
+
+    /// Milliseconds from receiving a prompt until acknowledging that it is displayed
+    #[arg(long, default_value_t = 100)]
+    display_delay_ms: u64,
+
+    /// Least milliseconds spent looking at a preset before swapping or answering
+    #[arg(long, default_value_t = 500)]
+    min_delay_ms: u64,
+
+    /// Most milliseconds spent looking at a preset before swapping or answering
+    #[arg(long, default_value_t = 1500)]
+    max_delay_ms: u64,
+
+    /// Stop after answering this many prompts
+    #[arg(long)]
+    max_answers: Option<usize>,
+
+    /// SHA-256 fingerprint of the certificate from the QR code, for servers using HTTPS with a self-signed certificate
+    #[arg(long)]
+    fingerprint: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let mut simulator = Simulator::new(args.url, args.secret, args.policy);
+    simulator.delays = Delays {
+        display: Duration::from_millis(args.display_delay_ms),
+        response: Duration::from_millis(args.min_delay_ms)
+            ..=Duration::from_millis(args.max_delay_ms.max(args.min_delay_ms)),
+    };
+    simulator.max_answers = args.max_answers;
+    simulator.fingerprint = args.fingerprint;
+
+    match simulator.run().await {
+        Ok(answered_prompts) => {
+            println!("Answered {} prompts", answered_prompts.len());
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use neuroviz::{
+    http_server::RATING_SCALE,
+    parameters::{ParameterKey, ParameterSchema, ParameterValues},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// One of the two presets of a choice prompt, A is the preset shown first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    A,
+    B,
+}
+
+/// What the simulated participant has seen of a prompt when deciding
+#[derive(Debug, Clone, Copy)]
+pub enum Stimulus<'a> {
+    Choice {
+        a: &'a ParameterValues,
+        b: &'a ParameterValues,
+    },
+    Rating {
+        parameters: &'a ParameterValues,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Choice(Choice),
+    Rating(u8),
+}
+
+impl Display for Decision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Decision::Choice(Choice::A) => write!(f, "A"),
+            Decision::Choice(Choice::B) => write!(f, "B"),
+            Decision::Rating(value) => write!(f, "rating {value}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PolicyError {
+    /// The script file could not be read
+    Io(PathBuf, io::Error),
+    /// A line of the script is not `a`, `b` or a rating
+    Parse { line: usize, text: String },
+    /// The script has no answer for the trial
+    ScriptEnded { trial_index: u32 },
+    /// The script answers the trial with a choice when a rating is asked for, or the other way around
+    Mismatch {
+        trial_index: u32,
+        decision: Decision,
+    },
+    /// The prompt does not have the parameter the policy compares
+    MissingParameter(ParameterKey),
+    /// The policy is not one of the known policies
+    Unknown(String),
+}
+
+impl Display for PolicyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyError::Io(path, error) => write!(f, "Could not read {}: {error}", path.display()),
+            PolicyError::Parse { line, text } => {
+                write!(f, "Line {line} of the script is not `a`, `b` or a rating: {text}")
+            }
+            PolicyError::ScriptEnded { trial_index } => {
+                write!(f, "The script has no answer for trial {trial_index}")
+            }
+            PolicyError::Mismatch {
+                trial_index,
+                decision,
+            } => write!(
+                f,
+                "The script answers trial {trial_index} with {decision}, which does not fit the prompt"
+            ),
+            PolicyError::MissingParameter(key) => write!(f, "The prompt has no parameter `{key}`"),
+            PolicyError::Unknown(policy) => write!(
+                f,
+                "Unknown policy `{policy}`, expected always-a, always-b, random[:<seed>], prefer-higher:<parameter> or script:<path>"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+/// Answers listed per trial, read from a file with one answer per line.
+/// A line is `a`, `b` or a rating, empty lines and lines starting with `#` are skipped
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script(Vec<Decision>);
+
+impl Script {
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, PolicyError> {
+        let path = path.as_ref();
+        let text =
+            fs::read_to_string(path).map_err(|error| PolicyError::Io(path.to_owned(), error))?;
+
+        text.parse()
+    }
+
+    /// The answer for a trial, trials are counted from 0
+    pub fn get(&self, trial_index: u32) -> Result<Decision, PolicyError> {
+        self.0
+            .get(trial_index as usize)
+            .copied()
+            .ok_or(PolicyError::ScriptEnded { trial_index })
+    }
+}
+
+impl FromStr for Script {
+    type Err = PolicyError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let decisions = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(line, text)| {
+                let decision = match text.to_ascii_lowercase().as_str() {
+                    "a" => Some(Decision::Choice(Choice::A)),
+                    "b" => Some(Decision::Choice(Choice::B)),
+                    rating => rating
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|value| RATING_SCALE.contains(value))
+                        .map(Decision::Rating),
+                };
+
+                decision.ok_or_else(|| PolicyError::Parse {
+                    line,
+                    text: text.to_owned(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Script(decisions))
+    }
+}
+
+/// How the simulated participant answers prompts
+#[derive(Debug, Clone)]
+pub enum Policy {
+    /// Always pick the same preset, and give every prompt the same rating
+    Fixed { choice: Choice, rating: u8 },
+    /// Pick and rate at random
+    Random(Box<StdRng>),
+    /// Pick the preset with the higher value of the parameter, and rate by where the value lies between its min and max
+    PreferHigher(ParameterKey),
+    /// Answer as listed in the script, by the trial index of the prompt
+    Script(Script),
+}
+
+impl Policy {
+    /// Always pick the preset, rating every prompt in the middle of the scale
+    pub fn always(choice: Choice) -> Self {
+        let middle = (RATING_SCALE.start() + RATING_SCALE.end()) / 2;
+
+        Policy::Fixed {
+            choice,
+            rating: middle,
+        }
+    }
+
+    /// Answer at random, the same seed gives the same answers
+    pub fn random(seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+
+        Policy::Random(Box::new(rng))
+    }
+
+    pub fn decide(
+        &mut self,
+        trial_index: u32,
+        stimulus: Stimulus,
+    ) -> Result<Decision, PolicyError> {
+        let decision = match (self, stimulus) {
+            (Policy::Fixed { choice, .. }, Stimulus::Choice { .. }) => Decision::Choice(*choice),
+            (Policy::Fixed { rating, .. }, Stimulus::Rating { .. }) => Decision::Rating(*rating),
+
+            (Policy::Random(rng), Stimulus::Choice { .. }) => match rng.random_bool(0.5) {
+                true => Decision::Choice(Choice::A),
+                false => Decision::Choice(Choice::B),
+            },
+            (Policy::Random(rng), Stimulus::Rating { .. }) => {
+                Decision::Rating(rng.random_range(RATING_SCALE))
+            }
+
+            (Policy::PreferHigher(key), Stimulus::Choice { a, b }) => {
+                match parameter(a, key)? >= parameter(b, key)? {
+                    true => Decision::Choice(Choice::A),
+                    false => Decision::Choice(Choice::B),
+                }
+            }
+            (Policy::PreferHigher(key), Stimulus::Rating { parameters }) => {
+                let value = parameter(parameters, key)?;
+
+                // Parameters outside the schema are rated as if they go from 0 to 1
+                let (min, max) = ParameterSchema::global()
+                    .get(key.as_str())
+                    .map(|parameter| (parameter.min, parameter.max))
+                    .unwrap_or((0.0, 1.0));
+
+                let position = ((value - min) / (max - min)).clamp(0.0, 1.0);
+                let steps = (RATING_SCALE.end() - RATING_SCALE.start()) as f32;
+
+                Decision::Rating(RATING_SCALE.start() + (position * steps).round() as u8)
+            }
+
+            (Policy::Script(script), stimulus) => {
+                let decision = script.get(trial_index)?;
+
+                let is_matching = matches!(
+                    (decision, stimulus),
+                    (Decision::Choice(_), Stimulus::Choice { .. })
+                        | (Decision::Rating(_), Stimulus::Rating { .. })
+                );
+
+                if !is_matching {
+                    return Err(PolicyError::Mismatch {
+                        trial_index,
+                        decision,
+                    });
+                }
+
+                decision
+            }
+        };
+
+        Ok(decision)
+    }
+}
+
+fn parameter(parameters: &ParameterValues, key: &ParameterKey) -> Result<f32, PolicyError> {
+    parameters
+        .get(key.as_str())
+        .ok_or_else(|| PolicyError::MissingParameter(key.clone()))
+}
+
+/// Parse a policy as given on the command line,
+/// e.g. `always-a`, `always-b`, `random`, `random:42`, `prefer-higher:glow` or `script:answers.txt`
+impl FromStr for Policy {
+    type Err = PolicyError;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        let (name, argument) = match policy.split_once(':') {
+            Some((name, argument)) => (name, Some(argument)),
+            None => (policy, None),
+        };
+
+        let policy = match (name, argument) {
+            ("always-a", None) => Policy::always(Choice::A),
+            ("always-b", None) => Policy::always(Choice::B),
+            ("random", None) => Policy::random(None),
+            ("random", Some(seed)) => match seed.parse() {
+                Ok(seed) => Policy::random(Some(seed)),
+                Err(_) => return Err(PolicyError::Unknown(policy.to_owned())),
+            },
+            ("prefer-higher", Some(key)) => Policy::PreferHigher(key.into()),
+            ("script", Some(path)) => Policy::Script(Script::read(path)?),
+            _ => return Err(PolicyError::Unknown(policy.to_owned())),
+        };
+
+        Ok(policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let script = "# Choices first\na\nB\n\n5\n".parse::<Script>().unwrap();
+
+        assert_eq!(script.get(0).unwrap(), Decision::Choice(Choice::A));
+        assert_eq!(script.get(1).unwrap(), Decision::Choice(Choice::B));
+        assert_eq!(script.get(2).unwrap(), Decision::Rating(5));
+        assert!(matches!(
+            script.get(3),
+            Err(PolicyError::ScriptEnded { trial_index: 3 })
+        ));
+
+        assert!(matches!(
+            "a\nc".parse::<Script>(),
+            Err(PolicyError::Parse { line: 2, .. })
+        ));
+        assert!(matches!(
+            "9".parse::<Script>(),
+            Err(PolicyError::Parse { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_prefer_higher() {
+        let mut policy = "prefer-higher:glow".parse::<Policy>().unwrap();
+
+        let low = ParameterValues::from([("glow", 0.2)]);
+        let high = ParameterValues::from([("glow", 0.8)]);
+
+        let choose =
+            |policy: &mut Policy, a, b| policy.decide(0, Stimulus::Choice { a, b }).unwrap();
+
+        assert_eq!(
+            choose(&mut policy, &high, &low),
+            Decision::Choice(Choice::A)
+        );
+        assert_eq!(
+            choose(&mut policy, &low, &high),
+            Decision::Choice(Choice::B)
+        );
+
+        // Glow goes from 0 to 1, so the highest glow gets the highest rating
+        let max = ParameterValues::from([("glow", 1.0)]);
+        assert_eq!(
+            policy
+                .decide(0, Stimulus::Rating { parameters: &max })
+                .unwrap(),
+            Decision::Rating(*RATING_SCALE.end())
+        );
+    }
+
+    #[test]
+    fn test_script_mismatch() {
+        let mut policy = Policy::Script("a".parse().unwrap());
+        let parameters = ParameterValues::default();
+
+        assert!(matches!(
+            policy.decide(
+                0,
+                Stimulus::Rating {
+                    parameters: &parameters
+                }
+            ),
+            Err(PolicyError::Mismatch { trial_index: 0, .. })
+        ));
+    }
+}
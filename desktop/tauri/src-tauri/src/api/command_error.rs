@@ -1,13 +1,14 @@
 use std::fmt::Display;
 
+use neuroviz::http_server::Rejection;
 use specta::{datatype::PrimitiveType, Type};
 
 pub struct AppError(pub anyhow::Error);
 
-/// Display the error message
+/// Display the error message, including its causes
 impl Display for AppError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{:#}", self.0)
     }
 }
 
@@ -37,3 +38,10 @@ impl From<anyhow::Error> for AppError {
         AppError(err)
     }
 }
+
+/// Convert from a command rejected by the experiment engine
+impl From<Rejection> for AppError {
+    fn from(rejection: Rejection) -> Self {
+        AppError(rejection.into())
+    }
+}
@@ -1,32 +1,35 @@
 use anyhow::Context;
-use chrono::Local;
+use chrono::{DateTime, Local};
 use local_ip_address::local_ip;
 use neuroviz::{
-    extensions::WatchSenderExt,
+    clients::ConnectedClient,
     http_server::ExperimentAnswer,
     parameters::{Parameter, ParameterValues},
+    transition::Transition,
 };
-use rand::seq::SliceRandom;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use slug::slugify;
 use specta::Type;
-use std::{collections::HashMap, path::PathBuf, time::Duration};
+use std::{collections::HashMap, path::PathBuf};
 use tauri::Manager;
 use tauri_plugin_opener::OpenerExt;
 use tauri_specta::Event;
-use tokio::{fs, time::sleep};
+use tokio::fs;
 
 use crate::{
     data::{
         experiment::{
-            ChoiceExperiment, CreateExperiment, CreateExperimentType, Experiment, RatingExperiment,
+            ChoiceExperiment, CreateExperiment, CreateExperimentType, Experiment, Mask,
+            RatingExperiment,
         },
-        experiment_result::{ChoiceExperimentResult, ExperimentResult, RatingExperimentResult},
+        experiment_result::{CompletionStatus, ExperimentResult},
         folder::TopLevelFolder,
         preset::Preset,
+        ValidateParameters, ValidateSettings,
     },
-    state::{experiment_state::ExperimentState, AppData, AppState},
+    engine::{Command, EngineHandle},
+    state::{experiment_state::ExperimentState, AfterExperiment, AppData, AppState, LiveViewState},
     storage::{self, Folder},
 };
 
@@ -49,12 +52,12 @@ pub fn current_state(app: tauri::AppHandle) -> AppState {
 
 #[specta::specta]
 #[tauri::command]
-pub fn is_connected(app: tauri::AppHandle) -> bool {
+pub fn get_connected_clients(app: tauri::AppHandle) -> Vec<ConnectedClient> {
     let app_data = app.state::<AppData>();
 
-    let connected_clients = *app_data.connected_clients.borrow();
+    let clients = app_data.clients.clients();
 
-    connected_clients > 0
+    clients
 }
 
 #[specta::specta]
@@ -88,7 +91,40 @@ pub fn get_ip_address() -> String {
 #[tauri::command]
 pub fn get_secret(app: tauri::AppHandle) -> String {
     let app_data = app.state::<AppData>();
-    let secret = (*app_data.secret).clone();
+    let secret = (*app_data.secret.current()).clone();
+
+    secret
+}
+
+/// SHA-256 fingerprint of the TLS certificate for the QR code, None when serving plain HTTP
+#[specta::specta]
+#[tauri::command]
+pub fn get_certificate_fingerprint(app: tauri::AppHandle) -> Option<String> {
+    let app_data = app.state::<AppData>();
+    let fingerprint = app_data
+        .certificate
+        .as_ref()
+        .map(|certificate| certificate.fingerprint().to_owned());
+
+    fingerprint
+}
+
+/// Name the server is advertised with on the local network, None when not advertised
+#[specta::specta]
+#[tauri::command]
+pub fn get_service_name(app: tauri::AppHandle) -> Option<String> {
+    let app_data = app.state::<AppData>();
+    let service_name = app_data.service_name.clone();
+
+    service_name
+}
+
+/// Replace the secret with a new one, disconnecting every headset connected with the old secret
+#[specta::specta]
+#[tauri::command]
+pub fn rotate_secret(app: tauri::AppHandle) -> String {
+    let app_data = app.state::<AppData>();
+    let secret = (*app_data.secret.rotate()).clone();
 
     secret
 }
@@ -126,6 +162,7 @@ pub async fn create_preset(app: tauri::AppHandle, preset_name: String) -> Result
         app_state
             .try_as_live_view_ref()
             .context("Must be in live mode")?
+            .parameters
             .clone()
     };
 
@@ -168,26 +205,67 @@ pub async fn create_experiment(
     let mut experiment_presets: HashMap<String, Preset> =
         HashMap::with_capacity(experiment_init_data.presets.len());
 
-    for preset_name in experiment_init_data.presets {
+    // A preset mask is shown like the other presets, so it is kept with them
+    let mask_preset = match &experiment_init_data.mask {
+        Some(Mask::Preset { preset }) => Some(preset.clone()),
+        _ => None,
+    };
+
+    // So are the presets of the practice
+    let practice_presets = experiment_init_data.experiment_type.practice_presets();
+
+    for preset_name in experiment_init_data
+        .presets
+        .into_iter()
+        .chain(mask_preset)
+        .chain(practice_presets)
+    {
         experiment_presets.insert(
             slugify(preset_name.clone()),
             storage::read_file::<Preset>(&slugify(preset_name), Folder::Presets).await?,
         );
     }
 
-    let experiment = match experiment_init_data.experiment_type {
-        CreateExperimentType::Rating { order } => Experiment::Rating(RatingExperiment::new(
-            experiment_init_data.name,
-            experiment_presets,
+    let mut experiment = match experiment_init_data.experiment_type {
+        CreateExperimentType::Rating {
             order,
-        )),
-        CreateExperimentType::Choice { choices } => Experiment::Choice(ChoiceExperiment::new(
-            experiment_init_data.name,
-            experiment_presets,
+            practice_order,
+        } => Experiment::Rating(RatingExperiment {
+            practice_order,
+            ..RatingExperiment::new(experiment_init_data.name, experiment_presets, order)
+        }),
+        CreateExperimentType::Choice {
             choices,
-        )),
+            swap_transition,
+            practice_choices,
+        } => Experiment::Choice(ChoiceExperiment {
+            practice_choices,
+            ..ChoiceExperiment::new(
+                experiment_init_data.name,
+                experiment_presets,
+                choices,
+                swap_transition,
+            )
+        }),
     };
 
+    let shared = experiment.shared_mut();
+    if let Some(inter_trial_interval) = experiment_init_data.inter_trial_interval {
+        shared.inter_trial_interval = inter_trial_interval;
+    }
+    shared.mask = experiment_init_data.mask.map(|mask| match mask {
+        Mask::Preset { preset } => Mask::Preset {
+            preset: slugify(preset),
+        },
+        Mask::Default => Mask::Default,
+    });
+    shared.response_timeout = experiment_init_data.response_timeout;
+    shared.on_timeout = experiment_init_data.on_timeout;
+    shared.drop_practice = experiment_init_data.drop_practice;
+
+    experiment.validate_parameters()?;
+    experiment.validate_settings()?;
+
     let path = storage::create_file(&experiment_key, &experiment, Folder::Experiments).await?;
     Ok(path)
 }
@@ -215,15 +293,27 @@ pub fn set_idle_mode(app: tauri::AppHandle) -> Result<(), AppError> {
     Ok(())
 }
 
-/// Enter live mode with parameters
+/// Enter live mode with parameters, optionally animating to them on the headset
 #[tauri::command]
 #[specta::specta]
-pub fn set_live_mode(app: tauri::AppHandle, parameters: ParameterValues) -> Result<(), AppError> {
+pub fn set_live_mode(
+    app: tauri::AppHandle,
+    mut parameters: ParameterValues,
+    transition: Option<Transition>,
+) -> Result<(), AppError> {
     let app_data = app.state::<AppData>();
 
+    parameters.validate_parameters()?;
+
+    let live_view = LiveViewState {
+        parameters,
+        transition,
+    };
+
+    app_data.last_live_view.send_replace(live_view.clone());
     app_data
         .state
-        .send(AppState::LiveView(parameters))
+        .send(AppState::LiveView(live_view))
         .context("Send new app state")?;
 
     Ok(())
@@ -238,151 +328,201 @@ pub async fn start_experiment(
     obeserver_id: u32,
     note: String,
     randomize: bool,
+    after_experiment: AfterExperiment,
 ) -> Result<(), AppError> {
-    let result_key = slugify(&result_name);
+    let mut experiment_state =
+        ExperimentState::start(experiment_key, result_name, obeserver_id, note, randomize).await?;
 
-    let experiment = storage::read_file::<Experiment>(&experiment_key, Folder::Experiments).await?;
+    let app_data = app.state::<AppData>();
 
-    let time = Local::now();
-    let mut rng = rand::rng();
+    // The live view is usually idle while choosing an experiment, so the last one set is remembered
+    let previous_live_view = app_data.last_live_view.borrow().clone();
+    experiment_state
+        .set_after_experiment(after_experiment, Some(previous_live_view))
+        .await?;
 
-    let experiment_state = match experiment {
-        Experiment::Rating(mut rating_experiment) => {
-            if randomize {
-                rating_experiment.order.shuffle(&mut rng);
-            }
+    let engine = app.state::<EngineHandle>();
 
-            let experiment_result = RatingExperimentResult::new(
-                result_name,
-                time,
-                obeserver_id,
-                note,
-                &rating_experiment,
-            );
+    engine
+        .send(Command::Start(Box::new(experiment_state)))
+        .await?;
 
-            ExperimentState::new_rating(
-                experiment_key,
-                result_key,
-                rating_experiment,
-                experiment_result,
-            )
-        }
+    Ok(())
+}
 
-        Experiment::Choice(mut choice_experiment) => {
-            if randomize {
-                choice_experiment.choices.shuffle(&mut rng);
-            }
+/// Exit the current experiment early. The answers so far are saved as an aborted result with the
+/// exit reason when `save_partial_result` is set, and discarded otherwise
+#[tauri::command]
+#[specta::specta]
+pub async fn exit_experiment(
+    app: tauri::AppHandle,
+    save_partial_result: bool,
+    exit_reason: Option<String>,
+) -> Result<(), AppError> {
+    let engine = app.state::<EngineHandle>();
 
-            let experiment_result = ChoiceExperimentResult::new(
-                result_name,
-                time,
-                obeserver_id,
-                note,
-                &choice_experiment,
-            );
+    // The partial result is reported by the engine, like the result of a finished experiment
+    engine
+        .send(Command::Exit {
+            save_partial: save_partial_result,
+            exit_reason: exit_reason.filter(|reason| !reason.trim().is_empty()),
+        })
+        .await?;
 
-            ExperimentState::new_choice(
-                experiment_key,
-                result_key,
-                choice_experiment,
-                experiment_result,
-            )
-        }
-    };
+    Ok(())
+}
 
-    // Update the AppState in AppData to be in "ExperimentMode"
-    let app_data = app.state::<AppData>();
+/// Answer the current experiment prompt
+#[tauri::command]
+#[specta::specta]
+pub async fn answer_experiment(
+    app: tauri::AppHandle,
+    answer: ExperimentAnswer,
+) -> Result<(), AppError> {
+    let engine = app.state::<EngineHandle>();
 
-    app_data
-        .state
-        .send(AppState::Experiment(experiment_state))
-        .unwrap();
+    engine.send(Command::Answer(answer)).await?;
 
     Ok(())
 }
 
-/// Exit the current experiment early
+/// Swap the current preset in the experiment
 #[tauri::command]
 #[specta::specta]
-pub fn exit_experiment(app: tauri::AppHandle) {
-    let app_data = app.state::<AppData>();
+pub async fn swap_preset(app: tauri::AppHandle) -> Result<(), AppError> {
+    let engine = app.state::<EngineHandle>();
 
-    app_data
-        .state
-        .send(AppState::LiveView(ParameterValues {
-            // TODO Fikse med endringer fra oliver
-            ..Default::default()
-        }))
-        .unwrap();
+    engine.send(Command::SwapPreset).await?;
+
+    Ok(())
 }
 
-/// Answer the current experiment prompt
+/// Pause the current experiment for a break, the headset shows idle until it is resumed
 #[tauri::command]
 #[specta::specta]
-pub async fn answer_experiment(
-    app: tauri::AppHandle,
-    answer: ExperimentAnswer,
-) -> Result<(), AppError> {
-    let app_data = app.state::<AppData>();
+pub async fn pause_experiment(app: tauri::AppHandle) -> Result<(), AppError> {
+    let engine = app.state::<EngineHandle>();
 
-    let experiment_is_done = app_data.state.send_modify_with(|state| {
-        let is_done = state.answer_experiment(answer);
+    engine.send(Command::Pause).await?;
 
-        if let Some(experiment_state) = state.try_as_experiment_mut() {
-            experiment_state.set_is_idle(true);
-        }
+    Ok(())
+}
 
-        is_done
-    })?;
+/// Resume the current experiment after a pause, showing the prompt that was paused
+#[tauri::command]
+#[specta::specta]
+pub async fn resume_experiment(app: tauri::AppHandle) -> Result<(), AppError> {
+    let engine = app.state::<EngineHandle>();
 
-    // Sleep for a second while idle
-    if !experiment_is_done {
-        sleep(Duration::from_secs(1)).await;
+    engine.send(Command::Resume).await?;
 
-        app_data.state.send_modify(|state| {
-            if let Some(experiment_state) = state.try_as_experiment_mut() {
-                experiment_state.set_is_idle(false);
-            }
-        });
+    Ok(())
+}
+
+/// Undo the last answer of the current experiment, e.g. when the observer pressed the wrong button
+#[tauri::command]
+#[specta::specta]
+pub async fn undo_answer(app: tauri::AppHandle) -> Result<(), AppError> {
+    let engine = app.state::<EngineHandle>();
+
+    engine.send(Command::UndoAnswer).await?;
+
+    Ok(())
+}
+
+/// Move the current prompt to the end of the experiment, to be shown again after the others
+#[tauri::command]
+#[specta::specta]
+pub async fn repeat_later(app: tauri::AppHandle) -> Result<(), AppError> {
+    let engine = app.state::<EngineHandle>();
+
+    engine.send(Command::RepeatLater).await?;
+
+    Ok(())
+}
+
+/// An experiment that was left before every prompt was answered, e.g. because the app crashed
+#[derive(Serialize, Type)]
+pub struct UnfinishedSession {
+    pub experiment_key: String,
+    pub experiment_name: String,
+    pub result_name: String,
+    pub observer_id: u32,
+    pub started_at: DateTime<Local>,
+    /// Prompts the observer answered, leaving out the practice and timeouts
+    pub answered: u32,
+    pub trial_count: u32,
+}
+
+impl From<&ExperimentState> for UnfinishedSession {
+    fn from(experiment_state: &ExperimentState) -> Self {
+        let result = experiment_state.result_shared();
+
+        let experiment_name = match experiment_state {
+            ExperimentState::Rating(state) => state.experiment.shared.name.clone(),
+            ExperimentState::Choice(state) => state.experiment.shared.name.clone(),
+        };
+
+        Self {
+            experiment_key: experiment_state.experiment_key.clone(),
+            experiment_name,
+            result_name: result.name.clone(),
+            observer_id: result.observer_id,
+            started_at: result.time,
+            answered: experiment_state.answered_count() as u32,
+            trial_count: experiment_state.trial_count() as u32,
+        }
     }
+}
 
-    // If the experiment is done, finish it and emit the result saved event
-    if experiment_is_done {
-        let experiment_state = app_data
-            .state
-            .send_replace(AppState::Idle)
-            .try_as_experiment()
-            .context("Must be in experiment")?;
+/// Get the experiments that were checkpointed but never finished, which can be resumed or saved as incomplete
+#[tauri::command]
+#[specta::specta]
+pub async fn get_unfinished_sessions() -> Result<Vec<WithKey<UnfinishedSession>>, AppError> {
+    let sessions = storage::read_files::<ExperimentState>(Folder::Sessions)
+        .await?
+        .into_iter()
+        .map(|session| WithKey {
+            value: UnfinishedSession::from(&session.value),
+            key: session.key,
+        })
+        .collect();
+
+    Ok(sessions)
+}
 
-        let result_file_path = experiment_state.finish_experiment().await?;
+/// Continue an unfinished experiment from the first unanswered prompt
+#[tauri::command]
+#[specta::specta]
+pub async fn resume_session(app: tauri::AppHandle, key: String) -> Result<(), AppError> {
+    let experiment_state = storage::read_file::<ExperimentState>(&key, Folder::Sessions).await?;
 
-        ResultSavedEvent { result_file_path }
-            .emit(&app)
-            .context("Could not emit event")?;
+    if experiment_state.is_done() {
+        return Err(anyhow::anyhow!("Every prompt is answered, save the session instead").into());
     }
 
+    let engine = app.state::<EngineHandle>();
+
+    engine
+        .send(Command::ResumeSession(Box::new(experiment_state)))
+        .await?;
+
     Ok(())
 }
 
-/// Swap the current preset in the experiment
+/// Save the answers of an unfinished experiment as an incomplete result
 #[tauri::command]
 #[specta::specta]
-pub fn swap_preset(app: tauri::AppHandle) -> Result<(), AppError> {
-    let app_data = app.state::<AppData>();
-
-    app_data
-        .state
-        .send_modify_with(|state| -> anyhow::Result<()> {
-            let choice = state
-                .try_as_experiment_mut()
-                .context("Must be in experiment")?
-                .try_as_choice_mut()
-                .context("Must be in a choice experiment")?;
+pub async fn save_incomplete_session(app: tauri::AppHandle, key: String) -> Result<(), AppError> {
+    let experiment_state = storage::read_file::<ExperimentState>(&key, Folder::Sessions).await?;
 
-            choice.swap_current_preset();
+    let result_file_path = experiment_state
+        .save_result(CompletionStatus::Incomplete)
+        .await?;
 
-            Ok(())
-        })?;
+    ResultSavedEvent { result_file_path }
+        .emit(&app)
+        .context("Could not emit event")?;
 
     Ok(())
 }
@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 
+use neuroviz::clients::ConnectedClient;
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use tauri_specta::Event;
@@ -7,8 +8,14 @@ use tauri_specta::Event;
 use crate::state::AppState;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Type, Event)]
-pub struct ConnectionEvent {
-    pub is_connected: bool,
+pub struct ConnectedClientsEvent {
+    pub clients: Vec<ConnectedClient>,
+}
+
+/// Sent when the secret is rotated, after which headsets must connect with the new secret
+#[derive(Serialize, Deserialize, Debug, Clone, Type, Event)]
+pub struct SecretEvent {
+    pub secret: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Type, Event)]
@@ -0,0 +1,364 @@
+//! Runs the headset server without the desktop window, for headless lab machines and scripted sessions
+
+use std::process::ExitCode;
+
+use anyhow::{bail, Context};
+use clap::{Parser, Subcommand};
+use local_ip_address::local_ip;
+use neuroviz::{
+    parameters::{ParameterSchema, ParameterValues},
+    transition::Transition,
+};
+use tauri_neuroviz_lib::{
+    consts::HTTP_SERVER_PORT,
+    data::{
+        experiment::Experiment, experiment_result::CompletionStatus, preset::Preset,
+        ValidateParameters,
+    },
+    engine::{Command as EngineCommand, EngineEvent, EngineHandle},
+    state::{experiment_state::ExperimentState, AppState, LiveViewState},
+    storage::{self, Folder},
+    Server,
+};
+use tokio::{
+    io::{stdin, AsyncBufReadExt, BufReader},
+    select,
+    signal::ctrl_c,
+    sync::mpsc,
+};
+
+/// Run NeuroViz experiments from the command line, using the same data folder as the desktop app
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the presets in the data folder
+    Presets,
+
+    /// List the experiments in the data folder
+    Experiments,
+
+    /// Run an experiment until every prompt is answered, then save the result.
+    /// Type `pause` or `resume` on stdin to take a break, `undo` to undo the last answer,
+    /// or `repeat` to show the current prompt again at the end
+    Run {
+        /// Key of the experiment, as listed by `experiments`
+        experiment: String,
+
+        /// Id of the observer taking part
+        #[arg(long)]
+        observer_id: u32,
+
+        /// Name of the result, defaults to the observer id
+        #[arg(long)]
+        name: Option<String>,
+
+        #[arg(long, default_value = "")]
+        note: String,
+
+        /// Shuffle the order of the prompts
+        #[arg(long)]
+        randomize: bool,
+    },
+
+    /// List the experiments that were left before every prompt was answered, e.g. because of a crash
+    Sessions,
+
+    /// Continue an unfinished experiment from the first unanswered prompt
+    Resume {
+        /// Key of the session, as listed by `sessions`
+        session: String,
+    },
+
+    /// Save the answers of an unfinished experiment as an incomplete result
+    SaveIncomplete {
+        /// Key of the session, as listed by `sessions`
+        session: String,
+    },
+
+    /// Show parameters read from stdin, one line at a time.
+    /// A line is `idle`, or parameter values like `glow=0.5 transparency=0.2`,
+    /// which change the given parameters and keep the others
+    Live {
+        /// Seconds the headset takes to animate to new parameters
+        #[arg(long)]
+        transition: Option<f64>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    match run(args.command).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{error:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(command: Command) -> anyhow::Result<()> {
+    // Load the parameter schema before anything reads or writes parameters
+    if let Some(schema) = storage::read_parameter_schema().await? {
+        ParameterSchema::set_global(schema);
+    }
+
+    let unfinished_sessions = storage::read_files::<ExperimentState>(Folder::Sessions).await?;
+    if !unfinished_sessions.is_empty() && !matches!(command, Command::Sessions) {
+        eprintln!(
+            "{} unfinished experiments can be resumed or saved, see `sessions`",
+            unfinished_sessions.len()
+        );
+    }
+
+    match command {
+        Command::Presets => {
+            for preset in storage::read_files::<Preset>(Folder::Presets).await? {
+                println!("{}\t{}", preset.key, preset.value.name);
+            }
+        }
+
+        Command::Experiments => {
+            for experiment in storage::read_files::<Experiment>(Folder::Experiments).await? {
+                let (kind, name, trial_count) = match &experiment.value {
+                    Experiment::Rating(rating) => {
+                        ("rating", &rating.shared.name, rating.order.len())
+                    }
+                    Experiment::Choice(choice) => {
+                        ("choice", &choice.shared.name, choice.choices.len())
+                    }
+                };
+
+                println!("{}\t{kind}\t{trial_count} prompts\t{name}", experiment.key);
+            }
+        }
+
+        Command::Run {
+            experiment,
+            observer_id,
+            name,
+            note,
+            randomize,
+        } => {
+            let name = name.unwrap_or_else(|| format!("observer-{observer_id}"));
+            let experiment_state =
+                ExperimentState::start(experiment, name, observer_id, note, randomize).await?;
+
+            run_experiment(experiment_state).await?;
+        }
+
+        Command::Sessions => {
+            for session in storage::read_files::<ExperimentState>(Folder::Sessions).await? {
+                let experiment_state = session.value;
+
+                println!(
+                    "{}\t{}\tobserver {}\t{}/{} answered",
+                    session.key,
+                    experiment_state.experiment_key,
+                    experiment_state.result_shared().observer_id,
+                    experiment_state.answered_count(),
+                    experiment_state.trial_count()
+                );
+            }
+        }
+
+        Command::Resume { session } => {
+            let mut experiment_state =
+                storage::read_file::<ExperimentState>(&session, Folder::Sessions).await?;
+
+            if experiment_state.is_done() {
+                bail!("Every prompt is answered, save the session instead");
+            }
+
+            experiment_state.resume_session();
+            run_experiment(experiment_state).await?;
+        }
+
+        Command::SaveIncomplete { session } => {
+            let experiment_state =
+                storage::read_file::<ExperimentState>(&session, Folder::Sessions).await?;
+
+            let result_file_path = experiment_state
+                .save_result(CompletionStatus::Incomplete)
+                .await?;
+
+            println!("Saved the result to {}", result_file_path.display());
+        }
+
+        Command::Live { transition } => {
+            let transition = transition
+                .map(|duration| Transition::new(duration, Default::default()))
+                .transpose()?;
+
+            run_live(transition).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Print where the headset should connect and the secret, since there is no QR code to scan
+fn print_connection(server: &Server) {
+    let scheme = match server.app_data.certificate {
+        Some(_) => "https",
+        None => "http",
+    };
+
+    match local_ip() {
+        Ok(ip) => println!("Connect the headset to {scheme}://{ip}:{HTTP_SERVER_PORT}"),
+        Err(error) => eprintln!("Could not get the local IP address: {error}"),
+    }
+
+    println!("Secret: {}", server.app_data.secret.current());
+}
+
+async fn run_experiment(experiment_state: ExperimentState) -> anyhow::Result<()> {
+    let trial_count = experiment_state.trial_count();
+    let session_key = experiment_state.session_key();
+    println!(
+        "Starting {} with {trial_count} prompts",
+        experiment_state.experiment_key
+    );
+
+    let server = Server::start(AppState::Experiment(experiment_state)).await?;
+    print_connection(&server);
+
+    let engine = server.engine.clone();
+    let (event_sender, mut event_receiver) = mpsc::unbounded_channel();
+
+    let run_server = server.run(move |event| {
+        let _ = event_sender.send(event);
+    });
+
+    let finished = async {
+        while let Some(event) = event_receiver.recv().await {
+            match event {
+                EngineEvent::Answered {
+                    answered,
+                    trial_count,
+                } => println!("Answered {answered}/{trial_count}"),
+                EngineEvent::TimedOut { trial_index } => {
+                    println!("Prompt {} was not answered in time", trial_index + 1)
+                }
+                EngineEvent::Finished(result) => return result,
+                // The command-line controller never exits an experiment early through the engine
+                EngineEvent::Exited(_) => {}
+            }
+        }
+
+        bail!("The experiment stopped")
+    };
+
+    let result_file_path = select! {
+        _ = run_server => bail!("The server stopped"),
+        // Stdin closing only stops the operator commands, the experiment keeps running
+        Err(error) = read_operator_commands(engine) => return Err(error),
+        result = finished => result?,
+        _ = ctrl_c() => bail!(
+            "Stopped before every prompt was answered, continue with `resume {session_key}`"
+        ),
+    };
+
+    println!("Saved the result to {}", result_file_path.display());
+
+    Ok(())
+}
+
+/// Correct the experiment by commands on stdin, one per line, until stdin is closed
+async fn read_operator_commands(engine: EngineHandle) -> anyhow::Result<()> {
+    let mut lines = BufReader::new(stdin()).lines();
+
+    while let Some(line) = lines.next_line().await.context("Could not read stdin")? {
+        let (command, done_message) = match line.trim() {
+            "pause" => (EngineCommand::Pause, "Paused, type `resume` to continue"),
+            "resume" => (EngineCommand::Resume, "Resumed"),
+            "undo" => (EngineCommand::UndoAnswer, "Undid the last answer"),
+            "repeat" => (EngineCommand::RepeatLater, "Moved the prompt to the end"),
+            "" => continue,
+            other => {
+                eprintln!("Expected `pause`, `resume`, `undo` or `repeat`, got `{other}`");
+                continue;
+            }
+        };
+
+        match engine.send(command).await {
+            Ok(()) => println!("{done_message}"),
+            Err(rejection) => eprintln!("{rejection}"),
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_live(transition: Option<Transition>) -> anyhow::Result<()> {
+    let server = Server::start(AppState::LiveView(LiveViewState::default())).await?;
+    print_connection(&server);
+
+    let state = server.app_data.state.clone();
+    let mut lines = BufReader::new(stdin()).lines();
+
+    let read_lines = async {
+        while let Some(line) = lines.next_line().await.context("Could not read stdin")? {
+            let line = line.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if line == "idle" {
+                state.send_replace(AppState::Idle);
+                continue;
+            }
+
+            // Parameters that are left out keep the values currently shown
+            let mut parameters = match &*state.borrow() {
+                AppState::LiveView(live_view) => live_view.parameters.clone(),
+                _ => ParameterValues::default(),
+            };
+
+            if let Err(error) = parse_parameters(line, &mut parameters) {
+                eprintln!("{error:#}");
+                continue;
+            }
+
+            state.send_replace(AppState::LiveView(LiveViewState {
+                parameters,
+                transition,
+            }));
+        }
+
+        anyhow::Ok(())
+    };
+
+    select! {
+        _ = server.run(|_| {}) => bail!("The server stopped"),
+        result = read_lines => result,
+        _ = ctrl_c() => Ok(()),
+    }
+}
+
+/// Set the parameters on a line like `glow=0.5 transparency=0.2`
+fn parse_parameters(line: &str, parameters: &mut ParameterValues) -> anyhow::Result<()> {
+    for assignment in line.split_whitespace() {
+        let (key, value) = assignment
+            .split_once('=')
+            .with_context(|| format!("Expected `<parameter>=<value>`, got `{assignment}`"))?;
+
+        let value = value
+            .parse::<f32>()
+            .with_context(|| format!("Invalid value for {key}: `{value}`"))?;
+
+        parameters.set(key, value);
+    }
+
+    parameters.validate_parameters()?;
+
+    Ok(())
+}
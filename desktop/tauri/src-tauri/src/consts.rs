@@ -1 +1,10 @@
 pub const HTTP_SERVER_PORT: u16 = 9001;
+
+/// File in the data folder that defines the parameter schema
+pub const PARAMETER_SCHEMA_FILE: &str = "parameters.json";
+
+/// File in the data folder with the settings of the headset server
+pub const SERVER_SETTINGS_FILE: &str = "server.json";
+
+/// Folder in the data folder where the TLS certificate is kept, so its fingerprint stays the same between runs
+pub const TLS_FOLDER: &str = "tls";
@@ -1,10 +1,12 @@
 use std::collections::HashMap;
 
+use anyhow::{bail, Context};
+use neuroviz::{parameters::ParameterValues, transition::Transition};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use strum::EnumTryAs;
 
-use super::preset::Preset;
+use super::{preset::Preset, ValidateParameters, ValidateSettings};
 
 #[derive(Debug, Deserialize, Serialize, Type, Clone)]
 pub struct Choice {
@@ -17,11 +19,36 @@ pub struct Choice {
 pub enum CreateExperimentType {
     /// Rating between 1-5
     #[serde(rename = "rating")]
-    Rating { order: Vec<String> },
+    Rating {
+        order: Vec<String>,
+        #[serde(default)]
+        practice_order: Vec<String>,
+    },
 
     /// Choose between two options
     #[serde(rename = "choice")]
-    Choice { choices: Vec<Choice> },
+    Choice {
+        choices: Vec<Choice>,
+        #[serde(default)]
+        swap_transition: Option<Transition>,
+        #[serde(default)]
+        practice_choices: Vec<Choice>,
+    },
+}
+
+impl CreateExperimentType {
+    /// Keys of the presets shown in the practice, which are added to the presets
+    pub fn practice_presets(&self) -> Vec<String> {
+        match self {
+            CreateExperimentType::Rating { practice_order, .. } => practice_order.clone(),
+            CreateExperimentType::Choice {
+                practice_choices, ..
+            } => practice_choices
+                .iter()
+                .flat_map(|choice| [choice.a.clone(), choice.b.clone()])
+                .collect(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Type)]
@@ -30,6 +57,20 @@ pub struct CreateExperiment {
     pub experiment_type: CreateExperimentType,
     pub name: String,
     pub presets: Vec<String>,
+    /// Seconds between prompts, defaults to one second
+    #[serde(default)]
+    pub inter_trial_interval: Option<f64>,
+    /// Shown between prompts instead of idle, a preset mask is added to the presets
+    #[serde(default)]
+    pub mask: Option<Mask>,
+    /// Seconds the observer has to answer each prompt, None to wait forever
+    #[serde(default)]
+    pub response_timeout: Option<f64>,
+    #[serde(default)]
+    pub on_timeout: TimeoutAction,
+    /// Leave the practice answers out of the result, instead of keeping them apart
+    #[serde(default)]
+    pub drop_practice: bool,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
@@ -38,10 +79,112 @@ pub enum CurrentPreset {
     B,
 }
 
+/// What the headset shows between prompts, so the observer does not compare against the previous prompt
+#[derive(Debug, Serialize, Deserialize, Type, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Mask {
+    /// One of the presets of the experiment, e.g. a neutral one
+    Preset { preset: String },
+    /// The default value of every parameter
+    Default,
+}
+
+/// What happens to a prompt that was not answered before the response timeout
+#[derive(Debug, Serialize, Deserialize, Type, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeoutAction {
+    /// Go on to the next prompt
+    #[default]
+    Skip,
+    /// Show the prompt again at the end, until it is answered
+    Requeue,
+}
+
+fn default_inter_trial_interval() -> f64 {
+    1.0
+}
+
 #[derive(Debug, Serialize, Deserialize, Type, Clone)]
 pub struct SharedExperiment {
     pub name: String,
     pub presets: HashMap<String, Preset>,
+    /// Seconds the headset shows idle, or the mask, between prompts. Zero shows the next prompt at once
+    #[serde(default = "default_inter_trial_interval")]
+    pub inter_trial_interval: f64,
+    /// Shown between prompts instead of idle
+    #[serde(default)]
+    pub mask: Option<Mask>,
+    /// Seconds the observer has to answer each prompt after it is displayed, None to wait forever
+    #[serde(default)]
+    pub response_timeout: Option<f64>,
+    #[serde(default)]
+    pub on_timeout: TimeoutAction,
+    /// Leave the practice answers out of the result, instead of keeping them apart
+    #[serde(default)]
+    pub drop_practice: bool,
+}
+
+impl SharedExperiment {
+    pub fn new(name: String, presets: HashMap<String, Preset>) -> Self {
+        Self {
+            name,
+            presets,
+            inter_trial_interval: default_inter_trial_interval(),
+            mask: None,
+            response_timeout: None,
+            on_timeout: TimeoutAction::default(),
+            drop_practice: false,
+        }
+    }
+
+    /// Parameters of the mask, None when idle is shown between prompts
+    pub fn mask_parameters(&self) -> Option<ParameterValues> {
+        let parameters = match self.mask.as_ref()? {
+            Mask::Preset { preset } => self.presets.get(preset)?.parameters.clone(),
+            Mask::Default => ParameterValues::default(),
+        };
+
+        Some(parameters)
+    }
+}
+
+impl ValidateSettings for SharedExperiment {
+    fn validate_settings(&self) -> anyhow::Result<()> {
+        if !(self.inter_trial_interval.is_finite() && self.inter_trial_interval >= 0.0) {
+            bail!(
+                "The inter-trial interval must be zero or more seconds, got {}",
+                self.inter_trial_interval
+            );
+        }
+
+        if let Some(response_timeout) = self.response_timeout {
+            if !(response_timeout.is_finite() && response_timeout > 0.0) {
+                bail!(
+                    "The response timeout must be more than zero seconds, got {response_timeout}"
+                );
+            }
+        }
+
+        if let Some(Mask::Preset { preset }) = &self.mask {
+            if !self.presets.contains_key(preset) {
+                bail!("The mask preset `{preset}` is not one of the presets");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ValidateParameters for SharedExperiment {
+    fn validate_parameters(&mut self) -> anyhow::Result<()> {
+        for (preset_key, preset) in &mut self.presets {
+            preset
+                .validate_parameters()
+                .with_context(|| format!("Invalid preset `{preset_key}`"))?;
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Type, Clone)]
@@ -50,13 +193,28 @@ pub struct ChoiceExperiment {
     pub shared: SharedExperiment,
 
     pub choices: Vec<Choice>,
+
+    /// How the headset morphs between A and B when swapping, None means switching instantly
+    #[serde(default)]
+    pub swap_transition: Option<Transition>,
+
+    /// Choices made before the others to practice, which are kept apart in the result
+    #[serde(default)]
+    pub practice_choices: Vec<Choice>,
 }
 
 impl ChoiceExperiment {
-    pub fn new(name: String, presets: HashMap<String, Preset>, choices: Vec<Choice>) -> Self {
+    pub fn new(
+        name: String,
+        presets: HashMap<String, Preset>,
+        choices: Vec<Choice>,
+        swap_transition: Option<Transition>,
+    ) -> Self {
         Self {
-            shared: SharedExperiment { name, presets },
+            shared: SharedExperiment::new(name, presets),
             choices,
+            swap_transition,
+            practice_choices: Vec::new(),
         }
     }
 }
@@ -67,13 +225,32 @@ pub struct RatingExperiment {
     pub shared: SharedExperiment,
 
     pub order: Vec<String>,
+
+    /// Presets rated before the others to practice, which are kept apart in the result
+    #[serde(default)]
+    pub practice_order: Vec<String>,
+}
+
+impl ValidateSettings for ChoiceExperiment {
+    fn validate_settings(&self) -> anyhow::Result<()> {
+        self.shared.validate_settings()?;
+
+        if let Some(swap_transition) = &self.swap_transition {
+            swap_transition
+                .validate()
+                .context("Invalid swap transition")?;
+        }
+
+        Ok(())
+    }
 }
 
 impl RatingExperiment {
     pub fn new(name: String, presets: HashMap<String, Preset>, order: Vec<String>) -> Self {
         Self {
-            shared: SharedExperiment { name, presets },
+            shared: SharedExperiment::new(name, presets),
             order,
+            practice_order: Vec::new(),
         }
     }
 }
@@ -86,3 +263,30 @@ pub enum Experiment {
     #[serde(rename = "choice")]
     Choice(ChoiceExperiment),
 }
+
+impl Experiment {
+    pub fn shared_mut(&mut self) -> &mut SharedExperiment {
+        match self {
+            Experiment::Rating(rating_experiment) => &mut rating_experiment.shared,
+            Experiment::Choice(choice_experiment) => &mut choice_experiment.shared,
+        }
+    }
+}
+
+impl ValidateParameters for Experiment {
+    fn validate_parameters(&mut self) -> anyhow::Result<()> {
+        match self {
+            Experiment::Rating(rating_experiment) => rating_experiment.shared.validate_parameters(),
+            Experiment::Choice(choice_experiment) => choice_experiment.shared.validate_parameters(),
+        }
+    }
+}
+
+impl ValidateSettings for Experiment {
+    fn validate_settings(&self) -> anyhow::Result<()> {
+        match self {
+            Experiment::Rating(rating_experiment) => rating_experiment.shared.validate_settings(),
+            Experiment::Choice(choice_experiment) => choice_experiment.validate_settings(),
+        }
+    }
+}
@@ -10,13 +10,33 @@ use super::{
     preset::Preset,
 };
 
+/// When a prompt was shown, as seen by the desktop and by the headset
+#[derive(Debug, Deserialize, Serialize, Type, Clone, PartialEq)]
+pub struct PromptTiming {
+    /// When the desktop sent the prompt to the headset
+    pub prompted_at: DateTime<Local>,
+    /// Seconds from the desktop sending the prompt to the answer, not counting pauses
+    pub desktop_duration: f64,
+    /// When the headset displayed the prompt, using the clock of the headset
+    pub displayed_at: Option<DateTime<Local>>,
+    /// When the desktop received the display acknowledgement from the headset
+    pub acknowledged_at: Option<DateTime<Local>>,
+    /// Seconds the headset showed idle or the mask before the prompt, None when there was no gap
+    #[serde(default)]
+    pub gap: Option<f64>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Type, Clone)]
 pub struct OutcomeChoice {
     pub a: String,
     pub b: String,
     pub selected: String,
     pub time: DateTime<Local>,
+    /// Seconds from the prompt being displayed to the answer, see [`PromptTiming`] for the raw timings
     pub duration: f64,
+    /// Missing in results saved before display acknowledgements were recorded
+    #[serde(default)]
+    pub timing: Option<PromptTiming>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Type, Clone)]
@@ -24,7 +44,101 @@ pub struct OutcomeRating {
     pub preset: String,
     pub rank: u8,
     pub time: DateTime<Local>,
+    /// Seconds from the prompt being displayed to the answer, see [`PromptTiming`] for the raw timings
     pub duration: f64,
+    /// Missing in results saved before display acknowledgements were recorded
+    #[serde(default)]
+    pub timing: Option<PromptTiming>,
+}
+
+/// A choice that was not answered before the response timeout
+#[derive(Debug, Deserialize, Serialize, Type, Clone)]
+pub struct TimeoutChoice {
+    pub a: String,
+    pub b: String,
+    pub timed_out_at: DateTime<Local>,
+    /// Whether the choice was shown again at the end
+    pub requeued: bool,
+    pub timing: PromptTiming,
+}
+
+/// A rating that was not answered before the response timeout
+#[derive(Debug, Deserialize, Serialize, Type, Clone)]
+pub struct TimeoutRating {
+    pub preset: String,
+    pub timed_out_at: DateTime<Local>,
+    /// Whether the preset was shown again at the end
+    pub requeued: bool,
+    pub timing: PromptTiming,
+}
+
+/// What came of a choice prompt. Untagged, so results saved before timeouts are still read
+#[derive(Debug, Deserialize, Serialize, Type, Clone, EnumTryAs)]
+#[serde(untagged)]
+pub enum ChoiceOutcome {
+    Answered(OutcomeChoice),
+    TimedOut(TimeoutChoice),
+}
+
+/// What came of a rating prompt. Untagged, so results saved before timeouts are still read
+#[derive(Debug, Deserialize, Serialize, Type, Clone, EnumTryAs)]
+#[serde(untagged)]
+pub enum RatingOutcome {
+    Answered(OutcomeRating),
+    TimedOut(TimeoutRating),
+}
+
+/// A break in the experiment, during which no prompt was shown and answers were rejected
+#[derive(Debug, Deserialize, Serialize, Type, Clone, PartialEq)]
+pub struct Pause {
+    pub started_at: DateTime<Local>,
+    pub ended_at: DateTime<Local>,
+}
+
+impl Pause {
+    /// Seconds of the pause that were after the time
+    pub fn seconds_after(&self, time: DateTime<Local>) -> f64 {
+        let started_at = self.started_at.max(time);
+        let nanoseconds = self
+            .ended_at
+            .signed_duration_since(started_at)
+            .num_nanoseconds()
+            .unwrap_or_default();
+
+        nanoseconds.max(0) as f64 / 1_000_000_000.0
+    }
+}
+
+/// How the operator corrected the experiment
+#[derive(Debug, Deserialize, Serialize, Type, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CorrectionAction {
+    /// The last answer was moved to the undone outcomes, and its prompt shown again
+    Undo,
+    /// The prompt was moved to the end of the order, to be shown again after the others
+    Repeat,
+}
+
+/// A correction by the operator, kept so that the result can be audited
+#[derive(Debug, Deserialize, Serialize, Type, Clone, PartialEq)]
+pub struct Correction {
+    pub action: CorrectionAction,
+    pub time: DateTime<Local>,
+    /// Index of the prompt that was corrected, in the order at the time
+    pub index: u32,
+}
+
+/// Whether every prompt of the experiment was answered before the result was saved
+#[derive(Debug, Deserialize, Serialize, Type, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionStatus {
+    /// Results saved before the status was recorded were always complete
+    #[default]
+    Complete,
+    /// Saved from an unfinished session, e.g. after a crash
+    Incomplete,
+    /// Exited by the operator before every prompt was answered, see the exit reason
+    Aborted,
 }
 
 #[derive(Debug, Deserialize, Serialize, Type, Clone)]
@@ -34,6 +148,16 @@ pub struct SharedExperimentResult {
     pub observer_id: u32,
     pub note: String,
     pub presets: HashMap<String, Preset>,
+    #[serde(default)]
+    pub status: CompletionStatus,
+    /// Breaks taken during the experiment, which are left out of the durations
+    #[serde(default)]
+    pub pauses: Vec<Pause>,
+    #[serde(default)]
+    pub corrections: Vec<Correction>,
+    /// Why the experiment was exited before every prompt was answered
+    #[serde(default)]
+    pub exit_reason: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Type, Clone)]
@@ -41,7 +165,14 @@ pub struct ChoiceExperimentResult {
     #[serde(flatten)]
     pub shared: SharedExperimentResult,
 
-    pub choices: Vec<OutcomeChoice>,
+    /// One outcome for each prompt shown, in order
+    pub choices: Vec<ChoiceOutcome>,
+    /// Answers that were undone, see [`SharedExperimentResult::corrections`]
+    #[serde(default)]
+    pub undone: Vec<ChoiceOutcome>,
+    /// Outcomes of the practice prompts, empty when the practice answers are dropped
+    #[serde(default)]
+    pub practice: Vec<ChoiceOutcome>,
 }
 
 impl ChoiceExperimentResult {
@@ -59,8 +190,14 @@ impl ChoiceExperimentResult {
                 observer_id,
                 note,
                 presets: experiment.shared.presets.clone(),
+                status: CompletionStatus::Incomplete,
+                pauses: Vec::new(),
+                corrections: Vec::new(),
+                exit_reason: None,
             },
             choices: Vec::new(),
+            undone: Vec::new(),
+            practice: Vec::new(),
         }
     }
 }
@@ -70,7 +207,14 @@ pub struct RatingExperimentResult {
     #[serde(flatten)]
     pub shared: SharedExperimentResult,
 
-    pub ratings: Vec<OutcomeRating>,
+    /// One outcome for each prompt shown, in order
+    pub ratings: Vec<RatingOutcome>,
+    /// Answers that were undone, see [`SharedExperimentResult::corrections`]
+    #[serde(default)]
+    pub undone: Vec<RatingOutcome>,
+    /// Outcomes of the practice prompts, empty when the practice answers are dropped
+    #[serde(default)]
+    pub practice: Vec<RatingOutcome>,
 }
 
 impl RatingExperimentResult {
@@ -88,8 +232,14 @@ impl RatingExperimentResult {
                 observer_id,
                 note,
                 presets: experiment.shared.presets.clone(),
+                status: CompletionStatus::Incomplete,
+                pauses: Vec::new(),
+                corrections: Vec::new(),
+                exit_reason: None,
             },
             ratings: Vec::new(),
+            undone: Vec::new(),
+            practice: Vec::new(),
         }
     }
 }
@@ -102,3 +252,19 @@ pub enum ExperimentResult {
     #[serde(rename = "choice")]
     Choice(ChoiceExperimentResult),
 }
+
+impl ExperimentResult {
+    pub fn shared(&self) -> &SharedExperimentResult {
+        match self {
+            ExperimentResult::Rating(result) => &result.shared,
+            ExperimentResult::Choice(result) => &result.shared,
+        }
+    }
+
+    pub fn shared_mut(&mut self) -> &mut SharedExperimentResult {
+        match self {
+            ExperimentResult::Rating(result) => &mut result.shared,
+            ExperimentResult::Choice(result) => &mut result.shared,
+        }
+    }
+}
@@ -2,3 +2,26 @@ pub mod experiment;
 pub mod experiment_result;
 pub mod folder;
 pub mod preset;
+pub mod settings;
+
+use neuroviz::parameters::ParameterValues;
+
+/// Data that contains parameter values, which must be validated against the parameter schema when loaded
+pub trait ValidateParameters {
+    /// Validate all parameter values, clamping them in place if the schema allows it
+    fn validate_parameters(&mut self) -> anyhow::Result<()>;
+}
+
+/// Data with settings that must be in range, like durations, checked when loaded or created
+pub trait ValidateSettings {
+    /// Check that every setting is in range, unlike parameters they are never clamped
+    fn validate_settings(&self) -> anyhow::Result<()>;
+}
+
+impl ValidateParameters for ParameterValues {
+    fn validate_parameters(&mut self) -> anyhow::Result<()> {
+        self.validate()?;
+
+        Ok(())
+    }
+}
@@ -2,8 +2,23 @@ use neuroviz::parameters::ParameterValues;
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
+use super::{ValidateParameters, ValidateSettings};
+
 #[derive(Deserialize, Serialize, Type, Clone, Debug, PartialEq)]
 pub struct Preset {
     pub name: String,
     pub parameters: ParameterValues,
 }
+
+impl ValidateParameters for Preset {
+    fn validate_parameters(&mut self) -> anyhow::Result<()> {
+        self.parameters.validate_parameters()
+    }
+}
+
+/// A preset only has parameters
+impl ValidateSettings for Preset {
+    fn validate_settings(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
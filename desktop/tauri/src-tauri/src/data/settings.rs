@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// Settings for the headset server, read from the data folder at startup
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ServerSettings {
+    /// Serve HTTPS with a self-signed certificate, which the headset pins using the QR code
+    pub tls: bool,
+    /// Advertise the server on the local network, so headsets can find it without scanning the QR code
+    pub advertise: bool,
+    /// Name the server is advertised with, defaults to a name based on the name of this computer
+    pub name: Option<String>,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            tls: false,
+            advertise: true,
+            name: None,
+        }
+    }
+}
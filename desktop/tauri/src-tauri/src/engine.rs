@@ -0,0 +1,493 @@
+//! The experiment engine owns the experiment state machine. Commands from the desktop and events
+//! from the headset are processed one at a time, so answers from both can not race each other,
+//! nor with starting, resuming or exiting the experiment
+
+use std::{future::pending, path::PathBuf};
+
+use neuroviz::{
+    extensions::WatchSenderExt,
+    http_server::{ExperimentAnswer, PromptId, Rejection, Responder, UnityEvent},
+};
+use tokio::{
+    select,
+    sync::{mpsc, watch},
+    time::{sleep_until, Instant},
+};
+
+use crate::{
+    state::{experiment_state::ExperimentState, AppState},
+    storage::{self, Folder},
+};
+
+/// Something the operator does to the running experiment from the desktop
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// Start the experiment, replacing the live view or idle. Fails if another experiment is in progress
+    Start(Box<ExperimentState>),
+    /// Continue an unfinished experiment, fails if another experiment is in progress
+    ResumeSession(Box<ExperimentState>),
+    /// Leave the experiment early. The answers so far are saved as an aborted result with the exit reason
+    /// when `save_partial` is set, and the session is discarded otherwise
+    Exit {
+        save_partial: bool,
+        exit_reason: Option<String>,
+    },
+    /// Answer the current prompt on behalf of the observer
+    Answer(ExperimentAnswer),
+    SwapPreset,
+    Pause,
+    Resume,
+    UndoAnswer,
+    RepeatLater,
+}
+
+/// What happened in the engine, for the desktop or the command-line controller to report
+#[derive(Debug)]
+pub enum EngineEvent {
+    /// An answer or a timeout was recorded, from the desktop or the headset
+    Answered { answered: u32, trial_count: usize },
+    /// The prompt was not answered before the response timeout
+    TimedOut { trial_index: u32 },
+    /// Every prompt is answered, with the path of the saved result or why it could not be saved
+    Finished(anyhow::Result<PathBuf>),
+    /// The experiment was exited early, with the path of the partial result if it was saved,
+    /// or why the result could not be saved or the session discarded
+    Exited(anyhow::Result<Option<PathBuf>>),
+}
+
+/// Sends commands to the engine, and waits for them to be processed
+#[derive(Debug, Clone)]
+pub struct EngineHandle(mpsc::Sender<(Command, Responder)>);
+
+impl EngineHandle {
+    /// Send a command, fails if the engine rejects it or is not running
+    pub async fn send(&self, command: Command) -> Result<(), Rejection> {
+        let not_running =
+            || Rejection::WrongState("The experiment engine is not running".to_owned());
+
+        let (responder, reply) = Responder::new();
+        self.0
+            .send((command, responder))
+            .await
+            .map_err(|_| not_running())?;
+
+        reply.await.unwrap_or_else(|_| Err(not_running()))
+    }
+}
+
+pub struct Engine {
+    state: watch::Sender<AppState>,
+    commands: mpsc::Receiver<(Command, Responder)>,
+    /// When the gap between prompts ends, and the session it belongs to
+    gap: Option<(Instant, String)>,
+    /// Whether the experiment in progress is saved to the sessions folder, off for tests
+    checkpoints: bool,
+    /// Session, number of answers, corrections and pauses in the last checkpoint, swaps need no new checkpoint
+    last_checkpoint: Option<(String, u32, usize, usize)>,
+}
+
+impl Engine {
+    /// An engine for the state, and the handle to send commands to it with
+    pub fn new(state: watch::Sender<AppState>) -> (Self, EngineHandle) {
+        let (command_sender, command_receiver) = mpsc::channel(100);
+
+        let engine = Self {
+            state,
+            commands: command_receiver,
+            gap: None,
+            checkpoints: false,
+            last_checkpoint: None,
+        };
+
+        (engine, EngineHandle(command_sender))
+    }
+
+    /// Checkpoint the experiment in progress, so it can be resumed after a crash
+    pub fn with_checkpoints(self) -> Self {
+        Self {
+            checkpoints: true,
+            ..self
+        }
+    }
+
+    /// Process commands and headset events in the order they arrive, and time out unanswered prompts
+    pub async fn run(
+        mut self,
+        mut unity_events: mpsc::Receiver<UnityEvent>,
+        on_event: impl Fn(EngineEvent),
+    ) {
+        let mut state_changes = self.state.subscribe();
+
+        loop {
+            let gap_ends_at = self.gap.as_ref().map(|(ends_at, _)| *ends_at);
+            let gap = async move {
+                match gap_ends_at {
+                    Some(ends_at) => sleep_until(ends_at).await,
+                    None => pending().await,
+                }
+            };
+
+            let deadline = self.response_deadline();
+            let deadline_prompt = deadline.as_ref().map(|(_, prompt_id)| prompt_id.clone());
+            let timeout = async move {
+                match deadline {
+                    Some((deadline, _)) => sleep_until(deadline).await,
+                    None => pending().await,
+                }
+            };
+
+            select! {
+                Some((command, responder)) = self.commands.recv() => {
+                    self.handle_command(command, responder, &on_event).await;
+                }
+                Some(event) = unity_events.recv() => {
+                    self.handle_unity_event(event, &on_event).await;
+                }
+                _ = gap => self.end_gap(),
+                _ = timeout => {
+                    if let Some(prompt_id) = deadline_prompt {
+                        self.time_out(prompt_id, &on_event).await;
+                    }
+                }
+                // The deadline is worked out again when the state changes, also from outside the engine,
+                // e.g. when an experiment is started or the display of a prompt is acknowledged
+                Ok(()) = state_changes.changed() => {}
+                else => break,
+            }
+
+            self.checkpoint().await;
+        }
+    }
+
+    /// Save the experiment in progress when it changed, so it can be resumed after a crash.
+    /// Written by the engine, so a checkpoint can not be written after the session is saved or discarded
+    async fn checkpoint(&mut self) {
+        if !self.checkpoints {
+            return;
+        }
+
+        let Some(experiment_state) = self.state.borrow().clone().try_as_experiment() else {
+            return;
+        };
+
+        // A finished session is saved as a result, which removes its checkpoint
+        if experiment_state.is_done() {
+            return;
+        }
+
+        let checkpoint = (
+            experiment_state.session_key(),
+            experiment_state.current_index,
+            experiment_state.result_shared().corrections.len(),
+            experiment_state.result_shared().pauses.len(),
+        );
+
+        if self.last_checkpoint.as_ref() == Some(&checkpoint) {
+            return;
+        }
+
+        match experiment_state.checkpoint().await {
+            Ok(()) => self.last_checkpoint = Some(checkpoint),
+            Err(error) => eprintln!("Could not checkpoint the experiment: {error:#}"),
+        }
+    }
+
+    async fn handle_command(
+        &mut self,
+        command: Command,
+        responder: Responder,
+        on_event: &impl Fn(EngineEvent),
+    ) {
+        let result = match command {
+            Command::Start(experiment_state) => self.begin(*experiment_state),
+            Command::ResumeSession(mut experiment_state) => {
+                experiment_state.resume_session();
+                self.begin(*experiment_state)
+            }
+            Command::Exit {
+                save_partial,
+                exit_reason,
+            } => {
+                self.exit(save_partial, exit_reason, on_event).await;
+                Ok(())
+            }
+            Command::Answer(answer) => {
+                let result = self.record_answer(|experiment_state| experiment_state.answer(answer));
+
+                self.respond_and_finish(result, responder, on_event).await;
+                return;
+            }
+            Command::SwapPreset => self.modify_experiment(ExperimentState::swap_preset),
+            Command::Pause => self.modify_experiment(ExperimentState::pause),
+            Command::Resume => self.resume(),
+            Command::UndoAnswer => self.modify_experiment(ExperimentState::undo_answer),
+            Command::RepeatLater => self.modify_experiment(ExperimentState::repeat_later),
+        };
+
+        responder.respond(result);
+    }
+
+    async fn handle_unity_event(&mut self, event: UnityEvent, on_event: &impl Fn(EngineEvent)) {
+        match event {
+            UnityEvent::SwapPreset(prompt, responder) => {
+                let result = self.modify_experiment(|experiment_state| {
+                    experiment_state.check_prompt(&prompt)?;
+                    experiment_state.swap_preset()
+                });
+
+                if let Err(rejection) = &result {
+                    eprintln!("Rejected swap: {rejection}");
+                }
+
+                responder.respond(result);
+            }
+
+            UnityEvent::Answer(prompt_answer, responder) => {
+                // Stale or duplicate answers are rejected, and do not advance the experiment
+                let result = self.record_answer(|experiment_state| {
+                    experiment_state.check_prompt(&prompt_answer.prompt)?;
+                    experiment_state.answer(prompt_answer.answer)
+                });
+
+                if let Err(rejection) = &result {
+                    eprintln!("Error answering experiment: {rejection}");
+                }
+
+                self.respond_and_finish(result, responder, on_event).await;
+            }
+
+            UnityEvent::Displayed(displayed, responder) => {
+                let result = self.modify_experiment(|experiment_state| {
+                    experiment_state.check_prompt(&displayed.prompt)?;
+                    experiment_state.acknowledge_display(displayed.displayed_at);
+
+                    Ok(())
+                });
+
+                if let Err(rejection) = &result {
+                    eprintln!("Rejected display acknowledgement: {rejection}");
+                }
+
+                responder.respond(result);
+            }
+
+            UnityEvent::Connected(client) => {
+                println!("Client {} connected: {:?}", client.id, client.identity)
+            }
+            UnityEvent::Disconnected(client_id) => println!("Client {client_id} disconnected"),
+        }
+    }
+
+    fn modify_experiment<T>(
+        &self,
+        modify: impl FnOnce(&mut ExperimentState) -> Result<T, Rejection>,
+    ) -> Result<T, Rejection> {
+        self.state.send_modify_with(|state| {
+            let experiment_state = state
+                .try_as_experiment_mut()
+                .ok_or_else(|| Rejection::WrongState("Not in experiment mode".to_owned()))?;
+
+            modify(experiment_state)
+        })
+    }
+
+    /// Record an answer and start the gap before the next prompt, returns true if the experiment is done
+    fn record_answer(
+        &mut self,
+        answer: impl FnOnce(&mut ExperimentState) -> Result<bool, Rejection>,
+    ) -> Result<bool, Rejection> {
+        let (is_done, gap) = self.modify_experiment(|experiment_state| {
+            let is_done = answer(experiment_state)?;
+            let gap_duration = experiment_state.start_gap();
+
+            let gap = (!is_done && !gap_duration.is_zero()).then(|| {
+                (
+                    Instant::now() + gap_duration,
+                    experiment_state.session_key(),
+                )
+            });
+
+            Ok((is_done, gap))
+        })?;
+
+        self.gap = gap;
+
+        Ok(is_done)
+    }
+
+    /// Reply to whoever answered before saving, they should not wait for it, then finish if done
+    async fn respond_and_finish(
+        &mut self,
+        result: Result<bool, Rejection>,
+        responder: Responder,
+        on_event: &impl Fn(EngineEvent),
+    ) {
+        let is_done = match result {
+            Ok(is_done) => {
+                responder.respond(Ok(()));
+                is_done
+            }
+            Err(rejection) => {
+                responder.respond(Err(rejection));
+                return;
+            }
+        };
+
+        self.advance(is_done, on_event).await;
+    }
+
+    /// Report the progress after an answer or a timeout, then finish if done
+    async fn advance(&mut self, is_done: bool, on_event: &impl Fn(EngineEvent)) {
+        if let AppState::Experiment(experiment_state) = &*self.state.borrow() {
+            on_event(EngineEvent::Answered {
+                answered: experiment_state.current_index,
+                trial_count: experiment_state.trial_count(),
+            });
+        }
+
+        if is_done {
+            self.finish(on_event).await;
+        }
+    }
+
+    /// When the current prompt times out, and the prompt, None when no deadline is running
+    fn response_deadline(&self) -> Option<(Instant, PromptId)> {
+        let AppState::Experiment(experiment_state) = &*self.state.borrow() else {
+            return None;
+        };
+
+        let time_until_timeout = experiment_state.time_until_timeout()?;
+
+        Some((
+            Instant::now() + time_until_timeout,
+            experiment_state.prompt_id.clone(),
+        ))
+    }
+
+    /// Record a timeout for the prompt and go on, unless it was answered or left in the meantime
+    async fn time_out(&mut self, prompt_id: PromptId, on_event: &impl Fn(EngineEvent)) {
+        let mut trial_index = 0;
+
+        let result = self.record_answer(|experiment_state| {
+            if experiment_state.prompt_id != prompt_id {
+                return Err(Rejection::WrongState(
+                    "The prompt changed before it timed out".to_owned(),
+                ));
+            }
+
+            trial_index = experiment_state.current_index;
+            experiment_state.time_out()
+        });
+
+        if let Ok(is_done) = result {
+            on_event(EngineEvent::TimedOut { trial_index });
+            self.advance(is_done, on_event).await;
+        }
+    }
+
+    /// Show the next prompt, unless the experiment was left during the gap.
+    /// When the experiment is paused, the next prompt is shown once it is resumed instead
+    fn end_gap(&mut self) {
+        let Some((_, session_key)) = self.gap.take() else {
+            return;
+        };
+
+        self.state.send_modify(|state| {
+            if let Some(experiment_state) = state.try_as_experiment_mut() {
+                if experiment_state.session_key() == session_key && !experiment_state.is_paused() {
+                    experiment_state.set_is_idle(false);
+                }
+            }
+        });
+    }
+
+    /// Resume after a pause. A pause during the gap between prompts ends the gap, so the next prompt is shown
+    fn resume(&mut self) -> Result<(), Rejection> {
+        self.modify_experiment(|experiment_state| {
+            experiment_state.resume()?;
+
+            if experiment_state.is_idle() {
+                experiment_state.set_is_idle(false);
+            }
+
+            Ok(())
+        })?;
+
+        self.gap = None;
+
+        Ok(())
+    }
+
+    /// Show a new or resumed experiment, unless another one is in progress.
+    /// Replacing it would leave its checkpoint behind, to be offered for resuming
+    fn begin(&mut self, experiment_state: ExperimentState) -> Result<(), Rejection> {
+        self.state.send_modify_with(|state| {
+            if state.try_as_experiment_ref().is_some() {
+                return Err(Rejection::WrongState(
+                    "Another experiment is in progress".to_owned(),
+                ));
+            }
+
+            *state = AppState::Experiment(experiment_state);
+
+            Ok(())
+        })?;
+
+        self.gap = None;
+
+        Ok(())
+    }
+
+    /// Leave the experiment early, then save the answers so far or discard the session
+    async fn exit(
+        &mut self,
+        save_partial: bool,
+        exit_reason: Option<String>,
+        on_event: &impl Fn(EngineEvent),
+    ) {
+        self.gap = None;
+
+        let Some(current_experiment) = self.state.borrow().clone().try_as_experiment() else {
+            return;
+        };
+
+        let Some(experiment_state) = self
+            .state
+            .send_replace(current_experiment.state_after().await)
+            .try_as_experiment()
+        else {
+            return;
+        };
+
+        let result = match save_partial {
+            true => experiment_state.abort(exit_reason).await.map(Some),
+            // The session is discarded, so it should not be offered for resuming
+            false => {
+                storage::delete_file_if_exists(&experiment_state.session_key(), Folder::Sessions)
+                    .await
+                    .map(|()| None)
+            }
+        };
+
+        on_event(EngineEvent::Exited(result));
+    }
+
+    /// Save the result of the finished experiment, and show what was chosen for after it
+    async fn finish(&mut self, on_event: &impl Fn(EngineEvent)) {
+        self.gap = None;
+
+        let Some(current_experiment) = self.state.borrow().clone().try_as_experiment() else {
+            return;
+        };
+
+        if let Some(experiment_state) = self
+            .state
+            .send_replace(current_experiment.state_after().await)
+            .try_as_experiment()
+        {
+            on_event(EngineEvent::Finished(
+                experiment_state.finish_experiment().await,
+            ));
+        }
+    }
+}
@@ -2,190 +2,238 @@
 pub mod api;
 pub mod consts;
 pub mod data;
+pub mod engine;
 pub mod state;
 pub mod storage;
 
 use std::path::PathBuf;
-use std::sync::Arc;
-use std::time::Duration;
 
-use api::events::{ConnectionEvent, ResultSavedEvent, StateEvent};
+use anyhow::Context;
+use api::events::{ConnectedClientsEvent, ResultSavedEvent, SecretEvent, StateEvent};
 use api::{commands, events};
 use consts::HTTP_SERVER_PORT;
+use engine::{Engine, EngineEvent, EngineHandle};
 
 use futures::StreamExt;
-use neuroviz::extensions::{MpscReceiverExt, WatchReceiverExt, WatchSenderExt};
-use neuroviz::http_server::{HttpServer, UnityEvent};
-use neuroviz::parameters::ParameterValues;
+use neuroviz::auth::Secret;
+use neuroviz::clients::ClientRegistry;
+use neuroviz::discovery::{Advertiser, ServiceAdvertisement};
+use neuroviz::extensions::WatchReceiverExt;
+use neuroviz::http_server::{HttpServer, UnityEvent, UnityStateSender};
+use neuroviz::parameters::ParameterSchema;
+use neuroviz::tls::TlsCertificate;
 use rand::distr::Alphanumeric;
 use rand::Rng;
 use specta_typescript::formatter::prettier;
 use specta_typescript::Typescript;
-use state::{AppData, AppState};
+use state::{AppData, AppState, LiveViewState};
 use tauri::{AppHandle, Manager};
 use tauri_specta::{collect_commands, collect_events, ErrorHandlingMode, Event};
 use tokio::join;
 use tokio::net::TcpListener;
 use tokio::sync::{mpsc, watch};
-use tokio::time::sleep;
 
 /// Runs the HTTP server, and also transforms the app state into a Unity state
 pub async fn http_server_task(
+    name: String,
     listener: TcpListener,
     app_state_receiver: watch::Receiver<AppState>,
     unity_event_sender: mpsc::Sender<UnityEvent>,
-    secret: Option<String>,
+    clients: ClientRegistry,
+    secret: Option<Secret>,
+    certificate: Option<TlsCertificate>,
 ) {
     // Channel for unity state
-    let (unity_state_sender, unity_state_receiver) =
-        watch::channel(app_state_receiver.borrow().clone().into());
+    let unity_state_sender = UnityStateSender::new(app_state_receiver.borrow().clone().into());
 
     let http_server = HttpServer {
-        state: unity_state_receiver,
+        name,
+        state: unity_state_sender.subscribe(),
         event_sender: unity_event_sender,
-        secret: secret.map(Arc::new),
+        secret,
+        clients,
     };
 
     // Task to update the unity state based on app state changes
     let update_unity_state = async move {
         let mut app_state_stream = app_state_receiver.into_stream();
 
-        // Whenever app_state changes, convert it to UnityState and send it the channel
+        // Whenever app_state changes, convert it to UnityState and send it the channel.
+        // Changes that do not affect Unity, like display acknowledgements, are not sent again
         while let Some(state) = app_state_stream.next().await {
-            unity_state_sender.send(state.into()).unwrap();
+            unity_state_sender.send_if_changed(state.into());
         }
     };
 
-    let app = http_server.app();
-    let http_server = axum::serve(listener, app);
+    let http_server = async {
+        match certificate {
+            Some(certificate) => http_server.serve_tls(listener, &certificate).await,
+            None => http_server.serve(listener).await,
+        }
+    };
 
     let (axum_result, _) = join!(http_server, update_unity_state);
     axum_result.unwrap();
 }
 
-/// Task to handle Unity events, will receive events from Unity and update the app state accordingly
-pub async fn handle_unity_events_task(
-    app_handle: AppHandle,
-    connected_clients_sender: watch::Sender<usize>,
-    app_state_sender: watch::Sender<AppState>,
-    unity_event_receiver: mpsc::Receiver<UnityEvent>,
-) {
-    let mut stream = unity_event_receiver.into_stream();
-
-    while let Some(event) = stream.next().await {
-        // let mut app_state = app_state.lock_mut();
-
-        match event {
-            UnityEvent::SwapPreset => {
-                app_state_sender.send_modify(|state| {
-                    let choice_experiment = state
-                        .try_as_experiment_mut()
-                        .and_then(|experiment| experiment.try_as_choice_mut());
+/// Generate random secret with 32 characters
+pub fn generate_secret() -> String {
+    let secret = rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect::<String>();
 
-                    if let Some(choice) = choice_experiment {
-                        choice.swap_current_preset();
-                    }
-                });
-            }
+    secret
+}
 
-            UnityEvent::Answer(experiment_answer) => {
-                let is_done = app_state_sender.send_modify_with(|state| {
-                    let is_done = match state.answer_experiment(experiment_answer) {
-                        Ok(is_done) => is_done,
-                        Err(error) => {
-                            eprintln!("Error answering experiment: {}", error);
-                            false
-                        }
-                    };
-
-                    if let Some(experiment_state) = state.try_as_experiment_mut() {
-                        experiment_state.set_is_idle(true);
-                    }
+/// The headset server, set up by the server settings in the data folder
+pub struct Server {
+    pub app_data: AppData,
+    /// Sends commands to the experiment engine, which runs along with the server
+    pub engine: EngineHandle,
+    engine_runner: Engine,
+    /// Name shown to headsets, both when discovering and in `/info`
+    pub name: String,
+    listener: TcpListener,
+    /// Kept alive for as long as the server runs, the advertisement is withdrawn when dropped
+    _advertiser: Option<Advertiser>,
+}
 
-                    is_done
-                });
+impl Server {
+    /// Load the settings and certificate, listen on the headset port and advertise the server
+    pub async fn start(state: AppState) -> anyhow::Result<Self> {
+        let secret = generate_secret();
 
-                // Sleep for a second while idle
-                if !is_done {
-                    sleep(Duration::from_secs(1)).await;
+        let settings = storage::read_server_settings().await?;
 
-                    app_state_sender.send_modify(|state| {
-                        if let Some(experiment_state) = state.try_as_experiment_mut() {
-                            experiment_state.set_is_idle(false);
-                        }
-                    });
-                }
+        let certificate = match settings.tls {
+            true => {
+                let certificate = storage::load_tls_certificate().await?;
+                println!("TLS certificate fingerprint: {}", certificate.fingerprint());
+                Some(certificate)
+            }
+            false => None,
+        };
 
-                // If the experiment is done, finish it and emit the result saved event
-                if is_done {
-                    if let Some(experiment_state) = app_state_sender
-                        .send_replace(AppState::Idle)
-                        .try_as_experiment()
-                    {
-                        if let Ok(result_file_path) = experiment_state.finish_experiment().await {
-                            let _ = ResultSavedEvent { result_file_path }.emit(&app_handle);
-                        }
+        let name = settings
+            .name
+            .unwrap_or_else(ServiceAdvertisement::default_instance_name);
+
+        let advertiser = match settings.advertise {
+            true => {
+                let advertisement = ServiceAdvertisement {
+                    instance_name: name.clone(),
+                    port: HTTP_SERVER_PORT,
+                    requires_auth: true,
+                    tls: certificate.is_some(),
+                };
+
+                match Advertiser::start(advertisement) {
+                    Ok(advertiser) => {
+                        println!("Advertising as {:?}", advertiser.instance_name());
+                        Some(advertiser)
+                    }
+                    Err(error) => {
+                        eprintln!("Could not advertise the server on the network: {error}");
+                        None
                     }
                 }
             }
+            false => None,
+        };
 
-            UnityEvent::Connected => connected_clients_sender.send_modify(|count| *count += 1),
-            UnityEvent::Disconnected => connected_clients_sender.send_modify(|count| {
-                if *count > 0 {
-                    *count -= 1;
-                }
-            }),
+        let app_data = AppData {
+            certificate,
+            service_name: advertiser
+                .as_ref()
+                .map(|advertiser| advertiser.instance_name().to_owned()),
+            ..AppData::new(state, secret)
         };
-    }
-}
 
-/// Generate random secret with 32 characters
-pub fn generate_secret() -> String {
-    let secret = rand::rng()
-        .sample_iter(&Alphanumeric)
-        .take(32)
-        .map(char::from)
-        .collect::<String>();
+        // Create a TCP listener for the HTTP server
+        let addr = format!("0.0.0.0:{HTTP_SERVER_PORT}");
+        let listener = TcpListener::bind(&addr)
+            .await
+            .with_context(|| format!("Could not listen on {addr}"))?;
+        let scheme = if app_data.certificate.is_some() {
+            "https"
+        } else {
+            "http"
+        };
+        println!("HTTP server listening on {scheme}://localhost:{HTTP_SERVER_PORT}");
+
+        let (engine_runner, engine) = Engine::new(app_data.state.clone());
+        let engine_runner = engine_runner.with_checkpoints();
+
+        Ok(Self {
+            app_data,
+            engine,
+            engine_runner,
+            name,
+            listener,
+            _advertiser: advertiser,
+        })
+    }
 
-    secret
+    /// Serve the headsets and run the experiment engine, calling `on_event` with what happens in it
+    pub async fn run(self, on_event: impl Fn(EngineEvent)) {
+        let app_data = self.app_data;
+
+        // Channel for events from Unity
+        let (unity_event_sender, unity_event_receiver) = mpsc::channel(100);
+
+        // Task that runs http server
+        let http_server = http_server_task(
+            self.name,
+            self.listener,
+            app_data.state.subscribe(),
+            unity_event_sender,
+            app_data.clients.clone(),
+            Some(app_data.secret.clone()),
+            app_data.certificate.clone(),
+        );
+
+        // Task to update the app state based on Unity events and commands from the desktop,
+        // which also checkpoints the experiment in progress so it survives a crash
+        let run_engine = self.engine_runner.run(unity_event_receiver, on_event);
+
+        join!(http_server, run_engine);
+    }
 }
 
 async fn setup(app: AppHandle) {
     // Initialize app state
-    let secret = generate_secret();
-    println!("Secret: {}", secret);
-
-    let app_data = AppData::new(
-        AppState::LiveView(ParameterValues::default()),
-        secret.clone(),
-    );
+    let server = Server::start(AppState::LiveView(LiveViewState::default()))
+        .await
+        .unwrap();
+    let app_data = server.app_data.clone();
     app.manage(app_data.clone());
+    app.manage(server.engine.clone());
 
-    // Channel for events from Unity
-    let (unity_event_sender, unity_event_receiver) = mpsc::channel(100);
-
-    // Create a TCP listener for the HTTP server
-    let addr = format!("0.0.0.0:{HTTP_SERVER_PORT}");
-    let listener = TcpListener::bind(&addr).await.unwrap();
-    println!("HTTP server listening on http://localhost:{HTTP_SERVER_PORT}");
-
-    // Task that runs http server
-    let http_server = http_server_task(
-        listener,
-        app_data.state.subscribe(),
-        unity_event_sender.clone(),
-        Some(secret),
-    );
+    // Task that runs the http server, and the experiment engine
+    let run_server = {
+        let app = app.clone();
 
-    // Task to update the app state based on Unity events
-    let handle_unity_events = handle_unity_events_task(
-        app.clone(),
-        app_data.connected_clients.clone(),
-        app_data.state.clone(),
-        unity_event_receiver,
-    );
+        server.run(move |event| match event {
+            EngineEvent::Answered { .. } | EngineEvent::TimedOut { .. } => {}
+            EngineEvent::Finished(Ok(result_file_path)) => {
+                let _ = ResultSavedEvent { result_file_path }.emit(&app);
+            }
+            EngineEvent::Finished(Err(error)) => {
+                eprintln!("Could not save the experiment result: {error:#}")
+            }
+            EngineEvent::Exited(Ok(Some(result_file_path))) => {
+                let _ = ResultSavedEvent { result_file_path }.emit(&app);
+            }
+            EngineEvent::Exited(Ok(None)) => {}
+            EngineEvent::Exited(Err(error)) => {
+                eprintln!("Could not save the partial experiment result: {error:#}")
+            }
+        })
+    };
 
-    // Tawsk to emit app state changes to the tauri frontend
+    // Task to emit app state changes to the tauri frontend
     let emit_app_state = {
         let app = app.clone();
 
@@ -193,7 +241,6 @@ async fn setup(app: AppHandle) {
             let mut app_state_stream = app_data.state.subscribe().into_stream();
 
             while let Some(new_state) = app_state_stream.next().await {
-                println!("Emitting new state: {:?}", new_state);
                 StateEvent {
                     state: new_state.clone(),
                 }
@@ -203,24 +250,35 @@ async fn setup(app: AppHandle) {
         }
     };
 
-    let emit_is_connected = async move {
-        let mut connected_clients = app_data.connected_clients.subscribe().into_stream();
+    let emit_connected_clients = {
+        let app = app.clone();
+        let clients = app_data.clients.clone();
 
-        while let Some(connected_clients) = connected_clients.next().await {
-            ConnectionEvent {
-                is_connected: connected_clients > 0,
+        async move {
+            let mut clients_stream = clients.subscribe().into_stream();
+
+            while let Some(clients) = clients_stream.next().await {
+                ConnectedClientsEvent { clients }.emit(&app).unwrap();
             }
-            .emit(&app)
-            .unwrap();
+        }
+    };
+
+    // The connection QR code must be updated when the secret is rotated
+    let emit_secret = async move {
+        let mut secret_receiver = app_data.secret.subscribe();
+
+        while secret_receiver.changed().await.is_ok() {
+            let secret = (**secret_receiver.borrow_and_update()).clone();
+            SecretEvent { secret }.emit(&app).unwrap();
         }
     };
 
     // Run all tasks concurrently
     join!(
-        http_server,
-        handle_unity_events,
+        run_server,
         emit_app_state,
-        emit_is_connected
+        emit_connected_clients,
+        emit_secret
     );
 }
 
@@ -230,10 +288,13 @@ pub fn tauri_commands() -> tauri_specta::Builder {
         .commands(collect_commands![
             // App data
             commands::current_state,
-            commands::is_connected,
+            commands::get_connected_clients,
             commands::show_folder,
             commands::get_ip_address,
             commands::get_secret,
+            commands::rotate_secret,
+            commands::get_certificate_fingerprint,
+            commands::get_service_name,
             commands::get_parameters,
             commands::get_default_parameters,
             // CRUD presets
@@ -254,10 +315,19 @@ pub fn tauri_commands() -> tauri_specta::Builder {
             commands::start_experiment,
             commands::exit_experiment,
             commands::answer_experiment,
-            commands::swap_preset
+            commands::swap_preset,
+            commands::pause_experiment,
+            commands::resume_experiment,
+            commands::undo_answer,
+            commands::repeat_later,
+            // Unfinished experiments
+            commands::get_unfinished_sessions,
+            commands::resume_session,
+            commands::save_incomplete_session
         ])
         .events(collect_events![
-            events::ConnectionEvent,
+            events::ConnectedClientsEvent,
+            events::SecretEvent,
             events::StateEvent,
             events::ResultSavedEvent
         ])
@@ -286,6 +356,12 @@ pub fn run() {
         .setup(move |app| {
             builder.mount_events(app);
 
+            // Load the parameter schema before anything reads or writes parameters
+            let schema = tauri::async_runtime::block_on(storage::read_parameter_schema())?;
+            if let Some(schema) = schema {
+                ParameterSchema::set_global(schema);
+            }
+
             tauri::async_runtime::spawn(setup(app.handle().clone()));
             Ok(())
         })
@@ -1,14 +1,15 @@
 use chrono::Local;
+use neuroviz::http_server::Rejection;
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
 use crate::data::{
     experiment::{ChoiceExperiment, CurrentPreset},
-    experiment_result::{ChoiceExperimentResult, OutcomeChoice},
+    experiment_result::{ChoiceExperimentResult, ChoiceOutcome, OutcomeChoice, TimeoutChoice},
     preset::Preset,
 };
 
-use super::{experiment_state::SharedExperimentState, get_duration_since};
+use super::experiment_state::SharedExperimentState;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
 pub struct ChoiceExperimentState {
@@ -49,7 +50,7 @@ impl ChoiceExperimentState {
         is_done
     }
 
-    pub fn answer(&mut self) -> anyhow::Result<bool> {
+    pub fn answer(&mut self) -> Result<bool, Rejection> {
         if self.is_done() {
             return Ok(true);
         }
@@ -58,13 +59,7 @@ impl ChoiceExperimentState {
 
         let choice = &self.experiment.choices[self.shared.current_index as usize];
 
-        let is_first_prompt = self.shared.current_index == 0;
-        let duration = match is_first_prompt {
-            true => get_duration_since(self.result.shared.time),
-            false => {
-                get_duration_since(self.result.choices[self.shared.current_index as usize - 1].time)
-            }
-        };
+        let (duration, timing) = self.shared.prompt_timing(&self.result.shared.pauses);
 
         let outcome = OutcomeChoice {
             a: choice.a.clone(),
@@ -72,15 +67,88 @@ impl ChoiceExperimentState {
             selected: selected_preset_key,
             time: Local::now(),
             duration,
+            timing: Some(timing),
         };
 
-        self.result.choices.push(outcome);
+        self.result.choices.push(ChoiceOutcome::Answered(outcome));
 
         self.shared.current_index += 1;
         let is_done = self.is_done();
 
         Ok(is_done)
     }
+
+    /// Record that the current choice was not answered in time and go on, returns true if done.
+    /// When requeued, the choice is shown again after the others
+    pub fn time_out(&mut self, requeue: bool) -> bool {
+        if self.is_done() {
+            return true;
+        }
+
+        let (_, timing) = self.shared.prompt_timing(&self.result.shared.pauses);
+        let choice = self.experiment.choices[self.shared.current_index as usize].clone();
+
+        self.result
+            .choices
+            .push(ChoiceOutcome::TimedOut(TimeoutChoice {
+                a: choice.a.clone(),
+                b: choice.b.clone(),
+                timed_out_at: Local::now(),
+                requeued: requeue,
+                timing,
+            }));
+
+        if requeue {
+            self.shared
+                .insert_later(&mut self.experiment.choices, choice, false);
+        }
+
+        self.shared.current_index += 1;
+
+        self.is_done()
+    }
+
+    /// Move the last answer to the undone outcomes and go back to its choice, false if nothing was answered.
+    /// The preset that was shown stays shown, like when moving on to the next choice
+    pub fn undo(&mut self) -> bool {
+        let Some(outcome) = self.result.choices.pop() else {
+            return false;
+        };
+
+        self.shared.current_index -= 1;
+
+        // The choice is shown again now, so not again at the end
+        if let ChoiceOutcome::TimedOut(TimeoutChoice {
+            a,
+            b,
+            requeued: true,
+            ..
+        }) = &outcome
+        {
+            let later = self.shared.current_index as usize + 1;
+
+            if let Some(position) = self.experiment.choices[later..]
+                .iter()
+                .rposition(|choice| choice.a == *a && choice.b == *b)
+            {
+                self.shared
+                    .remove_later(&mut self.experiment.choices, later + position);
+            }
+        }
+
+        self.result.undone.push(outcome);
+
+        true
+    }
+
+    /// Move the current choice to the end of the order, to be shown again after the others
+    pub fn repeat_later(&mut self) {
+        let index = self.shared.current_index as usize;
+        let choice = self.experiment.choices.remove(index);
+
+        self.shared
+            .insert_later(&mut self.experiment.choices, choice, true);
+    }
 }
 
 #[cfg(test)]
@@ -109,27 +177,27 @@ mod tests {
         // Create preset A
         let preset_a = Preset {
             name: "Preset A".to_string(),
-            parameters: ParameterValues {
-                transparency: 0.5,
-                glow: 0.7,
-                smoothness: 0.2,
-                emission: 0.3,
-                light_intensity: 0.5,
-                light_temperature: 0.,
-            },
+            parameters: ParameterValues::from([
+                ("transparency", 0.5),
+                ("glow", 0.7),
+                ("smoothness", 0.2),
+                ("emission", 0.3),
+                ("light_intensity", 0.5),
+                ("light_temperature", 6500.),
+            ]),
         };
 
         // Create preset B
         let preset_b = Preset {
             name: "Preset B".to_string(),
-            parameters: ParameterValues {
-                transparency: 0.2,
-                glow: 0.4,
-                smoothness: 0.6,
-                emission: 0.9,
-                light_intensity: 0.8,
-                light_temperature: 0.,
-            },
+            parameters: ParameterValues::from([
+                ("transparency", 0.2),
+                ("glow", 0.4),
+                ("smoothness", 0.6),
+                ("emission", 0.9),
+                ("light_intensity", 0.8),
+                ("light_temperature", 6500.),
+            ]),
         };
 
         // Add presets to hashmap
@@ -138,11 +206,10 @@ mod tests {
 
         // Create choice experiment
         let experiment = ChoiceExperiment {
-            shared: SharedExperiment {
-                name: "Test Experiment".to_string(),
-                presets,
-            },
+            shared: SharedExperiment::new("Test Experiment".to_string(), presets),
             choices,
+            swap_transition: None,
+            practice_choices: vec![],
         };
 
         // Create shared experiment result
@@ -152,21 +219,28 @@ mod tests {
             observer_id: 1,
             note: "Test note".to_string(),
             presets: experiment.shared.presets.clone(),
+            status: Default::default(),
+            pauses: Vec::new(),
+            corrections: Vec::new(),
+            exit_reason: None,
         };
 
         // Create choice experiment result
         let result = ChoiceExperimentResult {
             shared: shared_result,
             choices: Vec::new(),
+            undone: Vec::new(),
+            practice: Vec::new(),
         };
 
         // Create choice experiment state
         ChoiceExperimentState {
             shared: SharedExperimentState {
-                experiment_key: "test_experiment".to_string(),
-                result_key: "test_result".to_string(),
                 current_index,
-                is_idle: false,
+                ..SharedExperimentState::new(
+                    "test_experiment".to_string(),
+                    "test_result".to_string(),
+                )
             },
             experiment,
             result,
@@ -174,6 +248,11 @@ mod tests {
         }
     }
 
+    /// The answer of an outcome, fails the test on a timeout
+    fn answered(outcome: &ChoiceOutcome) -> &OutcomeChoice {
+        outcome.try_as_answered_ref().unwrap()
+    }
+
     #[test]
     fn test_get_current_preset_key() {
         // Create choices
@@ -217,13 +296,13 @@ mod tests {
         let state = create_test_state(0, CurrentPreset::A, choices.clone());
         let preset = state.get_current_preset();
         assert_eq!(preset.name, "Preset A");
-        assert_eq!(preset.parameters.transparency, 0.5);
+        assert_eq!(preset.parameters.get("transparency"), Some(0.5));
 
         // Test for CurrentPreset::B
         let state = create_test_state(0, CurrentPreset::B, choices);
         let preset = state.get_current_preset();
         assert_eq!(preset.name, "Preset B");
-        assert_eq!(preset.parameters.transparency, 0.2);
+        assert_eq!(preset.parameters.get("transparency"), Some(0.2));
     }
 
     #[test]
@@ -295,9 +374,9 @@ mod tests {
         assert_eq!(state.shared.current_index, 1);
         // Should record outcome
         assert_eq!(state.result.choices.len(), 1);
-        assert_eq!(state.result.choices[0].a, "preset_a");
-        assert_eq!(state.result.choices[0].b, "preset_b");
-        assert_eq!(state.result.choices[0].selected, "preset_a");
+        assert_eq!(answered(&state.result.choices[0]).a, "preset_a");
+        assert_eq!(answered(&state.result.choices[0]).b, "preset_b");
+        assert_eq!(answered(&state.result.choices[0]).selected, "preset_a");
 
         // Test recording an answer for the second choice
         let is_done = state.answer().unwrap();
@@ -308,9 +387,9 @@ mod tests {
         assert_eq!(state.shared.current_index, 2);
         // Should record second outcome
         assert_eq!(state.result.choices.len(), 2);
-        assert_eq!(state.result.choices[1].a, "preset_b");
-        assert_eq!(state.result.choices[1].b, "preset_a");
-        assert_eq!(state.result.choices[1].selected, "preset_b");
+        assert_eq!(answered(&state.result.choices[1]).a, "preset_b");
+        assert_eq!(answered(&state.result.choices[1]).b, "preset_a");
+        assert_eq!(answered(&state.result.choices[1]).selected, "preset_b");
 
         // Test returning when already done
         let is_done = state.answer().unwrap();
@@ -318,4 +397,62 @@ mod tests {
         // Should not add more outcomes
         assert_eq!(state.result.choices.len(), 2);
     }
+
+    #[test]
+    fn test_undo() {
+        let choices = vec![
+            Choice {
+                a: "preset_a".to_string(),
+                b: "preset_b".to_string(),
+            },
+            Choice {
+                a: "preset_b".to_string(),
+                b: "preset_a".to_string(),
+            },
+        ];
+
+        let mut state = create_test_state(0, CurrentPreset::B, choices);
+        state.answer().unwrap();
+        state.swap_current_preset();
+        assert!(state.undo());
+
+        // The choice is shown again, and the undone answer is kept
+        assert_eq!(state.shared.current_index, 0);
+        assert!(state.result.choices.is_empty());
+        assert_eq!(answered(&state.result.undone[0]).selected, "preset_b");
+
+        assert!(!state.undo());
+    }
+
+    #[test]
+    fn test_next_choice_keeps_shown_preset() {
+        let choices = vec![
+            Choice {
+                a: "preset_a".to_string(),
+                b: "preset_b".to_string(),
+            },
+            Choice {
+                a: "preset_b".to_string(),
+                b: "preset_a".to_string(),
+            },
+            Choice {
+                a: "preset_a".to_string(),
+                b: "preset_b".to_string(),
+            },
+        ];
+
+        // Answering, timing out, undoing and repeating later all keep B shown
+        let mut state = create_test_state(0, CurrentPreset::B, choices);
+        state.answer().unwrap();
+        assert_eq!(state.current_preset, CurrentPreset::B);
+
+        state.time_out(false);
+        assert_eq!(state.current_preset, CurrentPreset::B);
+
+        assert!(state.undo());
+        assert_eq!(state.current_preset, CurrentPreset::B);
+
+        state.repeat_later();
+        assert_eq!(state.current_preset, CurrentPreset::B);
+    }
 }
@@ -1,22 +1,35 @@
-use std::{ops::Deref, path::PathBuf};
+use std::{ops::Deref, path::PathBuf, time::Duration};
 
 use crate::{
     data::{
-        experiment::{ChoiceExperiment, CurrentPreset, RatingExperiment},
-        experiment_result::{ChoiceExperimentResult, ExperimentResult, RatingExperimentResult},
+        experiment::{
+            ChoiceExperiment, CurrentPreset, Experiment, RatingExperiment, SharedExperiment,
+            TimeoutAction,
+        },
+        experiment_result::{
+            ChoiceExperimentResult, CompletionStatus, Correction, CorrectionAction,
+            ExperimentResult, Pause, PromptTiming, RatingExperimentResult, SharedExperimentResult,
+        },
         preset::Preset,
+        ValidateParameters, ValidateSettings,
     },
     storage::{self, Folder},
 };
 use anyhow::Context;
-use chrono::prelude::Local;
-use neuroviz::http_server::ExperimentAnswer;
+use chrono::{prelude::Local, DateTime};
+use neuroviz::{
+    http_server::{ExperimentAnswer, PromptId, PromptMismatch, PromptReference, Rejection},
+    parameters::ParameterValues,
+};
+use rand::seq::SliceRandom;
 use serde::{Deserialize, Serialize};
+use slug::slugify;
 use specta::Type;
 use strum::EnumTryAs;
 
 use super::{
-    choice_experiment_state::ChoiceExperimentState, rating_experiment_state::RatingExperimentState,
+    choice_experiment_state::ChoiceExperimentState, get_duration_since,
+    rating_experiment_state::RatingExperimentState, AfterExperiment, AppState, LiveViewState,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
@@ -25,6 +38,129 @@ pub struct SharedExperimentState {
     pub result_key: String,
     pub current_index: u32,
     pub is_idle: bool,
+    /// Identifies the current prompt, answers from the headset must refer to it
+    pub prompt_id: PromptId,
+    /// When the current prompt was sent to the headset
+    pub prompted_at: DateTime<Local>,
+    /// When the headset displayed the current prompt, using the clock of the headset
+    pub displayed_at: Option<DateTime<Local>>,
+    /// When the display acknowledgement for the current prompt was received
+    pub acknowledged_at: Option<DateTime<Local>>,
+    /// When the experiment was paused, None while running
+    #[serde(default)]
+    pub paused_at: Option<DateTime<Local>>,
+    /// When the gap before the current prompt started, None when it was shown without a gap
+    #[serde(default)]
+    pub gap_started_at: Option<DateTime<Local>>,
+    /// Live view the operator set before the experiment started
+    #[serde(default)]
+    pub previous_live_view: Option<LiveViewState>,
+    /// What to show once the experiment is finished or exited
+    #[serde(default)]
+    pub after_experiment: AfterExperiment,
+    /// Number of practice prompts at the start of the order
+    #[serde(default)]
+    pub practice_count: u32,
+}
+
+impl SharedExperimentState {
+    pub fn new(experiment_key: String, result_key: String) -> Self {
+        Self {
+            experiment_key,
+            result_key,
+            current_index: 0,
+            is_idle: false,
+            prompt_id: PromptId::random(),
+            prompted_at: Local::now(),
+            displayed_at: None,
+            acknowledged_at: None,
+            paused_at: None,
+            gap_started_at: None,
+            previous_live_view: None,
+            after_experiment: AfterExperiment::default(),
+            practice_count: 0,
+        }
+    }
+
+    pub fn is_practice(&self) -> bool {
+        self.current_index < self.practice_count
+    }
+
+    /// Insert a trial to be shown again after the others, or after the other practice prompts during
+    /// the practice. `moved` is set when the current trial was just removed to be inserted again
+    pub fn insert_later<T>(&mut self, trials: &mut Vec<T>, trial: T, moved: bool) {
+        if !self.is_practice() {
+            trials.push(trial);
+            return;
+        }
+
+        if moved {
+            trials.insert(self.practice_count as usize - 1, trial);
+        } else {
+            trials.insert(self.practice_count as usize, trial);
+            self.practice_count += 1;
+        }
+    }
+
+    /// Remove a trial after the current one that was inserted with [`Self::insert_later`]
+    pub fn remove_later<T>(&mut self, trials: &mut Vec<T>, index: usize) {
+        trials.remove(index);
+
+        if index < self.practice_count as usize {
+            self.practice_count -= 1;
+        }
+    }
+
+    /// Start timing a new prompt, forgetting any acknowledgement of the previous one
+    pub fn start_prompt(&mut self) {
+        self.prompt_id = PromptId::random();
+        self.prompted_at = Local::now();
+        self.displayed_at = None;
+        self.acknowledged_at = None;
+        self.gap_started_at = None;
+    }
+
+    /// Duration of the current prompt until now, and the timings it is based on.
+    ///
+    /// When the headset has acknowledged the prompt, the duration is measured from when the
+    /// acknowledgement was received. That excludes the latency of sending and rendering the prompt,
+    /// and the latency of the acknowledgement cancels out against the latency of the answer.
+    /// Time spent in the pauses is left out.
+    pub fn prompt_timing(&self, pauses: &[Pause]) -> (f64, PromptTiming) {
+        let duration_since = |then| {
+            let paused = pauses
+                .iter()
+                .map(|pause| pause.seconds_after(then))
+                .sum::<f64>();
+
+            get_duration_since(then) - paused
+        };
+
+        let desktop_duration = duration_since(self.prompted_at);
+
+        let duration = match self.acknowledged_at {
+            Some(acknowledged_at) => duration_since(acknowledged_at),
+            None => desktop_duration,
+        };
+
+        let timing = PromptTiming {
+            prompted_at: self.prompted_at,
+            desktop_duration,
+            displayed_at: self.displayed_at,
+            acknowledged_at: self.acknowledged_at,
+            gap: self.gap_started_at.map(|gap_started_at| {
+                let nanoseconds = self
+                    .prompted_at
+                    .signed_duration_since(gap_started_at)
+                    .num_nanoseconds()
+                    .unwrap_or_default();
+
+                nanoseconds as f64 / 1_000_000_000.0
+            }),
+        };
+
+        (duration, timing)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type, EnumTryAs)]
@@ -45,10 +181,198 @@ impl ExperimentState {
     }
 
     pub fn set_is_idle(&mut self, is_idle: bool) {
+        let shared = self.shared_mut();
+        shared.is_idle = is_idle;
+
+        // Leaving idle shows the next prompt, which keeps when the gap before it started
+        if !is_idle {
+            let gap_started_at = shared.gap_started_at;
+            shared.start_prompt();
+            shared.gap_started_at = gap_started_at;
+        }
+    }
+
+    /// Start the gap after an answer, returns how long to wait before leaving idle with `set_is_idle(false)`.
+    /// Without a gap the next prompt is shown at once, and when done the headset is left idle
+    pub fn start_gap(&mut self) -> Duration {
+        let inter_trial_interval =
+            Duration::try_from_secs_f64(self.experiment_shared().inter_trial_interval)
+                .unwrap_or_default();
+
+        if inter_trial_interval.is_zero() && !self.is_done() {
+            self.shared_mut().start_prompt();
+        } else {
+            self.set_is_idle(true);
+        }
+
+        self.shared_mut().gap_started_at = Some(Local::now());
+
+        inter_trial_interval
+    }
+
+    /// Parameters the headset shows in the gap between prompts, None when it shows idle
+    pub fn mask_parameters(&self) -> Option<ParameterValues> {
+        if self.is_done() {
+            return None;
+        }
+
+        self.experiment_shared().mask_parameters()
+    }
+
+    fn experiment_shared(&self) -> &SharedExperiment {
+        match self {
+            ExperimentState::Rating(state) => &state.experiment.shared,
+            ExperimentState::Choice(state) => &state.experiment.shared,
+        }
+    }
+
+    fn shared_mut(&mut self) -> &mut SharedExperimentState {
         match self {
-            ExperimentState::Rating(state) => state.shared.is_idle = is_idle,
-            ExperimentState::Choice(state) => state.shared.is_idle = is_idle,
+            ExperimentState::Rating(state) => &mut state.shared,
+            ExperimentState::Choice(state) => &mut state.shared,
+        }
+    }
+
+    /// The prompt currently shown to the headset
+    pub fn prompt_reference(&self) -> PromptReference {
+        PromptReference {
+            prompt_id: self.prompt_id.clone(),
+            trial_index: self.current_index,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Pause for a break, the headset shows idle and answers are rejected until resumed
+    pub fn pause(&mut self) -> Result<(), Rejection> {
+        if self.is_paused() {
+            return Err(Rejection::WrongState(
+                "The experiment is already paused".to_owned(),
+            ));
         }
+
+        self.shared_mut().paused_at = Some(Local::now());
+
+        Ok(())
+    }
+
+    /// Resume after a pause, showing the same prompt again and recording the pause in the result
+    pub fn resume(&mut self) -> Result<(), Rejection> {
+        let started_at = self
+            .shared_mut()
+            .paused_at
+            .take()
+            .ok_or_else(|| Rejection::WrongState("The experiment is not paused".to_owned()))?;
+
+        self.result_shared_mut().pauses.push(Pause {
+            started_at,
+            ended_at: Local::now(),
+        });
+
+        Ok(())
+    }
+
+    /// Check that a request from the headset refers to the prompt currently shown
+    pub fn check_prompt(&self, prompt: &PromptReference) -> Result<(), PromptMismatch> {
+        let current = (!self.is_idle && !self.is_paused()).then(|| self.prompt_reference());
+
+        prompt.check(current.as_ref())
+    }
+
+    /// Record that the headset displayed the current prompt, only the first acknowledgement counts
+    pub fn acknowledge_display(&mut self, displayed_at: DateTime<Local>) {
+        let shared = self.shared_mut();
+
+        if shared.is_idle || shared.paused_at.is_some() || shared.acknowledged_at.is_some() {
+            return;
+        }
+
+        shared.displayed_at = Some(displayed_at);
+        shared.acknowledged_at = Some(Local::now());
+    }
+
+    /// Load the experiment from the experiments folder and start it for the observer,
+    /// optionally shuffling the order of the trials
+    pub async fn start(
+        experiment_key: String,
+        result_name: String,
+        observer_id: u32,
+        note: String,
+        randomize: bool,
+    ) -> anyhow::Result<Self> {
+        let result_key = slugify(&result_name);
+
+        let experiment =
+            storage::read_file::<Experiment>(&experiment_key, Folder::Experiments).await?;
+
+        let time = Local::now();
+        let mut rng = rand::rng();
+
+        let practice_count = match &experiment {
+            Experiment::Rating(rating_experiment) => rating_experiment.practice_order.len(),
+            Experiment::Choice(choice_experiment) => choice_experiment.practice_choices.len(),
+        };
+
+        let mut experiment_state = match experiment {
+            Experiment::Rating(mut rating_experiment) => {
+                if randomize {
+                    rating_experiment.order.shuffle(&mut rng);
+                    rating_experiment.practice_order.shuffle(&mut rng);
+                }
+
+                // The practice is shown first, as part of the order
+                rating_experiment
+                    .order
+                    .splice(0..0, rating_experiment.practice_order.clone());
+
+                let experiment_result = RatingExperimentResult::new(
+                    result_name,
+                    time,
+                    observer_id,
+                    note,
+                    &rating_experiment,
+                );
+
+                ExperimentState::new_rating(
+                    experiment_key,
+                    result_key,
+                    rating_experiment,
+                    experiment_result,
+                )
+            }
+
+            Experiment::Choice(mut choice_experiment) => {
+                if randomize {
+                    choice_experiment.choices.shuffle(&mut rng);
+                    choice_experiment.practice_choices.shuffle(&mut rng);
+                }
+
+                choice_experiment
+                    .choices
+                    .splice(0..0, choice_experiment.practice_choices.clone());
+
+                let experiment_result = ChoiceExperimentResult::new(
+                    result_name,
+                    time,
+                    observer_id,
+                    note,
+                    &choice_experiment,
+                );
+
+                ExperimentState::new_choice(
+                    experiment_key,
+                    result_key,
+                    choice_experiment,
+                    experiment_result,
+                )
+            }
+        };
+
+        experiment_state.shared_mut().practice_count = practice_count as u32;
+
+        Ok(experiment_state)
     }
 
     pub fn new_rating(
@@ -58,12 +382,7 @@ impl ExperimentState {
         result: RatingExperimentResult,
     ) -> Self {
         Self::Rating(RatingExperimentState {
-            shared: SharedExperimentState {
-                experiment_key,
-                result_key,
-                current_index: 0,
-                is_idle: false,
-            },
+            shared: SharedExperimentState::new(experiment_key, result_key),
             experiment,
             result,
         })
@@ -76,12 +395,7 @@ impl ExperimentState {
         result: ChoiceExperimentResult,
     ) -> Self {
         Self::Choice(ChoiceExperimentState {
-            shared: SharedExperimentState {
-                experiment_key,
-                result_key,
-                current_index: 0,
-                is_idle: false,
-            },
+            shared: SharedExperimentState::new(experiment_key, result_key),
             current_preset: CurrentPreset::A,
             experiment,
             result,
@@ -108,6 +422,87 @@ impl ExperimentState {
         }
     }
 
+    pub fn result_shared(&self) -> &SharedExperimentResult {
+        match self {
+            ExperimentState::Rating(state) => &state.result.shared,
+            ExperimentState::Choice(state) => &state.result.shared,
+        }
+    }
+
+    fn result_shared_mut(&mut self) -> &mut SharedExperimentResult {
+        match self {
+            ExperimentState::Rating(state) => &mut state.result.shared,
+            ExperimentState::Choice(state) => &mut state.result.shared,
+        }
+    }
+
+    /// Identifies the session in the sessions folder, and stays the same when the session is resumed
+    pub fn session_key(&self) -> String {
+        format!(
+            "{}-{}",
+            self.result_shared().time.format("%Y-%m-%d-%H.%M.%S"),
+            self.result_key
+        )
+    }
+
+    pub fn is_done(&self) -> bool {
+        match self {
+            ExperimentState::Rating(state) => state.is_done(),
+            ExperimentState::Choice(state) => state.is_done(),
+        }
+    }
+
+    /// Continue a session loaded from its checkpoint, showing the first unanswered prompt as a new prompt.
+    /// A session that was paused is resumed, counting the time since as part of the pause
+    pub fn resume_session(&mut self) {
+        if self.is_paused() {
+            let _ = self.resume();
+        }
+
+        if let ExperimentState::Choice(state) = self {
+            state.current_preset = CurrentPreset::A;
+        }
+
+        self.set_is_idle(false);
+    }
+
+    /// Save the session to the sessions folder, replacing the previous checkpoint
+    pub async fn checkpoint(&self) -> anyhow::Result<()> {
+        storage::replace_file(&self.session_key(), self, Folder::Sessions).await?;
+
+        Ok(())
+    }
+
+    /// Number of prompts the observer answered so far, leaving out the practice and timeouts
+    pub fn answered_count(&self) -> usize {
+        let practice_count = self.practice_count as usize;
+
+        match self {
+            ExperimentState::Rating(state) => state
+                .result
+                .ratings
+                .iter()
+                .skip(practice_count)
+                .filter(|outcome| outcome.try_as_answered_ref().is_some())
+                .count(),
+            ExperimentState::Choice(state) => state
+                .result
+                .choices
+                .iter()
+                .skip(practice_count)
+                .filter(|outcome| outcome.try_as_answered_ref().is_some())
+                .count(),
+        }
+    }
+
+    /// Number of prompts in the experiment, the experiment is done when this many are answered
+    pub fn trial_count(&self) -> usize {
+        match self {
+            ExperimentState::Rating(state) => state.experiment.order.len(),
+            ExperimentState::Choice(state) => state.experiment.choices.len(),
+        }
+    }
+
     pub fn get_current_preset(&self) -> Preset {
         match self {
             ExperimentState::Rating(state) => state.get_current_preset(),
@@ -115,19 +510,138 @@ impl ExperimentState {
         }
     }
 
-    pub fn answer(&mut self, experiment_answer: ExperimentAnswer) -> anyhow::Result<bool> {
+    /// Swap between A and B in a choice experiment
+    pub fn swap_preset(&mut self) -> Result<(), Rejection> {
+        if self.is_paused() {
+            return Err(Rejection::WrongState("The experiment is paused".to_owned()));
+        }
+
+        let Some(choice) = self.try_as_choice_mut() else {
+            return Err(Rejection::WrongState(
+                "Only choice experiments can swap presets".to_owned(),
+            ));
+        };
+
+        choice.swap_current_preset();
+
+        Ok(())
+    }
+
+    /// Undo the last answer and show its prompt again as a new prompt.
+    /// The undone answer is kept in the result, along with the correction
+    pub fn undo_answer(&mut self) -> Result<(), Rejection> {
+        let is_undone = match self {
+            ExperimentState::Rating(state) => state.undo(),
+            ExperimentState::Choice(state) => state.undo(),
+        };
+
+        if !is_undone {
+            return Err(Rejection::WrongState("No answer to undo yet".to_owned()));
+        }
+
+        self.record_correction(CorrectionAction::Undo);
+        self.shared_mut().start_prompt();
+
+        Ok(())
+    }
+
+    /// Skip the current prompt for now and show it again at the end, e.g. when the observer was distracted
+    pub fn repeat_later(&mut self) -> Result<(), Rejection> {
+        if self.is_done() {
+            return Err(Rejection::WrongState("Every prompt is answered".to_owned()));
+        }
+
+        self.record_correction(CorrectionAction::Repeat);
+
+        match self {
+            ExperimentState::Rating(state) => state.repeat_later(),
+            ExperimentState::Choice(state) => state.repeat_later(),
+        }
+
+        self.shared_mut().start_prompt();
+
+        Ok(())
+    }
+
+    /// Seconds the observer has to answer the prompt, None when the experiment has no deadline
+    pub fn response_timeout(&self) -> Option<f64> {
+        self.experiment_shared().response_timeout
+    }
+
+    /// Time left to answer the current prompt, None when no deadline is running.
+    /// The deadline counts from the display acknowledgement, or from sending the prompt without one,
+    /// and starts over when the experiment is resumed after a pause
+    pub fn time_until_timeout(&self) -> Option<Duration> {
+        let response_timeout = self.response_timeout()?;
+
+        if self.is_idle() || self.is_paused() || self.is_done() {
+            return None;
+        }
+
+        let shown_at = self.acknowledged_at.unwrap_or(self.prompted_at);
+        let since = match self.result_shared().pauses.last() {
+            Some(pause) => shown_at.max(pause.ended_at),
+            None => shown_at,
+        };
+
+        let remaining = response_timeout - get_duration_since(since);
+
+        Some(Duration::try_from_secs_f64(remaining).unwrap_or_default())
+    }
+
+    /// Record that the current prompt was not answered in time, and skip or requeue it as the experiment says.
+    /// Returns true if the experiment is done
+    pub fn time_out(&mut self) -> Result<bool, Rejection> {
+        if self.time_until_timeout().is_none() {
+            return Err(Rejection::WrongState(
+                "No deadline is running for the prompt".to_owned(),
+            ));
+        }
+
+        let requeue = self.experiment_shared().on_timeout == TimeoutAction::Requeue;
+
+        let is_done = match self {
+            ExperimentState::Rating(state) => state.time_out(requeue),
+            ExperimentState::Choice(state) => state.time_out(requeue),
+        };
+
+        Ok(is_done)
+    }
+
+    fn record_correction(&mut self, action: CorrectionAction) {
+        let correction = Correction {
+            action,
+            time: Local::now(),
+            index: self.current_index,
+        };
+
+        self.result_shared_mut().corrections.push(correction);
+    }
+
+    /// Answer the current prompt, fails if the answer does not match the kind of experiment
+    pub fn answer(&mut self, experiment_answer: ExperimentAnswer) -> Result<bool, Rejection> {
+        if self.is_paused() {
+            return Err(Rejection::WrongState("The experiment is paused".to_owned()));
+        }
+
+        experiment_answer.validate()?;
+
         let is_done = match experiment_answer {
             ExperimentAnswer::Choice => {
-                let choice_state = self
-                    .try_as_choice_mut()
-                    .context("Failed to get choice state")?;
+                let choice_state = self.try_as_choice_mut().ok_or_else(|| {
+                    Rejection::WrongState(
+                        "A choice was answered during a rating experiment".to_owned(),
+                    )
+                })?;
 
                 choice_state.answer()?
             }
             ExperimentAnswer::Rating { value } => {
-                let rating_state = self
-                    .try_as_rating_mut()
-                    .context("Failed to get rating state")?;
+                let rating_state = self.try_as_rating_mut().ok_or_else(|| {
+                    Rejection::WrongState(
+                        "A rating was answered during a choice experiment".to_owned(),
+                    )
+                })?;
 
                 rating_state.answer(value)?
             }
@@ -141,6 +655,68 @@ impl ExperimentState {
         // Experiment is over, save the result
         println!("Experiment is done, saving result");
 
+        self.save_result(CompletionStatus::Complete).await
+    }
+
+    /// Choose what to show once the experiment is over, fails if the preset does not exist
+    pub async fn set_after_experiment(
+        &mut self,
+        after_experiment: AfterExperiment,
+        previous_live_view: Option<LiveViewState>,
+    ) -> anyhow::Result<()> {
+        if let AfterExperiment::Preset { preset } = &after_experiment {
+            storage::read_file::<Preset>(preset, Folder::Presets)
+                .await
+                .with_context(|| format!("Could not read the preset `{preset}`"))?;
+        }
+
+        let shared = self.shared_mut();
+        shared.after_experiment = after_experiment;
+        shared.previous_live_view = previous_live_view;
+
+        Ok(())
+    }
+
+    /// The state to show once the experiment is finished or exited.
+    /// Falls back to the default live view when the previous one or the preset is not available
+    pub async fn state_after(&self) -> AppState {
+        let live_view = match &self.after_experiment {
+            AfterExperiment::Idle => return AppState::Idle,
+            AfterExperiment::PreviousLiveView => self.previous_live_view.clone(),
+            AfterExperiment::Preset { preset } => {
+                match storage::read_file::<Preset>(preset, Folder::Presets).await {
+                    Ok(preset) => Some(LiveViewState {
+                        parameters: preset.parameters,
+                        transition: None,
+                    }),
+                    Err(error) => {
+                        eprintln!(
+                            "Could not show the preset `{preset}` after the experiment: {error:#}"
+                        );
+                        None
+                    }
+                }
+            }
+        };
+
+        AppState::LiveView(live_view.unwrap_or_default())
+    }
+
+    /// Save the answers so far as an aborted result, when the operator exits before every prompt is answered
+    pub async fn abort(mut self, exit_reason: Option<String>) -> anyhow::Result<PathBuf> {
+        // A pause that is still going ends with the experiment
+        if self.is_paused() {
+            let _ = self.resume();
+        }
+
+        self.result_shared_mut().exit_reason = exit_reason;
+
+        self.save_result(CompletionStatus::Aborted).await
+    }
+
+    /// Save the result with the status, and remove the checkpoint of the session.
+    /// Returns the file path of the result
+    pub async fn save_result(self, status: CompletionStatus) -> anyhow::Result<PathBuf> {
         let result_name = format!(
             "{}-{}",
             Local::now().format("%Y-%m-%d-%H.%M.%S"),
@@ -148,19 +724,296 @@ impl ExperimentState {
         );
 
         let experiment_key = self.experiment_key.clone();
+        let session_key = self.session_key();
+
+        // The practice answers come first, and are kept apart from the others or dropped
+        let practice_count = self.practice_count as usize;
+        let keep_practice = !self.experiment_shared().drop_practice;
 
-        let result = match self {
+        let mut result = match self {
             ExperimentState::Rating(rating_experiment_state) => {
-                ExperimentResult::Rating(rating_experiment_state.result)
+                let mut result = rating_experiment_state.result;
+                let practice_count = practice_count.min(result.ratings.len());
+                let practice = result.ratings.drain(..practice_count).collect();
+
+                if keep_practice {
+                    result.practice = practice;
+                }
+
+                ExperimentResult::Rating(result)
             }
             ExperimentState::Choice(choice_experiment_state) => {
-                ExperimentResult::Choice(choice_experiment_state.result)
+                let mut result = choice_experiment_state.result;
+                let practice_count = practice_count.min(result.choices.len());
+                let practice = result.choices.drain(..practice_count).collect();
+
+                if keep_practice {
+                    result.practice = practice;
+                }
+
+                ExperimentResult::Choice(result)
             }
         };
+        result.shared_mut().status = status;
 
         let file_path =
             storage::create_file(&result_name, &result, Folder::Results { experiment_key }).await?;
 
+        // The checkpoint is only removed once the result is safely saved
+        storage::delete_file_if_exists(&session_key, Folder::Sessions).await?;
+
         Ok(file_path)
     }
 }
+
+impl ValidateParameters for ExperimentState {
+    fn validate_parameters(&mut self) -> anyhow::Result<()> {
+        match self {
+            ExperimentState::Rating(state) => state.experiment.shared.validate_parameters(),
+            ExperimentState::Choice(state) => state.experiment.shared.validate_parameters(),
+        }
+    }
+}
+
+impl ValidateSettings for ExperimentState {
+    fn validate_settings(&self) -> anyhow::Result<()> {
+        match self {
+            ExperimentState::Rating(state) => state.experiment.shared.validate_settings(),
+            ExperimentState::Choice(state) => state.experiment.validate_settings(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::data::experiment::Mask;
+    use chrono::TimeDelta;
+    use neuroviz::{http_server::UnityState, transition::Transition};
+    use std::collections::HashMap;
+
+    /// A rating experiment of three presets, with the shared experiment settings changed by `configure`
+    fn rating_state(configure: impl FnOnce(&mut SharedExperiment)) -> ExperimentState {
+        let presets = ["preset1", "preset2", "preset3"];
+
+        let mut experiment = RatingExperiment::new(
+            "Test Experiment".to_owned(),
+            HashMap::from_iter(presets.map(|key| {
+                let preset = Preset {
+                    name: key.to_owned(),
+                    parameters: ParameterValues::from([("glow", 0.5)]),
+                };
+
+                (key.to_owned(), preset)
+            })),
+            presets.map(str::to_owned).to_vec(),
+        );
+        configure(&mut experiment.shared);
+
+        let result = RatingExperimentResult::new(
+            "Test Result".to_owned(),
+            Local::now(),
+            1,
+            "Test note".to_owned(),
+            &experiment,
+        );
+
+        ExperimentState::new_rating(
+            "experiment1".to_owned(),
+            "result1".to_owned(),
+            experiment,
+            result,
+        )
+    }
+
+    fn is_practice_prompt(state: &ExperimentState) -> bool {
+        match UnityState::from(AppState::Experiment(state.clone())) {
+            UnityState::Experiment { prompt } => prompt.practice,
+            _ => panic!("Expected a prompt"),
+        }
+    }
+
+    #[test]
+    fn test_start_gap() {
+        // The default gap shows idle
+        let mut state = rating_state(|_| {});
+        state.answer(ExperimentAnswer::Rating { value: 3 }).unwrap();
+        assert_eq!(state.start_gap(), Duration::from_secs(1));
+        assert!(state.is_idle());
+        assert_eq!(
+            UnityState::from(AppState::Experiment(state.clone())),
+            UnityState::Idle
+        );
+
+        // The gap is recorded for the next prompt
+        state.set_is_idle(false);
+        state.answer(ExperimentAnswer::Rating { value: 3 }).unwrap();
+        let gaps = state
+            .try_as_rating_ref()
+            .unwrap()
+            .result
+            .ratings
+            .iter()
+            .map(|outcome| {
+                let rating = outcome.try_as_answered_ref().unwrap();
+                rating.timing.as_ref().unwrap().gap.is_some()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(gaps, [false, true]);
+
+        // A mask is shown instead of idle
+        let mut state = rating_state(|shared| {
+            shared.mask = Some(Mask::Preset {
+                preset: "preset2".to_owned(),
+            })
+        });
+        state.answer(ExperimentAnswer::Rating { value: 3 }).unwrap();
+        state.start_gap();
+        assert!(matches!(
+            UnityState::from(AppState::Experiment(state)),
+            UnityState::Live { .. }
+        ));
+
+        // Without a gap the next prompt is shown at once
+        let mut state = rating_state(|shared| shared.inter_trial_interval = 0.0);
+        let prompt_id = state.prompt_id.clone();
+        state.answer(ExperimentAnswer::Rating { value: 3 }).unwrap();
+        assert_eq!(state.start_gap(), Duration::ZERO);
+        assert!(!state.is_idle());
+        assert_ne!(state.prompt_id, prompt_id);
+    }
+
+    #[test]
+    fn test_validate_settings() {
+        assert!(rating_state(|_| {}).validate_settings().is_ok());
+
+        let invalid_settings: [fn(&mut SharedExperiment); 3] = [
+            |shared| shared.inter_trial_interval = -1.0,
+            |shared| shared.response_timeout = Some(0.0),
+            |shared| {
+                shared.mask = Some(Mask::Preset {
+                    preset: "missing".to_owned(),
+                })
+            },
+        ];
+        for configure in invalid_settings {
+            assert!(rating_state(configure).validate_settings().is_err());
+        }
+
+        // Transitions set directly are checked too, not only when deserialized
+        let mut experiment = ChoiceExperiment::new(
+            "Test Experiment".to_owned(),
+            HashMap::new(),
+            Vec::new(),
+            Some(Transition::new(0.5, Default::default()).unwrap()),
+        );
+        assert!(experiment.validate_settings().is_ok());
+        experiment.swap_transition.as_mut().unwrap().duration = -0.5;
+        assert!(experiment.validate_settings().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_state_after() {
+        let previous_live_view = LiveViewState {
+            parameters: ParameterValues::from([("glow", 0.3)]),
+            transition: None,
+        };
+
+        // The live view from before the experiment is shown again by default
+        let mut state = rating_state(|_| {});
+        state
+            .set_after_experiment(AfterExperiment::default(), Some(previous_live_view))
+            .await
+            .unwrap();
+        let AppState::LiveView(live_view) = state.state_after().await else {
+            panic!("Expected the live view");
+        };
+        assert_eq!(live_view.parameters, ParameterValues::from([("glow", 0.3)]));
+
+        state
+            .set_after_experiment(AfterExperiment::Idle, None)
+            .await
+            .unwrap();
+        assert!(matches!(state.state_after().await, AppState::Idle));
+    }
+
+    #[test]
+    fn test_time_out() {
+        // Without a response timeout the prompt waits forever
+        let mut state = rating_state(|_| {});
+        assert!(state.time_until_timeout().is_none());
+        assert!(state.time_out().is_err());
+
+        let mut state = rating_state(|shared| shared.response_timeout = Some(5.0));
+        state.shared_mut().prompted_at -= TimeDelta::seconds(2);
+        assert!(state.time_until_timeout().unwrap() <= Duration::from_secs(3));
+
+        // A skipped preset is recorded as a timeout, and the next one is shown
+        assert!(!state.time_out().unwrap());
+        let rating = state.try_as_rating_ref().unwrap();
+        let timeout = rating.result.ratings[0].try_as_timed_out_ref().unwrap();
+        assert_eq!(timeout.preset, "preset1");
+        assert!(!timeout.requeued);
+        assert_eq!(rating.experiment.order.len(), 3);
+        assert_eq!(state.current_index, 1);
+
+        // A requeued preset is shown again at the end
+        state
+            .try_as_rating_mut()
+            .unwrap()
+            .experiment
+            .shared
+            .on_timeout = TimeoutAction::Requeue;
+        state.time_out().unwrap();
+        assert_eq!(
+            state.try_as_rating_ref().unwrap().experiment.order,
+            ["preset1", "preset2", "preset3", "preset2"]
+        );
+
+        // Undoing the timeout shows the preset now instead of at the end
+        state.undo_answer().unwrap();
+        assert_eq!(state.current_index, 1);
+        assert_eq!(
+            state.try_as_rating_ref().unwrap().experiment.order,
+            ["preset1", "preset2", "preset3"]
+        );
+    }
+
+    #[test]
+    fn test_answered_count() {
+        let mut state = rating_state(|shared| shared.response_timeout = Some(5.0));
+        state.shared_mut().practice_count = 1;
+
+        // Neither the practice prompt nor the timeout count as answered
+        state.answer(ExperimentAnswer::Rating { value: 1 }).unwrap();
+        state.time_out().unwrap();
+        assert_eq!(state.answered_count(), 0);
+
+        state.answer(ExperimentAnswer::Rating { value: 2 }).unwrap();
+        assert_eq!(state.answered_count(), 1);
+        assert_eq!(state.current_index, 3);
+    }
+
+    #[test]
+    fn test_practice() {
+        let mut state = rating_state(|_| {});
+        state.shared_mut().practice_count = 2;
+        assert!(is_practice_prompt(&state));
+
+        // A practice prompt is repeated at the end of the practice, not of the experiment
+        state.repeat_later().unwrap();
+        assert_eq!(
+            state.try_as_rating_ref().unwrap().experiment.order,
+            ["preset2", "preset1", "preset3"]
+        );
+
+        state.answer(ExperimentAnswer::Rating { value: 1 }).unwrap();
+        state.set_is_idle(false);
+        assert!(is_practice_prompt(&state));
+
+        state.answer(ExperimentAnswer::Rating { value: 2 }).unwrap();
+        state.set_is_idle(false);
+        assert!(!is_practice_prompt(&state));
+    }
+}
@@ -1,11 +1,12 @@
-use std::sync::Arc;
-
-use anyhow::bail;
 use chrono::{DateTime, Local};
 use experiment_state::ExperimentState;
 use neuroviz::{
-    http_server::{ExperimentAnswer, ExperimentPrompt, UnityExperimentType, UnityState},
+    auth::Secret,
+    clients::ClientRegistry,
+    http_server::{ExperimentPrompt, UnityExperimentType, UnityState},
     parameters::ParameterValues,
+    tls::TlsCertificate,
+    transition::Transition,
 };
 use serde::{Deserialize, Serialize};
 use specta::Type;
@@ -24,6 +25,27 @@ pub fn get_duration_since(then: DateTime<Local>) -> f64 {
         / 1_000_000_000.0
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+pub struct LiveViewState {
+    pub parameters: ParameterValues,
+    /// How the headset should animate to the parameters, None means switching instantly
+    pub transition: Option<Transition>,
+}
+
+/// What the headset shows once an experiment is finished or exited
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AfterExperiment {
+    /// Live view with the parameters shown before the experiment started
+    #[default]
+    PreviousLiveView,
+    Idle,
+    /// Live view with the parameters of a preset
+    Preset {
+        preset: String,
+    },
+}
+
 #[derive(Debug, Clone, EnumTryAs, Serialize, Deserialize, Type)]
 #[serde(tag = "kind")]
 pub enum AppState {
@@ -31,47 +53,51 @@ pub enum AppState {
     Idle,
 
     #[serde(rename = "live_view")]
-    LiveView(ParameterValues),
+    LiveView(LiveViewState),
 
     #[serde(rename = "experiment")]
     Experiment(ExperimentState),
 }
 
-impl AppState {
-    /// Answer experiment, returns true if experiment is done
-    #[must_use]
-    pub fn answer_experiment(
-        &mut self,
-        experiment_answer: ExperimentAnswer,
-    ) -> anyhow::Result<bool> {
-        let AppState::Experiment(experiment_state) = self else {
-            bail!("Not in experiment mode");
-        };
-
-        let is_done = experiment_state.answer(experiment_answer)?;
-
-        Ok(is_done)
-    }
-}
-
 impl From<AppState> for UnityState {
     fn from(app_state: AppState) -> Self {
         match app_state {
             AppState::Idle => UnityState::Idle,
 
-            AppState::LiveView(parameters) => UnityState::Live { parameters },
+            AppState::LiveView(live_view) => UnityState::Live {
+                parameters: live_view.parameters,
+                transition: live_view.transition,
+            },
 
-            AppState::Experiment(experiment_state) if experiment_state.is_idle() => {
+            AppState::Experiment(experiment_state) if experiment_state.is_paused() => {
                 UnityState::Idle
             }
 
+            // Between prompts the headset shows the mask, or idle without one
+            AppState::Experiment(experiment_state) if experiment_state.is_idle() => {
+                match experiment_state.mask_parameters() {
+                    Some(parameters) => UnityState::Live {
+                        parameters,
+                        transition: None,
+                    },
+                    None => UnityState::Idle,
+                }
+            }
+
             AppState::Experiment(experiment_state) => UnityState::Experiment {
                 prompt: ExperimentPrompt {
+                    reference: experiment_state.prompt_reference(),
                     experiment_type: match experiment_state {
                         ExperimentState::Choice { .. } => UnityExperimentType::Choice,
                         ExperimentState::Rating { .. } => UnityExperimentType::Rating,
                     },
                     parameters: experiment_state.get_current_preset().parameters,
+                    transition: match &experiment_state {
+                        ExperimentState::Choice(choice) => choice.experiment.swap_transition,
+                        ExperimentState::Rating(_) => None,
+                    },
+                    response_timeout: experiment_state.response_timeout(),
+                    practice: experiment_state.is_practice(),
                 },
             },
         }
@@ -82,16 +108,32 @@ impl From<AppState> for UnityState {
 #[derive(Clone)]
 pub struct AppData {
     pub state: watch::Sender<AppState>,
-    pub connected_clients: watch::Sender<usize>,
-    pub secret: Arc<String>,
+    /// Headsets currently connected to the HTTP server
+    pub clients: ClientRegistry,
+    /// Secret the headsets authenticate with, which can be rotated to disconnect them
+    pub secret: Secret,
+    /// Certificate the headset server uses, None when serving plain HTTP
+    pub certificate: Option<TlsCertificate>,
+    /// Name the server is advertised with on the local network, None when not advertised
+    pub service_name: Option<String>,
+    /// Live view the operator set last, so it can be shown again after an experiment
+    pub last_live_view: watch::Sender<LiveViewState>,
 }
 
 impl AppData {
     pub fn new(state: AppState, secret: String) -> Self {
+        let last_live_view = match &state {
+            AppState::LiveView(live_view) => live_view.clone(),
+            _ => LiveViewState::default(),
+        };
+
         Self {
+            last_live_view: watch::Sender::new(last_live_view),
             state: watch::Sender::new(state),
-            connected_clients: watch::Sender::new(0),
-            secret: Arc::new(secret),
+            clients: ClientRegistry::new(),
+            secret: Secret::new(secret),
+            certificate: None,
+            service_name: None,
         }
     }
 }
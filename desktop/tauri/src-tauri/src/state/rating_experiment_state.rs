@@ -1,14 +1,15 @@
 use chrono::Local;
+use neuroviz::http_server::Rejection;
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
 use crate::data::{
     experiment::RatingExperiment,
-    experiment_result::{OutcomeRating, RatingExperimentResult},
+    experiment_result::{OutcomeRating, RatingExperimentResult, RatingOutcome, TimeoutRating},
     preset::Preset,
 };
 
-use super::{experiment_state::SharedExperimentState, get_duration_since};
+use super::experiment_state::SharedExperimentState;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
 pub struct RatingExperimentState {
@@ -38,53 +39,119 @@ impl RatingExperimentState {
         is_done
     }
 
-    pub fn answer(&mut self, value: u8) -> anyhow::Result<bool> {
+    pub fn answer(&mut self, value: u8) -> Result<bool, Rejection> {
         if self.is_done() {
             return Ok(true);
         }
 
-        let is_first_prompt = self.shared.current_index == 0;
-        let duration = match is_first_prompt {
-            true => get_duration_since(self.result.shared.time),
-            false => {
-                get_duration_since(self.result.ratings[self.shared.current_index as usize - 1].time)
-            }
-        };
+        let (duration, timing) = self.shared.prompt_timing(&self.result.shared.pauses);
 
         let outcome = OutcomeRating {
             preset: self.experiment.order[self.shared.current_index as usize].clone(),
             rank: value,
             time: Local::now(),
             duration,
+            timing: Some(timing),
         };
 
-        self.result.ratings.push(outcome);
+        self.result.ratings.push(RatingOutcome::Answered(outcome));
 
         self.shared.current_index += 1;
         let is_done = self.is_done();
 
         Ok(is_done)
     }
+
+    /// Record that the current preset was not rated in time and go on, returns true if done.
+    /// When requeued, the preset is shown again after the others
+    pub fn time_out(&mut self, requeue: bool) -> bool {
+        if self.is_done() {
+            return true;
+        }
+
+        let (_, timing) = self.shared.prompt_timing(&self.result.shared.pauses);
+        let preset = self.get_current_preset_key();
+
+        if requeue {
+            self.shared
+                .insert_later(&mut self.experiment.order, preset.clone(), false);
+        }
+
+        self.result
+            .ratings
+            .push(RatingOutcome::TimedOut(TimeoutRating {
+                preset,
+                timed_out_at: Local::now(),
+                requeued: requeue,
+                timing,
+            }));
+
+        self.shared.current_index += 1;
+
+        self.is_done()
+    }
+
+    /// Move the last answer to the undone outcomes and go back to its prompt, false if nothing was answered
+    pub fn undo(&mut self) -> bool {
+        let Some(outcome) = self.result.ratings.pop() else {
+            return false;
+        };
+
+        self.shared.current_index -= 1;
+
+        // The prompt is shown again now, so not again at the end
+        if let RatingOutcome::TimedOut(TimeoutRating {
+            preset,
+            requeued: true,
+            ..
+        }) = &outcome
+        {
+            let later = self.shared.current_index as usize + 1;
+
+            if let Some(position) = self.experiment.order[later..]
+                .iter()
+                .rposition(|requeued| requeued == preset)
+            {
+                self.shared
+                    .remove_later(&mut self.experiment.order, later + position);
+            }
+        }
+
+        self.result.undone.push(outcome);
+
+        true
+    }
+
+    /// Move the current preset to the end of the order, to be shown again after the others
+    pub fn repeat_later(&mut self) {
+        let index = self.shared.current_index as usize;
+        let preset_key = self.experiment.order.remove(index);
+
+        self.shared
+            .insert_later(&mut self.experiment.order, preset_key, true);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::data::experiment_result::Pause;
+    use chrono::TimeDelta;
     use neuroviz::parameters::ParameterValues;
     use std::collections::HashMap;
 
     // Helper function to create test data
     fn create_test_state() -> RatingExperimentState {
         // Create sample parameter values
-        let parameters = ParameterValues {
-            transparency: 0.5,
-            glow: 0.7,
-            smoothness: 0.8,
-            emission: 0.9,
-            light_intensity: 0.6,
-            light_temperature: 0.,
-        };
+        let parameters = ParameterValues::from([
+            ("transparency", 0.5),
+            ("glow", 0.7),
+            ("smoothness", 0.8),
+            ("emission", 0.9),
+            ("light_intensity", 0.6),
+            ("light_temperature", 6500.),
+        ]);
 
         // Create preset map
         let mut presets = HashMap::new();
@@ -112,15 +179,16 @@ mod tests {
 
         // Create experiment with fixed order
         let experiment = RatingExperiment {
-            shared: crate::data::experiment::SharedExperiment {
-                name: "Test Experiment".to_string(),
+            shared: crate::data::experiment::SharedExperiment::new(
+                "Test Experiment".to_string(),
                 presets,
-            },
+            ),
             order: vec![
                 "preset1".to_string(),
                 "preset2".to_string(),
                 "preset3".to_string(),
             ],
+            practice_order: vec![],
         };
 
         // Create initial result
@@ -132,17 +200,18 @@ mod tests {
                 observer_id: 1,
                 note: "Test note".to_string(),
                 presets: experiment.shared.presets.clone(),
+                status: Default::default(),
+                pauses: Vec::new(),
+                corrections: Vec::new(),
+                exit_reason: None,
             },
             ratings: vec![],
+            undone: vec![],
+            practice: vec![],
         };
 
         // Create shared experiment state
-        let shared = SharedExperimentState {
-            experiment_key: "experiment1".to_string(),
-            result_key: "result1".to_string(),
-            current_index: 0,
-            is_idle: false,
-        };
+        let shared = SharedExperimentState::new("experiment1".to_string(), "result1".to_string());
 
         RatingExperimentState {
             shared,
@@ -151,6 +220,11 @@ mod tests {
         }
     }
 
+    /// The answer of an outcome, fails the test on a timeout
+    fn answered(outcome: &RatingOutcome) -> &OutcomeRating {
+        outcome.try_as_answered_ref().unwrap()
+    }
+
     #[test]
     fn test_get_current_preset_key() {
         let mut state = create_test_state();
@@ -210,24 +284,24 @@ mod tests {
         assert!(!is_done);
         assert_eq!(state.shared.current_index, 1);
         assert_eq!(state.result.ratings.len(), 1);
-        assert_eq!(state.result.ratings[0].preset, "preset1");
-        assert_eq!(state.result.ratings[0].rank, 4);
+        assert_eq!(answered(&state.result.ratings[0]).preset, "preset1");
+        assert_eq!(answered(&state.result.ratings[0]).rank, 4);
 
         // Second answer - not done
         let is_done = state.answer(3).unwrap();
         assert!(!is_done);
         assert_eq!(state.shared.current_index, 2);
         assert_eq!(state.result.ratings.len(), 2);
-        assert_eq!(state.result.ratings[1].preset, "preset2");
-        assert_eq!(state.result.ratings[1].rank, 3);
+        assert_eq!(answered(&state.result.ratings[1]).preset, "preset2");
+        assert_eq!(answered(&state.result.ratings[1]).rank, 3);
 
         // Third answer - done
         let is_done = state.answer(5).unwrap();
         assert!(is_done);
         assert_eq!(state.shared.current_index, 3);
         assert_eq!(state.result.ratings.len(), 3);
-        assert_eq!(state.result.ratings[2].preset, "preset3");
-        assert_eq!(state.result.ratings[2].rank, 5);
+        assert_eq!(answered(&state.result.ratings[2]).preset, "preset3");
+        assert_eq!(answered(&state.result.ratings[2]).rank, 5);
 
         // Attempt to answer when done - should return true (done) without changing state
         let is_done = state.answer(2).unwrap();
@@ -235,4 +309,96 @@ mod tests {
         assert_eq!(state.shared.current_index, 3); // no change
         assert_eq!(state.result.ratings.len(), 3); // no change
     }
+
+    #[test]
+    fn test_answer_duration_from_acknowledgement() {
+        let mut state = create_test_state();
+
+        // Without an acknowledgement the duration is measured from the prompt
+        state.shared.prompted_at = Local::now() - TimeDelta::seconds(5);
+        state.answer(4).unwrap();
+
+        let timing = answered(&state.result.ratings[0]).timing.clone().unwrap();
+        assert!(answered(&state.result.ratings[0]).duration >= 5.0);
+        assert_eq!(timing.acknowledged_at, None);
+
+        // With an acknowledgement the duration is measured from when it was received
+        let displayed_at = Local::now() - TimeDelta::seconds(3);
+        state.shared.prompted_at = Local::now() - TimeDelta::seconds(5);
+        state.shared.displayed_at = Some(displayed_at);
+        state.shared.acknowledged_at = Some(Local::now() - TimeDelta::seconds(2));
+        state.answer(3).unwrap();
+
+        let outcome = answered(&state.result.ratings[1]);
+        let timing = outcome.timing.clone().unwrap();
+        assert!((2.0..5.0).contains(&outcome.duration));
+        assert!(timing.desktop_duration >= 5.0);
+        assert_eq!(timing.displayed_at, Some(displayed_at));
+    }
+
+    #[test]
+    fn test_answer_duration_excludes_pauses() {
+        let mut state = create_test_state();
+        let now = Local::now();
+
+        // A pause of 3 seconds during the prompt, and one before it that does not count
+        state.shared.prompted_at = now - TimeDelta::seconds(5);
+        state.result.shared.pauses = vec![
+            Pause {
+                started_at: now - TimeDelta::seconds(10),
+                ended_at: now - TimeDelta::seconds(6),
+            },
+            Pause {
+                started_at: now - TimeDelta::seconds(4),
+                ended_at: now - TimeDelta::seconds(1),
+            },
+        ];
+        state.answer(4).unwrap();
+
+        let outcome = answered(&state.result.ratings[0]);
+        let timing = outcome.timing.clone().unwrap();
+        assert!((2.0..3.0).contains(&outcome.duration));
+        assert!((2.0..3.0).contains(&timing.desktop_duration));
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut state = create_test_state();
+
+        // Nothing to undo before the first answer
+        assert!(!state.undo());
+
+        state.answer(4).unwrap();
+        state.answer(1).unwrap();
+        assert!(state.undo());
+
+        // The undone answer is kept, and its preset is shown again
+        assert_eq!(state.shared.current_index, 1);
+        assert_eq!(state.result.ratings.len(), 1);
+        assert_eq!(state.result.undone.len(), 1);
+        assert_eq!(answered(&state.result.undone[0]).rank, 1);
+        assert_eq!(state.get_current_preset_key(), "preset2");
+
+        state.answer(2).unwrap();
+        assert_eq!(answered(&state.result.ratings[1]).preset, "preset2");
+        assert_eq!(answered(&state.result.ratings[1]).rank, 2);
+    }
+
+    #[test]
+    fn test_repeat_later() {
+        let mut state = create_test_state();
+
+        state.repeat_later();
+
+        // The preset is shown after the others, and every preset is still rated once
+        assert_eq!(state.get_current_preset_key(), "preset2");
+        assert_eq!(state.experiment.order, ["preset2", "preset3", "preset1"]);
+
+        state.answer(1).unwrap();
+        state.answer(2).unwrap();
+        let is_done = state.answer(3).unwrap();
+
+        assert!(is_done);
+        assert_eq!(answered(&state.result.ratings[2]).preset, "preset1");
+    }
 }
@@ -1,19 +1,28 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context};
 use dirs;
+use neuroviz::parameters::ParameterSchema;
+use neuroviz::tls::TlsCertificate;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use tokio::fs::{self, File};
 use tokio::io::AsyncWriteExt;
 
 use super::commands::WithKey;
+use crate::consts::{PARAMETER_SCHEMA_FILE, SERVER_SETTINGS_FILE, TLS_FOLDER};
+use crate::data::settings::ServerSettings;
+use crate::data::{ValidateParameters, ValidateSettings};
 
 #[derive(Debug)]
 pub enum Folder {
     Presets,
     Experiments,
-    Results { experiment_key: String },
+    Results {
+        experiment_key: String,
+    },
+    /// Checkpoints of experiments in progress, so they can be resumed after a crash
+    Sessions,
 }
 
 impl Folder {
@@ -24,6 +33,7 @@ impl Folder {
             Folder::Results {
                 experiment_key: experiment,
             } => format!("results/{experiment}"),
+            Folder::Sessions => "sessions".to_owned(),
         }
         .to_string()
     }
@@ -47,6 +57,53 @@ pub fn data_folder() -> anyhow::Result<PathBuf> {
     Ok(path)
 }
 
+/// Read the parameter schema from the data folder, returns None if no schema file exists
+pub async fn read_parameter_schema() -> anyhow::Result<Option<ParameterSchema>> {
+    let path = data_folder()?.join(PARAMETER_SCHEMA_FILE);
+
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let file_content = fs::read_to_string(&path)
+        .await
+        .context("Could not read parameter schema")?;
+
+    let schema = ParameterSchema::from_json(&file_content)
+        .with_context(|| format!("Invalid parameter schema in {}", path.display()))?;
+
+    Ok(Some(schema))
+}
+
+/// Read the server settings from the data folder, using the defaults if no settings file exists
+pub async fn read_server_settings() -> anyhow::Result<ServerSettings> {
+    let path = data_folder()?.join(SERVER_SETTINGS_FILE);
+
+    if !path.exists() {
+        return Ok(ServerSettings::default());
+    }
+
+    let file_content = fs::read_to_string(&path)
+        .await
+        .context("Could not read server settings")?;
+
+    let settings = serde_json::from_str(&file_content)
+        .with_context(|| format!("Invalid server settings in {}", path.display()))?;
+
+    Ok(settings)
+}
+
+/// Load the TLS certificate from the data folder, generating it the first time
+pub async fn load_tls_certificate() -> anyhow::Result<TlsCertificate> {
+    let folder = data_folder()?.join(TLS_FOLDER);
+
+    let certificate = tokio::task::spawn_blocking(move || TlsCertificate::load_or_generate(folder))
+        .await?
+        .context("Could not load TLS certificate")?;
+
+    Ok(certificate)
+}
+
 pub async fn get_folder(folder: Folder) -> anyhow::Result<PathBuf> {
     let mut path = data_folder()?;
 
@@ -56,7 +113,6 @@ pub async fn get_folder(folder: Folder) -> anyhow::Result<PathBuf> {
         .await
         .context("Could not create directory")?;
 
-    println!("data dir: {}", path.display());
     Ok(path)
 }
 
@@ -81,7 +137,41 @@ pub async fn create_file(
     Ok(path)
 }
 
-pub async fn read_file<T: DeserializeOwned>(key: &str, folder: Folder) -> anyhow::Result<T> {
+/// Write the file, replacing it if it exists.
+/// The contents are written to a temporary file that is then renamed, so a crash never leaves a partially written file
+pub async fn replace_file(
+    key: &str,
+    contents: impl Serialize,
+    folder: Folder,
+) -> anyhow::Result<PathBuf> {
+    let folder_path = get_folder(folder).await?;
+    let path = folder_path.join(format!("{}.json", key));
+    let temporary_path = folder_path.join(format!("{}.json.tmp", key));
+
+    let json = serde_json::to_string_pretty(&contents).context("Could not serialize to JSON")?;
+
+    let mut file = File::create(&temporary_path)
+        .await
+        .context("Could not create file")?;
+
+    file.write_all(json.as_bytes())
+        .await
+        .context("Could not write to file")?;
+
+    // The contents must be on disk before the rename makes them visible
+    file.sync_all().await.context("Could not write to file")?;
+
+    fs::rename(&temporary_path, &path)
+        .await
+        .context("Could not replace file")?;
+
+    Ok(path)
+}
+
+pub async fn read_file<T: DeserializeOwned + ValidateParameters + ValidateSettings>(
+    key: &str,
+    folder: Folder,
+) -> anyhow::Result<T> {
     let file_name = format!("{}.json", key);
     let path = get_folder(folder).await?.join(file_name);
 
@@ -89,13 +179,24 @@ pub async fn read_file<T: DeserializeOwned>(key: &str, folder: Folder) -> anyhow
         .await
         .context("Could not read file")?;
 
-    let deserialized =
+    let mut deserialized =
         serde_json::from_str::<T>(&file_content).context("Could not deserialize JSON")?;
 
+    deserialized
+        .validate_parameters()
+        .with_context(|| format!("Invalid parameters in {}", path.display()))?;
+    deserialized
+        .validate_settings()
+        .with_context(|| format!("Invalid settings in {}", path.display()))?;
+
     Ok(deserialized)
 }
 
-pub async fn read_files<T: DeserializeOwned>(folder: Folder) -> anyhow::Result<Vec<WithKey<T>>> {
+/// Read every JSON file in the folder.
+/// Files that cannot be read or are invalid, like files in an older format, are skipped with a warning so one bad file does not hide the rest
+pub async fn read_files<T: DeserializeOwned + ValidateParameters + ValidateSettings>(
+    folder: Folder,
+) -> anyhow::Result<Vec<WithKey<T>>> {
     let path = get_folder(folder).await.context("Could not open folder")?;
 
     if !path.is_dir() {
@@ -113,30 +214,53 @@ pub async fn read_files<T: DeserializeOwned>(folder: Folder) -> anyhow::Result<V
         .await
         .context("Failed to read directory entry")?
     {
-        let file_content = fs::read_to_string(entry.path()).await?;
-
-        let deserialized = serde_json::from_str::<T>(&file_content)
-            .context("Failed to deserialize JSON content")?;
-
         let path = entry.path();
 
-        let file_without_extension = path
-            .file_stem()
-            .context("Could not get file stem from path")?;
-
-        let file_without_extension = file_without_extension
-            .to_str()
-            .context("Could not convert OsStr to str")?;
+        // Skip other files, like temporary files left by a crash while writing
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
 
-        results.push(WithKey {
-            key: file_without_extension.to_owned(),
-            value: deserialized,
-        });
+        match read_entry(&path).await {
+            Ok(result) => results.push(result),
+            Err(error) => eprintln!("Skipping {}: {error:#}", path.display()),
+        }
     }
 
     Ok(results)
 }
 
+async fn read_entry<T: DeserializeOwned + ValidateParameters + ValidateSettings>(
+    path: &Path,
+) -> anyhow::Result<WithKey<T>> {
+    let file_content = fs::read_to_string(path)
+        .await
+        .context("Could not read file")?;
+
+    let mut deserialized =
+        serde_json::from_str::<T>(&file_content).context("Failed to deserialize JSON content")?;
+
+    deserialized
+        .validate_parameters()
+        .context("Invalid parameters")?;
+    deserialized
+        .validate_settings()
+        .context("Invalid settings")?;
+
+    let file_without_extension = path
+        .file_stem()
+        .context("Could not get file stem from path")?;
+
+    let file_without_extension = file_without_extension
+        .to_str()
+        .context("Could not convert OsStr to str")?;
+
+    Ok(WithKey {
+        key: file_without_extension.to_owned(),
+        value: deserialized,
+    })
+}
+
 pub async fn delete_file(key: &str, folder: Folder) -> anyhow::Result<()> {
     let file_name = format!("{}.json", key);
     let path = get_folder(folder).await?.join(file_name);
@@ -147,3 +271,19 @@ pub async fn delete_file(key: &str, folder: Folder) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Delete the file, doing nothing if it does not exist
+pub async fn delete_file_if_exists(key: &str, folder: Folder) -> anyhow::Result<()> {
+    let file_name = format!("{}.json", key);
+    let path = get_folder(folder).await?.join(file_name);
+
+    if !path.exists() {
+        return Ok(());
+    }
+
+    fs::remove_file(&path)
+        .await
+        .context("Could not delete file")?;
+
+    Ok(())
+}
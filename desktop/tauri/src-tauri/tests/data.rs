@@ -15,4 +15,7 @@ pub async fn test_data() {
         .await
         .unwrap();
     assert!(!experiments.is_empty());
+
+    let schema = storage::read_parameter_schema().await.unwrap();
+    assert!(schema.is_some());
 }
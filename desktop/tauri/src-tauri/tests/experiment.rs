@@ -1,27 +1,29 @@
 use std::collections::HashMap;
 
 use chrono::Local;
-use eventsource_stream::Eventsource;
 use futures::StreamExt;
 use neuroviz::{
-    extensions::MpscReceiverExt,
-    http_server::{UnityEvent, UnityState},
+    client::Client,
+    http_server::{
+        ExperimentAnswer, PromptReference, Rejection, Responder, UnityEvent, UnityState,
+    },
     parameters::ParameterValues,
 };
-use reqwest::header::AUTHORIZATION;
 use tauri_neuroviz_lib::{
     data::{
         experiment::{Choice, ChoiceExperiment, CurrentPreset},
         experiment_result::ChoiceExperimentResult,
         preset::Preset,
     },
+    engine::{Command, Engine},
     http_server_task,
     state::{experiment_state::ExperimentState, AppData, AppState},
 };
 use tokio::{
     join,
     net::TcpListener,
-    sync::{mpsc, watch},
+    sync::mpsc,
+    time::{sleep, Duration},
 };
 
 /// Helper function to create a TCP listener on a random port
@@ -38,35 +40,6 @@ async fn listener_random_port() -> (TcpListener, String) {
     (listener, listening_url)
 }
 
-/// Task to handle Unity events, will receive events from Unity and update the app state accordingly
-pub async fn handle_unity_events_task(
-    app_state_sender: watch::Sender<AppState>,
-    unity_event_receiver: mpsc::Receiver<UnityEvent>,
-) {
-    let mut stream = unity_event_receiver.into_stream();
-
-    while let Some(event) = stream.next().await {
-        app_state_sender.send_modify(|state| match event {
-            UnityEvent::SwapPreset => state
-                .try_as_experiment_mut()
-                .unwrap()
-                .try_as_choice_mut()
-                .unwrap()
-                .swap_current_preset(),
-            UnityEvent::Answer(experiment_answer) => {
-                let is_done = state.answer_experiment(experiment_answer).unwrap();
-                if is_done {
-                    app_state_sender
-                        .send(AppState::LiveView(Default::default()))
-                        .unwrap();
-                }
-            }
-            UnityEvent::Connected => {}
-            UnityEvent::Disconnected => {}
-        });
-    }
-}
-
 /// Integration test for the experiment functionality, tests the AppData and HTTP server integrated
 #[tokio::test]
 async fn experiment_integration_test() {
@@ -78,31 +51,27 @@ async fn experiment_integration_test() {
     let (listener, listening_url) = listener_random_port().await;
 
     let http_server = http_server_task(
+        "NeuroViz".to_owned(),
         listener,
         app_data.state.subscribe(),
         unity_event_sender.clone(),
-        Some(secret.clone()),
+        app_data.clients.clone(),
+        Some(app_data.secret.clone()),
+        None,
     );
-    let handle_unity_events =
-        handle_unity_events_task(app_data.state.clone(), unity_event_receiver);
+    let (engine, engine_handle) = Engine::new(app_data.state.clone());
+    let run_engine = engine.run(unity_event_receiver, |_| {});
 
     // Spawn tasks in background
-    tokio::spawn(async { join!(http_server, handle_unity_events) });
+    tokio::spawn(async { join!(http_server, run_engine) });
 
-    let mut event_stream = reqwest::Client::new()
-        .get(format!("{listening_url}/state/subscribe"))
-        .header(AUTHORIZATION, &secret)
-        .send()
-        .await
-        .unwrap()
-        .bytes_stream()
-        .eventsource();
+    let client = Client::new(listening_url, Some(secret)).unwrap();
+    let mut state_stream = Box::pin(client.subscribe());
 
     let mut get_next_state = async || {
-        let event = event_stream.next().await.unwrap().unwrap();
-        let data = serde_json::from_str::<UnityState>(&event.data).unwrap();
+        let state: UnityState = state_stream.next().await.unwrap().unwrap().state;
 
-        data
+        state
     };
 
     // Check if the initial state is sent
@@ -111,23 +80,23 @@ async fn experiment_integration_test() {
         app_data.state.borrow().clone().into()
     );
 
-    let parameters_1 = ParameterValues {
-        transparency: 0.5,
-        glow: 0.5,
-        smoothness: 0.5,
-        emission: 0.3,
-        light_intensity: 0.5,
-        light_temperature: 0.5,
-    };
+    let parameters_1 = ParameterValues::from([
+        ("transparency", 0.5),
+        ("glow", 0.5),
+        ("smoothness", 0.5),
+        ("emission", 0.3),
+        ("light_intensity", 0.5),
+        ("light_temperature", 6500.),
+    ]);
 
-    let parameters_2 = ParameterValues {
-        transparency: 0.7,
-        glow: 0.7,
-        smoothness: 0.7,
-        emission: 0.7,
-        light_intensity: 0.,
-        light_temperature: 0.,
-    };
+    let parameters_2 = ParameterValues::from([
+        ("transparency", 0.7),
+        ("glow", 0.7),
+        ("smoothness", 0.7),
+        ("emission", 0.7),
+        ("light_intensity", 0.),
+        ("light_temperature", 6500.),
+    ]);
 
     // Start an experiment
     let experiment = ChoiceExperiment::new(
@@ -152,6 +121,7 @@ async fn experiment_integration_test() {
             a: "preset-1".to_owned(),
             b: "preset-2".to_owned(),
         }],
+        None,
     );
 
     let experiment_result = ChoiceExperimentResult::new(
@@ -162,16 +132,26 @@ async fn experiment_integration_test() {
         &experiment,
     );
 
-    app_data
-        .state
-        .send(AppState::Experiment(ExperimentState::new_choice(
-            "experiment-1".to_owned(),
-            "result-1".to_owned(),
-            experiment,
-            experiment_result,
-        )))
+    let experiment_state = ExperimentState::new_choice(
+        "experiment-1".to_owned(),
+        "result-1".to_owned(),
+        experiment,
+        experiment_result,
+    );
+
+    engine_handle
+        .send(Command::Start(Box::new(experiment_state.clone())))
+        .await
         .unwrap();
 
+    // Another experiment can not be started while this one is in progress
+    assert!(matches!(
+        engine_handle
+            .send(Command::Start(Box::new(experiment_state)))
+            .await,
+        Err(Rejection::WrongState(_))
+    ));
+
     // Check if the experiment state is sent
     let _unity_state = get_next_state().await;
     let unity_state = get_next_state().await;
@@ -190,11 +170,24 @@ async fn experiment_integration_test() {
 
     assert_eq!(get_current_preset(), CurrentPreset::A);
 
-    // Send a swap event
+    // A swap for another prompt should be rejected
+    let prompt = unity_state.prompt_reference().unwrap().clone();
+    let stale_prompt = PromptReference::new(prompt.trial_index);
+
+    let (responder, reply) = Responder::new();
     unity_event_sender
-        .send(UnityEvent::SwapPreset)
+        .send(UnityEvent::SwapPreset(stale_prompt, responder))
         .await
         .unwrap();
+    assert!(matches!(reply.await, Ok(Err(Rejection::Prompt(_)))));
+
+    // Send a swap event for the current prompt
+    let (responder, reply) = Responder::new();
+    unity_event_sender
+        .send(UnityEvent::SwapPreset(prompt.clone(), responder))
+        .await
+        .unwrap();
+    assert_eq!(reply.await, Ok(Ok(())));
 
     // Check state is in sync
     let unity_state = get_next_state().await;
@@ -202,4 +195,99 @@ async fn experiment_integration_test() {
 
     // Check if the current preset is swapped
     assert_eq!(get_current_preset(), CurrentPreset::B);
+
+    // While paused the headset shows idle, and its swaps are rejected
+    engine_handle.send(Command::Pause).await.unwrap();
+    assert_eq!(get_next_state().await, UnityState::Idle);
+
+    let (responder, reply) = Responder::new();
+    unity_event_sender
+        .send(UnityEvent::SwapPreset(prompt.clone(), responder))
+        .await
+        .unwrap();
+    assert!(matches!(reply.await, Ok(Err(Rejection::Prompt(_)))));
+    assert!(engine_handle.send(Command::Pause).await.is_err());
+
+    // Resuming shows the same prompt again, which the desktop can swap
+    engine_handle.send(Command::Resume).await.unwrap();
+    let unity_state = get_next_state().await;
+    assert_eq!(unity_state.prompt_reference(), Some(&prompt));
+
+    engine_handle.send(Command::SwapPreset).await.unwrap();
+    assert_eq!(get_current_preset(), CurrentPreset::A);
+}
+
+/// Pausing in the gap between prompts should keep the headset idle until the experiment is resumed
+#[tokio::test]
+async fn pause_during_gap_test() {
+    let app_data = AppData::new(AppState::Idle, "secret".to_owned());
+    let (_unity_event_sender, unity_event_receiver) = mpsc::channel(100);
+
+    let (engine, engine_handle) = Engine::new(app_data.state.clone());
+    tokio::spawn(engine.run(unity_event_receiver, |_| {}));
+
+    let preset = |name: &str, glow: f32| Preset {
+        name: name.to_owned(),
+        parameters: ParameterValues::from([("glow", glow)]),
+    };
+    let choice = || Choice {
+        a: "preset-1".to_owned(),
+        b: "preset-2".to_owned(),
+    };
+
+    let mut experiment = ChoiceExperiment::new(
+        "Experiment 1".to_owned(),
+        HashMap::from_iter([
+            ("preset-1".to_owned(), preset("Preset 1", 0.2)),
+            ("preset-2".to_owned(), preset("Preset 2", 0.8)),
+        ]),
+        vec![choice(), choice()],
+        None,
+    );
+    experiment.shared.inter_trial_interval = 0.2;
+
+    let experiment_result = ChoiceExperimentResult::new(
+        "result-1".to_owned(),
+        Local::now(),
+        0,
+        "my note".to_owned(),
+        &experiment,
+    );
+
+    engine_handle
+        .send(Command::Start(Box::new(ExperimentState::new_choice(
+            "experiment-1".to_owned(),
+            "result-1".to_owned(),
+            experiment,
+            experiment_result,
+        ))))
+        .await
+        .unwrap();
+
+    let unity_state = || -> UnityState { app_data.state.borrow().clone().into() };
+    let first_prompt = unity_state().prompt_reference().unwrap().clone();
+
+    // Answering starts the gap, and the operator pauses during it
+    engine_handle
+        .send(Command::Answer(ExperimentAnswer::Choice))
+        .await
+        .unwrap();
+    engine_handle.send(Command::Pause).await.unwrap();
+
+    // The end of the gap should not show the next prompt while paused
+    sleep(Duration::from_millis(400)).await;
+
+    assert_eq!(unity_state(), UnityState::Idle);
+    {
+        let state = app_data.state.borrow();
+        let experiment_state = state.try_as_experiment_ref().unwrap();
+        assert!(experiment_state.is_paused());
+        assert!(experiment_state.is_idle());
+    }
+
+    // Resuming ends the gap and shows the next prompt
+    engine_handle.send(Command::Resume).await.unwrap();
+
+    let next_prompt = unity_state().prompt_reference().unwrap().clone();
+    assert_eq!(next_prompt.trial_index, first_prompt.trial_index + 1);
 }
@@ -5,13 +5,14 @@ import { useCallback, useEffect, useState } from "react";
 import { BrowserRouter, Navigate, Route, Routes } from "react-router-dom";
 import { AppState, commands, events, ExperimentState } from "./bindings.gen";
 import Sidebar from "./components/Sidebar";
+import UnfinishedSessionsPopup from "./components/UnfinishedSessionsPopup";
 import {
   ROUTE_EXPERIMENTS,
   ROUTE_LIVE_VIEW,
   ROUTE_PRESETS,
   ROUTE_RESULTS,
 } from "./const";
-import { useIsConnected } from "./hooks";
+import { useConnectedClients } from "./hooks";
 import ActiveExperiment from "./pages/ActiveExperiment/ActiveExperiment";
 import ExperimentsPage from "./pages/Experiments";
 import LiveViewPage from "./pages/LiveView";
@@ -51,14 +52,14 @@ function useExperimentState(): ExperimentState | undefined {
 }
 
 export default function App() {
-  const isConnected = useIsConnected();
+  const clients = useConnectedClients();
   const experimentState = useExperimentState();
 
   if (experimentState) {
     return (
       <ActiveExperiment
         experimentState={experimentState}
-        isConnected={isConnected}
+        clients={clients}
       />
     );
   }
@@ -66,7 +67,7 @@ export default function App() {
   return (
     <BrowserRouter>
       <div className={styles.mainLayout}>
-        <Sidebar isConnected={isConnected} />
+        <Sidebar clients={clients} />
         <Routes>
           <Route index element={<Navigate to={ROUTE_LIVE_VIEW} />} />
           <Route path={ROUTE_LIVE_VIEW} element={<LiveViewPage />} />
@@ -75,6 +76,7 @@ export default function App() {
           <Route path={ROUTE_RESULTS} element={<ResultsPage />} />
         </Routes>
       </div>
+      <UnfinishedSessionsPopup />
     </BrowserRouter>
   );
 }
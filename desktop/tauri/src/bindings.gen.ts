@@ -6,8 +6,8 @@ export const commands = {
   async currentState(): Promise<AppState> {
     return await TAURI_INVOKE("current_state");
   },
-  async isConnected(): Promise<boolean> {
-    return await TAURI_INVOKE("is_connected");
+  async getConnectedClients(): Promise<ConnectedClient[]> {
+    return await TAURI_INVOKE("get_connected_clients");
   },
   async showFolder(folder: TopLevelFolder): Promise<null> {
     return await TAURI_INVOKE("show_folder", { folder });
@@ -18,6 +18,24 @@ export const commands = {
   async getSecret(): Promise<string> {
     return await TAURI_INVOKE("get_secret");
   },
+  /**
+   * SHA-256 fingerprint of the TLS certificate for the QR code, None when serving plain HTTP
+   */
+  async getCertificateFingerprint(): Promise<string | null> {
+    return await TAURI_INVOKE("get_certificate_fingerprint");
+  },
+  /**
+   * Name the server is advertised with on the local network, None when not advertised
+   */
+  async getServiceName(): Promise<string | null> {
+    return await TAURI_INVOKE("get_service_name");
+  },
+  /**
+   * Replace the secret with a new one, disconnecting every headset connected with the old secret
+   */
+  async rotateSecret(): Promise<string> {
+    return await TAURI_INVOKE("rotate_secret");
+  },
   async getParameters(): Promise<Parameter[]> {
     return await TAURI_INVOKE("get_parameters");
   },
@@ -72,10 +90,13 @@ export const commands = {
     return await TAURI_INVOKE("set_idle_mode");
   },
   /**
-   * Enter live mode with parameters
+   * Enter live mode with parameters, optionally animating to them on the headset
    */
-  async setLiveMode(parameters: ParameterValues): Promise<null> {
-    return await TAURI_INVOKE("set_live_mode", { parameters });
+  async setLiveMode(
+    parameters: ParameterValues,
+    transition: Transition | null,
+  ): Promise<null> {
+    return await TAURI_INVOKE("set_live_mode", { parameters, transition });
   },
   async startExperiment(
     experimentKey: string,
@@ -83,6 +104,7 @@ export const commands = {
     obeserverId: number,
     note: string,
     randomize: boolean,
+    afterExperiment: AfterExperiment,
   ): Promise<null> {
     return await TAURI_INVOKE("start_experiment", {
       experimentKey,
@@ -90,13 +112,21 @@ export const commands = {
       obeserverId,
       note,
       randomize,
+      afterExperiment,
     });
   },
   /**
-   * Exit the current experiment early
+   * Exit the current experiment early. The answers so far are saved as an aborted result with the
+   * exit reason when `save_partial_result` is set, and discarded otherwise
    */
-  async exitExperiment(): Promise<void> {
-    await TAURI_INVOKE("exit_experiment");
+  async exitExperiment(
+    savePartialResult: boolean,
+    exitReason: string | null,
+  ): Promise<null> {
+    return await TAURI_INVOKE("exit_experiment", {
+      savePartialResult,
+      exitReason,
+    });
   },
   /**
    * Answer the current experiment prompt
@@ -110,17 +140,61 @@ export const commands = {
   async swapPreset(): Promise<null> {
     return await TAURI_INVOKE("swap_preset");
   },
+  /**
+   * Pause the current experiment for a break, the headset shows idle until it is resumed
+   */
+  async pauseExperiment(): Promise<null> {
+    return await TAURI_INVOKE("pause_experiment");
+  },
+  /**
+   * Resume the current experiment after a pause, showing the prompt that was paused
+   */
+  async resumeExperiment(): Promise<null> {
+    return await TAURI_INVOKE("resume_experiment");
+  },
+  /**
+   * Undo the last answer of the current experiment, e.g. when the observer pressed the wrong button
+   */
+  async undoAnswer(): Promise<null> {
+    return await TAURI_INVOKE("undo_answer");
+  },
+  /**
+   * Move the current prompt to the end of the experiment, to be shown again after the others
+   */
+  async repeatLater(): Promise<null> {
+    return await TAURI_INVOKE("repeat_later");
+  },
+  /**
+   * Get the experiments that were checkpointed but never finished, which can be resumed or saved as incomplete
+   */
+  async getUnfinishedSessions(): Promise<WithKey<UnfinishedSession>[]> {
+    return await TAURI_INVOKE("get_unfinished_sessions");
+  },
+  /**
+   * Continue an unfinished experiment from the first unanswered prompt
+   */
+  async resumeSession(key: string): Promise<null> {
+    return await TAURI_INVOKE("resume_session", { key });
+  },
+  /**
+   * Save the answers of an unfinished experiment as an incomplete result
+   */
+  async saveIncompleteSession(key: string): Promise<null> {
+    return await TAURI_INVOKE("save_incomplete_session", { key });
+  },
 };
 
 /** user-defined events **/
 
 export const events = __makeEvents__<{
-  connectionEvent: ConnectionEvent;
+  connectedClientsEvent: ConnectedClientsEvent;
   resultSavedEvent: ResultSavedEvent;
+  secretEvent: SecretEvent;
   stateEvent: StateEvent;
 }>({
-  connectionEvent: "connection-event",
+  connectedClientsEvent: "connected-clients-event",
   resultSavedEvent: "result-saved-event",
+  secretEvent: "secret-event",
   stateEvent: "state-event",
 });
 
@@ -128,43 +202,242 @@ export const events = __makeEvents__<{
 
 /** user-defined types **/
 
+/**
+ * What the headset shows once an experiment is finished or exited
+ */
+export type AfterExperiment =
+  /**
+   * Live view with the parameters shown before the experiment started
+   */
+  | { kind: "previous_live_view" }
+  | { kind: "idle" }
+  /**
+   * Live view with the parameters of a preset
+   */
+  | { kind: "preset"; preset: string };
 export type AppState =
   | { kind: "idle" }
-  | ({ kind: "live_view" } & ParameterValues)
+  | ({ kind: "live_view" } & LiveViewState)
   | ({ kind: "experiment" } & ExperimentState);
 export type Choice = { a: string; b: string };
 export type ChoiceExperiment = {
   name: string;
   presets: Partial<{ [key in string]: Preset }>;
-} & { choices: Choice[] };
+  /**
+   * Seconds the headset shows idle, or the mask, between prompts. Zero shows the next prompt at once
+   */
+  inter_trial_interval?: number;
+  /**
+   * Shown between prompts instead of idle
+   */
+  mask?: Mask | null;
+  /**
+   * Seconds the observer has to answer each prompt after it is displayed, None to wait forever
+   */
+  response_timeout?: number | null;
+  on_timeout?: TimeoutAction;
+  /**
+   * Leave the practice answers out of the result, instead of keeping them apart
+   */
+  drop_practice?: boolean;
+} & {
+  choices: Choice[];
+  /**
+   * How the headset morphs between A and B when swapping, None means switching instantly
+   */
+  swap_transition?: Transition | null;
+  /**
+   * Choices made before the others to practice, which are kept apart in the result
+   */
+  practice_choices?: Choice[];
+};
 export type ChoiceExperimentResult = {
   name: string;
   time: string;
   observer_id: number;
   note: string;
   presets: Partial<{ [key in string]: Preset }>;
-} & { choices: OutcomeChoice[] };
+  status?: CompletionStatus;
+  /**
+   * Breaks taken during the experiment, which are left out of the durations
+   */
+  pauses?: Pause[];
+  corrections?: Correction[];
+  /**
+   * Why the experiment was exited before every prompt was answered
+   */
+  exit_reason?: string | null;
+} & {
+  /**
+   * One outcome for each prompt shown, in order
+   */
+  choices: ChoiceOutcome[];
+  /**
+   * Answers that were undone, see [`SharedExperimentResult::corrections`]
+   */
+  undone?: ChoiceOutcome[];
+  /**
+   * Outcomes of the practice prompts, empty when the practice answers are dropped
+   */
+  practice?: ChoiceOutcome[];
+};
 export type ChoiceExperimentState = {
   experiment_key: string;
   result_key: string;
   current_index: number;
   is_idle: boolean;
+  /**
+   * Identifies the current prompt, answers from the headset must refer to it
+   */
+  prompt_id: PromptId;
+  /**
+   * When the current prompt was sent to the headset
+   */
+  prompted_at: string;
+  /**
+   * When the headset displayed the current prompt, using the clock of the headset
+   */
+  displayed_at: string | null;
+  /**
+   * When the display acknowledgement for the current prompt was received
+   */
+  acknowledged_at: string | null;
+  /**
+   * When the experiment was paused, None while running
+   */
+  paused_at?: string | null;
+  /**
+   * When the gap before the current prompt started, None when it was shown without a gap
+   */
+  gap_started_at?: string | null;
+  /**
+   * Live view the operator set before the experiment started
+   */
+  previous_live_view?: LiveViewState | null;
+  /**
+   * What to show once the experiment is finished or exited
+   */
+  after_experiment?: AfterExperiment;
+  /**
+   * Number of practice prompts at the start of the order
+   */
+  practice_count?: number;
 } & {
   experiment: ChoiceExperiment;
   result: ChoiceExperimentResult;
   current_preset: CurrentPreset;
 };
-export type ConnectionEvent = { is_connected: boolean };
+/**
+ * What came of a choice prompt. Untagged, so results saved before timeouts are still read
+ */
+export type ChoiceOutcome = OutcomeChoice | TimeoutChoice;
+export type ClientId = number;
+/**
+ * What a client tells about itself when subscribing, every field is optional
+ */
+export type ClientIdentity = {
+  /**
+   * Stable identifier of the device, e.g. the Android device id
+   */
+  device_id: string | null;
+  /**
+   * Version of the headset app
+   */
+  app_version: string | null;
+  /**
+   * Model of the device, e.g. `XREAL Air 2 Ultra`
+   */
+  model: string | null;
+};
+export type ConnectedClient = {
+  id: ClientId;
+  identity: ClientIdentity;
+  connected_at: string;
+  /**
+   * Last time data was exchanged with the client
+   */
+  last_seen: string;
+  remote_address: string | null;
+};
+export type ConnectedClientsEvent = { clients: ConnectedClient[] };
 export type CreateExperiment = /**
  * Rating between 1-5
  */
-(| { experiment_type: "rating"; order: string[] }
+(| { experiment_type: "rating"; order: string[]; practice_order?: string[] }
   /**
    * Choose between two options
    */
-  | { experiment_type: "choice"; choices: Choice[] }
-) & { name: string; presets: string[] };
+  | {
+      experiment_type: "choice";
+      choices: Choice[];
+      swap_transition?: Transition | null;
+      practice_choices?: Choice[];
+    }
+) & {
+  name: string;
+  presets: string[];
+  /**
+   * Seconds between prompts, defaults to one second
+   */
+  inter_trial_interval?: number | null;
+  /**
+   * Shown between prompts instead of idle, a preset mask is added to the presets
+   */
+  mask?: Mask | null;
+  /**
+   * Seconds the observer has to answer each prompt, None to wait forever
+   */
+  response_timeout?: number | null;
+  on_timeout?: TimeoutAction;
+  /**
+   * Leave the practice answers out of the result, instead of keeping them apart
+   */
+  drop_practice?: boolean;
+};
+/**
+ * Whether every prompt of the experiment was answered before the result was saved
+ */
+export type CompletionStatus =
+  /**
+   * Results saved before the status was recorded were always complete
+   */
+  | "complete"
+  /**
+   * Saved from an unfinished session, e.g. after a crash
+   */
+  | "incomplete"
+  /**
+   * Exited by the operator before every prompt was answered, see the exit reason
+   */
+  | "aborted";
+/**
+ * A correction by the operator, kept so that the result can be audited
+ */
+export type Correction = {
+  action: CorrectionAction;
+  time: string;
+  /**
+   * Index of the prompt that was corrected, in the order at the time
+   */
+  index: number;
+};
+/**
+ * How the operator corrected the experiment
+ */
+export type CorrectionAction =
+  /**
+   * The last answer was moved to the undone outcomes, and its prompt shown again
+   */
+  | "undo"
+  /**
+   * The prompt was moved to the end of the order, to be shown again after the others
+   */
+  | "repeat";
 export type CurrentPreset = "A" | "B";
+/**
+ * Easing curve used when animating between two sets of parameters
+ */
+export type Easing = "linear" | "ease_in" | "ease_out" | "ease_in_out";
 export type Experiment =
   | ({ experiment_type: "rating" } & RatingExperiment)
   | ({ experiment_type: "choice" } & ChoiceExperiment);
@@ -177,18 +450,51 @@ export type ExperimentResult =
 export type ExperimentState =
   | ({ experiment_type: "rating" } & RatingExperimentState)
   | ({ experiment_type: "choice" } & ChoiceExperimentState);
+export type LiveViewState = {
+  parameters: ParameterValues;
+  /**
+   * How the headset should animate to the parameters, None means switching instantly
+   */
+  transition: Transition | null;
+};
+/**
+ * What the headset shows between prompts, so the observer does not compare against the previous prompt
+ */
+export type Mask =
+  /**
+   * One of the presets of the experiment, e.g. a neutral one
+   */
+  | { kind: "preset"; preset: string }
+  /**
+   * The default value of every parameter
+   */
+  | { kind: "default" };
 export type OutcomeChoice = {
   a: string;
   b: string;
   selected: string;
   time: string;
+  /**
+   * Seconds from the prompt being displayed to the answer, see [`PromptTiming`] for the raw timings
+   */
   duration: number;
+  /**
+   * Missing in results saved before display acknowledgements were recorded
+   */
+  timing?: PromptTiming | null;
 };
 export type OutcomeRating = {
   preset: string;
   rank: number;
   time: string;
+  /**
+   * Seconds from the prompt being displayed to the answer, see [`PromptTiming`] for the raw timings
+   */
   duration: number;
+  /**
+   * Missing in results saved before display acknowledgements were recorded
+   */
+  timing?: PromptTiming | null;
 };
 export type Parameter = {
   key: ParameterKey;
@@ -197,46 +503,227 @@ export type Parameter = {
   max: number;
   default: number;
 };
-export type ParameterKey =
-  | "transparency"
-  | "glow"
-  | "smoothness"
-  | "emission"
-  | "light_intensity"
-  | "light_temperature";
-export type ParameterValues = {
-  transparency: number;
-  glow: number;
-  smoothness: number;
-  emission: number;
-  light_intensity: number;
-  light_temperature: number;
-};
+/**
+ * Identifies a parameter, e.g. `transparency`
+ */
+export type ParameterKey = string;
+/**
+ * Values for a set of parameters, keyed by parameter key.
+ *
+ * When deserializing, parameters missing from the input are set to the default
+ * from the schema currently in use, so files written before a parameter was added still load.
+ */
+export type ParameterValues = Partial<{ [key in ParameterKey]: number }>;
+/**
+ * A break in the experiment, during which no prompt was shown and answers were rejected
+ */
+export type Pause = { started_at: string; ended_at: string };
 export type Preset = { name: string; parameters: ParameterValues };
+/**
+ * Unique identifier of a single prompt
+ */
+export type PromptId = string;
+/**
+ * When a prompt was shown, as seen by the desktop and by the headset
+ */
+export type PromptTiming = {
+  /**
+   * When the desktop sent the prompt to the headset
+   */
+  prompted_at: string;
+  /**
+   * Seconds from the desktop sending the prompt to the answer, not counting pauses
+   */
+  desktop_duration: number;
+  /**
+   * When the headset displayed the prompt, using the clock of the headset
+   */
+  displayed_at: string | null;
+  /**
+   * When the desktop received the display acknowledgement from the headset
+   */
+  acknowledged_at: string | null;
+  /**
+   * Seconds the headset showed idle or the mask before the prompt, None when there was no gap
+   */
+  gap?: number | null;
+};
 export type RatingExperiment = {
   name: string;
   presets: Partial<{ [key in string]: Preset }>;
-} & { order: string[] };
+  /**
+   * Seconds the headset shows idle, or the mask, between prompts. Zero shows the next prompt at once
+   */
+  inter_trial_interval?: number;
+  /**
+   * Shown between prompts instead of idle
+   */
+  mask?: Mask | null;
+  /**
+   * Seconds the observer has to answer each prompt after it is displayed, None to wait forever
+   */
+  response_timeout?: number | null;
+  on_timeout?: TimeoutAction;
+  /**
+   * Leave the practice answers out of the result, instead of keeping them apart
+   */
+  drop_practice?: boolean;
+} & {
+  order: string[];
+  /**
+   * Presets rated before the others to practice, which are kept apart in the result
+   */
+  practice_order?: string[];
+};
 export type RatingExperimentResult = {
   name: string;
   time: string;
   observer_id: number;
   note: string;
   presets: Partial<{ [key in string]: Preset }>;
-} & { ratings: OutcomeRating[] };
+  status?: CompletionStatus;
+  /**
+   * Breaks taken during the experiment, which are left out of the durations
+   */
+  pauses?: Pause[];
+  corrections?: Correction[];
+  /**
+   * Why the experiment was exited before every prompt was answered
+   */
+  exit_reason?: string | null;
+} & {
+  /**
+   * One outcome for each prompt shown, in order
+   */
+  ratings: RatingOutcome[];
+  /**
+   * Answers that were undone, see [`SharedExperimentResult::corrections`]
+   */
+  undone?: RatingOutcome[];
+  /**
+   * Outcomes of the practice prompts, empty when the practice answers are dropped
+   */
+  practice?: RatingOutcome[];
+};
 export type RatingExperimentState = {
   experiment_key: string;
   result_key: string;
   current_index: number;
   is_idle: boolean;
+  /**
+   * Identifies the current prompt, answers from the headset must refer to it
+   */
+  prompt_id: PromptId;
+  /**
+   * When the current prompt was sent to the headset
+   */
+  prompted_at: string;
+  /**
+   * When the headset displayed the current prompt, using the clock of the headset
+   */
+  displayed_at: string | null;
+  /**
+   * When the display acknowledgement for the current prompt was received
+   */
+  acknowledged_at: string | null;
+  /**
+   * When the experiment was paused, None while running
+   */
+  paused_at?: string | null;
+  /**
+   * When the gap before the current prompt started, None when it was shown without a gap
+   */
+  gap_started_at?: string | null;
+  /**
+   * Live view the operator set before the experiment started
+   */
+  previous_live_view?: LiveViewState | null;
+  /**
+   * What to show once the experiment is finished or exited
+   */
+  after_experiment?: AfterExperiment;
+  /**
+   * Number of practice prompts at the start of the order
+   */
+  practice_count?: number;
 } & { experiment: RatingExperiment; result: RatingExperimentResult };
+/**
+ * What came of a rating prompt. Untagged, so results saved before timeouts are still read
+ */
+export type RatingOutcome = OutcomeRating | TimeoutRating;
 export type ResultSavedEvent = { result_file_path: string };
 export type ResultWithExperiment = {
   experiment_key: string;
   result: ExperimentResult;
 };
+/**
+ * Sent when the secret is rotated, after which headsets must connect with the new secret
+ */
+export type SecretEvent = { secret: string };
 export type StateEvent = { state: AppState };
+/**
+ * A choice that was not answered before the response timeout
+ */
+export type TimeoutChoice = {
+  a: string;
+  b: string;
+  timed_out_at: string;
+  /**
+   * Whether the choice was shown again at the end
+   */
+  requeued: boolean;
+  timing: PromptTiming;
+};
+/**
+ * What happens to a prompt that was not answered before the response timeout
+ */
+export type TimeoutAction =
+  /**
+   * Go on to the next prompt
+   */
+  | "skip"
+  /**
+   * Show the prompt again at the end, until it is answered
+   */
+  | "requeue";
+/**
+ * A rating that was not answered before the response timeout
+ */
+export type TimeoutRating = {
+  preset: string;
+  timed_out_at: string;
+  /**
+   * Whether the preset was shown again at the end
+   */
+  requeued: boolean;
+  timing: PromptTiming;
+};
 export type TopLevelFolder = "Presets" | "Experiments" | "Results";
+/**
+ * How the headset should animate from the parameters it currently shows to new parameters
+ */
+export type Transition = {
+  /**
+   * Duration of the transition in seconds
+   */
+  duration: number;
+  easing?: Easing;
+};
+/**
+ * An experiment that was left before every prompt was answered, e.g. because the app crashed
+ */
+export type UnfinishedSession = {
+  experiment_key: string;
+  experiment_name: string;
+  result_name: string;
+  observer_id: number;
+  started_at: string;
+  /**
+   * Prompts the observer answered, leaving out the practice and timeouts
+   */
+  answered: number;
+  trial_count: number;
+};
 export type WithKey<T> = { key: string; value: T };
 
 /** tauri-specta globals **/
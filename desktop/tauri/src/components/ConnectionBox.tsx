@@ -1,16 +1,29 @@
 import { QRCodeCanvas } from "qrcode.react";
 import styles from "./ConnectionBox.module.css";
 import xrealGlassesPng from "../assets/xreal-glasses.png";
+import { commands, ConnectedClient } from "../bindings.gen";
 
 interface ConnectionBoxProps {
   qrText: string;
-  isConnected: boolean;
+  /** Name the server is advertised with, which headsets show when discovering controllers */
+  serviceName: string | null;
+  clients: ConnectedClient[];
+}
+
+/** A human readable name for a client, based on what it told about itself */
+function clientName(client: ConnectedClient) {
+  const { model, device_id } = client.identity;
+
+  return model ?? device_id ?? client.remote_address ?? `Client ${client.id}`;
 }
 
 export default function ConnectionBox({
   qrText,
-  isConnected,
+  serviceName,
+  clients,
 }: ConnectionBoxProps) {
+  const isConnected = clients.length > 0;
+
   let qrCode = (
     <div
       style={{
@@ -46,12 +59,26 @@ export default function ConnectionBox({
       {isConnected ? (
         <>
           <img src={xrealGlassesPng} />
-          <p>Connected</p>
+          <p title={clients.map(clientName).join("\n")}>
+            {clients.length > 1 ? `${clients.length} connected` : "Connected"}
+          </p>
+          <button
+            className={styles.revoke}
+            title="Disconnect all headsets by replacing the secret, they must scan the new QR code to reconnect"
+            onClick={() => commands.rotateSecret()}
+          >
+            Disconnect
+          </button>
         </>
       ) : (
         <>
           {qrCode}
           <p>Disconnected</p>
+          {serviceName && (
+            <small className={styles.serviceName} title="Advertised name">
+              {serviceName}
+            </small>
+          )}
         </>
       )}
     </div>
@@ -1,11 +1,12 @@
 import { NavLink } from "react-router-dom";
+import { ConnectedClient } from "../bindings.gen.ts";
 import {
   ROUTE_EXPERIMENTS,
   ROUTE_LIVE_VIEW,
   ROUTE_PRESETS,
   ROUTE_RESULTS,
 } from "../const.ts";
-import { useConnectionQrCode } from "../hooks.ts";
+import { useConnectionQrCode, useServiceName } from "../hooks.ts";
 import ConnectionBox from "./ConnectionBox.tsx";
 import styles from "./Sidebar.module.css";
 
@@ -13,6 +14,8 @@ export interface QrPayload {
   ip: string;
   port: number;
   secret: string;
+  /** SHA-256 fingerprint of the certificate to pin, null when the server uses plain HTTP */
+  fingerprint: string | null;
 }
 
 interface SidebarLinkProps {
@@ -34,16 +37,21 @@ function SidebarLink(props: SidebarLinkProps) {
 }
 
 interface SidebarProps {
-  isConnected: boolean;
+  clients: ConnectedClient[];
 }
 
 export default function Sidebar(props: SidebarProps) {
-  const { isConnected } = props;
+  const { clients } = props;
   const connectionQrCode = useConnectionQrCode();
+  const serviceName = useServiceName();
 
   return (
     <div className={styles.Sidebar}>
-      <ConnectionBox qrText={connectionQrCode} isConnected={isConnected} />
+      <ConnectionBox
+        qrText={connectionQrCode}
+        serviceName={serviceName}
+        clients={clients}
+      />
 
       <nav>
         <SidebarLink to={ROUTE_LIVE_VIEW}>Live View</SidebarLink>
@@ -0,0 +1,69 @@
+import { useEffect, useState } from "react";
+import { commands, UnfinishedSession, WithKey } from "../bindings.gen";
+import Button from "./Button";
+import Popup from "./Popup";
+import styles from "./UnfinishedSessionsPopup.module.css";
+
+/**
+ * Offers to resume or save the experiments that were left unfinished, e.g. because the app crashed.
+ * Shown once at startup, and only when there are unfinished experiments.
+ */
+export default function UnfinishedSessionsPopup() {
+  const [sessions, setSessions] = useState<WithKey<UnfinishedSession>[]>([]);
+  const [isDismissed, setIsDismissed] = useState(false);
+
+  useEffect(() => {
+    commands.getUnfinishedSessions().then(setSessions);
+  }, []);
+
+  if (isDismissed || sessions.length === 0) {
+    return null;
+  }
+
+  const removeSession = (key: string) =>
+    setSessions((sessions) => sessions.filter((session) => session.key !== key));
+
+  return (
+    <Popup title="Unfinished experiments" onClose={() => setIsDismissed(true)}>
+      <p>
+        These experiments were left before every prompt was answered. Resume
+        one from the first unanswered prompt, or save its answers as an
+        incomplete result.
+      </p>
+
+      <div className={styles.sessions}>
+        {sessions.map(({ key, value: session }) => (
+          <div key={key} className={styles.session}>
+            <div>
+              <h3>
+                {session.experiment_name}: {session.result_name}
+              </h3>
+              <p>
+                Observer {session.observer_id}, started{" "}
+                {new Date(session.started_at).toLocaleString()},{" "}
+                {session.answered} of {session.trial_count} answered
+              </p>
+            </div>
+
+            <div className={styles.actions}>
+              <Button
+                onClick={async () => {
+                  await commands.saveIncompleteSession(key);
+                  removeSession(key);
+                }}
+              >
+                Save as incomplete
+              </Button>
+              <Button
+                variant="primary"
+                onClick={() => commands.resumeSession(key)}
+              >
+                Resume
+              </Button>
+            </div>
+          </div>
+        ))}
+      </div>
+    </Popup>
+  );
+}
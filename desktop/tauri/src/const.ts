@@ -1,13 +1,20 @@
+import { Transition } from "./bindings.gen";
+
 const ROUTE_LIVE_VIEW = "/live-view";
 const ROUTE_PRESETS = "/presets";
 const ROUTE_EXPERIMENTS = "/experiments";
 const ROUTE_RESULTS = "/results";
 
 const UNITY_API_PORT = 9001;
+
+// Cross-fade used on the headset when switching between presets
+const PRESET_TRANSITION: Transition = { duration: 0.5, easing: "ease_in_out" };
+
 export {
   ROUTE_EXPERIMENTS,
   ROUTE_LIVE_VIEW,
   ROUTE_PRESETS,
   ROUTE_RESULTS,
+  PRESET_TRANSITION,
   UNITY_API_PORT,
 };
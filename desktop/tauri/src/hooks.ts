@@ -1,6 +1,6 @@
 import { useSuspenseQuery } from "@tanstack/react-query";
 import { use, useEffect, useMemo, useState } from "react";
-import { commands, events } from "./bindings.gen";
+import { commands, ConnectedClient, events } from "./bindings.gen";
 import { QrPayload } from "./components/Sidebar";
 import { UNITY_API_PORT } from "./const";
 import Fuse, { IFuseOptions } from "fuse.js";
@@ -17,47 +17,87 @@ export function useCommand<T>(command: () => Promise<T>) {
 }
 
 /**
- * A hook that tracks the connection status.
- * It listens to connection events and updates state accordingly.
+ * A hook that tracks the headsets connected to the server.
+ * It listens to connected clients events and updates state accordingly.
  *
- * @returns A boolean indicating whether the application is currently connected
+ * @returns The currently connected clients, empty when no headset is connected
  */
-export function useIsConnected() {
-  const [isConnected, setIsConnected] = useState(false);
+export function useConnectedClients() {
+  const [clients, setClients] = useState<ConnectedClient[]>([]);
 
   useEffect(() => {
-    commands.isConnected().then(setIsConnected);
+    commands.getConnectedClients().then(setClients);
 
-    const connectionEventListener = events.connectionEvent.listen((event) => {
-      setIsConnected(event.payload.is_connected);
-    });
+    const clientsEventListener = events.connectedClientsEvent.listen(
+      (event) => {
+        setClients(event.payload.clients);
+      },
+    );
 
     return () => {
       // Remove event listeners
-      connectionEventListener.then((unlisten) => unlisten());
+      clientsEventListener.then((unlisten) => unlisten());
     };
   }, []);
 
-  return isConnected;
+  return clients;
 }
 
 const secretPromise = commands.getSecret();
 const ipAddressPromise = commands.getIpAddress();
+const fingerprintPromise = commands.getCertificateFingerprint();
+const serviceNamePromise = commands.getServiceName();
+
+/**
+ * A hook that returns the name the server is advertised with on the local network.
+ * Headsets list controllers by this name when discovering them.
+ *
+ * @returns The advertised name, or null when the server is not advertised
+ */
+export function useServiceName() {
+  return use(serviceNamePromise);
+}
+
+/**
+ * A hook that tracks the secret headsets authenticate with.
+ * It listens to secret events, so it updates when the secret is rotated.
+ *
+ * @returns The current secret
+ */
+export function useSecret() {
+  const initialSecret = use(secretPromise);
+  const [secret, setSecret] = useState(initialSecret);
+
+  useEffect(() => {
+    const secretEventListener = events.secretEvent.listen((event) => {
+      setSecret(event.payload.secret);
+    });
+
+    return () => {
+      // Remove event listeners
+      secretEventListener.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  return secret;
+}
 
 /**
  * A hook that generates a QR code payload for connection.
- * It combines IP address, port, and secret into a JSON string.
+ * It combines IP address, port, secret and certificate fingerprint into a JSON string.
  *
  * @returns A JSON string containing connection information for QR code generation
  */
 export function useConnectionQrCode() {
-  const secret = use(secretPromise);
+  const secret = useSecret();
   const ipAddress = use(ipAddressPromise);
+  const fingerprint = use(fingerprintPromise);
 
   const qrPayload: QrPayload = {
     ip: ipAddress,
     port: UNITY_API_PORT,
     secret,
+    fingerprint,
   };
 
   const qrText = JSON.stringify(qrPayload);
@@ -1,6 +1,11 @@
 import { useState } from "react";
 import { match } from "ts-pattern";
-import { commands, CurrentPreset, ExperimentState } from "../../bindings.gen";
+import {
+  commands,
+  ConnectedClient,
+  CurrentPreset,
+  ExperimentState,
+} from "../../bindings.gen";
 import Button from "../../components/Button";
 import ConnectionBox from "../../components/ConnectionBox";
 import Popup from "../../components/Popup";
@@ -11,20 +16,32 @@ import { PresetBox } from "./PresetBox";
 
 interface ActiveExperimentProps {
   experimentState: ExperimentState;
-  isConnected: boolean;
+  clients: ConnectedClient[];
 }
 
 function handleExit() {
   const confirmExit = confirm("Are you sure you want to exit the experiment?");
 
-  if (confirmExit) {
-    // Call the command to exit the experiment
-    commands.exitExperiment();
+  if (!confirmExit) {
+    return;
   }
+
+  const savePartialResult = confirm(
+    "Save the answers so far as an aborted result? Cancel discards them.",
+  );
+
+  // The reason is kept in the result, e.g. that the observer felt motion-sick
+  const exitReason = savePartialResult
+    ? prompt("Why was the experiment exited? (optional)")
+    : null;
+
+  // Call the command to exit the experiment
+  commands.exitExperiment(savePartialResult, exitReason).catch(alert);
 }
 
 export default function ActiveExperiment(props: ActiveExperimentProps) {
-  const { experimentState, isConnected } = props;
+  const { experimentState, clients } = props;
+  const isConnected = clients.length > 0;
 
   const connectionQrCode = useConnectionQrCode();
   const [sliderValue, setSldierValue] = useState(1);
@@ -45,6 +62,16 @@ export default function ActiveExperiment(props: ActiveExperimentProps) {
       .exhaustive();
   }
 
+  const isPaused = experimentState.paused_at != null;
+
+  function onTogglePause() {
+    if (isPaused) {
+      commands.resumeExperiment();
+    } else {
+      commands.pauseExperiment();
+    }
+  }
+
   const questionIndex = experimentState.current_index + 1;
 
   const questionAmount = match(experimentState)
@@ -68,6 +95,10 @@ export default function ActiveExperiment(props: ActiveExperimentProps) {
 
   const progessBarWidth = `${(questionIndex / questionAmount) * 100}%`;
 
+  // The practice comes first, and is counted apart from the questions
+  const practiceCount = experimentState.practice_count ?? 0;
+  const isPractice = experimentState.current_index < practiceCount;
+
   const content = match(experimentState)
     .with({ experiment_type: "choice" }, (state) => {
       const choice = state.experiment.choices[state.current_index];
@@ -77,7 +108,7 @@ export default function ActiveExperiment(props: ActiveExperimentProps) {
         const preset = state.experiment.presets[presetKey]!;
 
         function onClick() {
-          if (isSelected) {
+          if (isSelected || isPaused) {
             return;
           }
 
@@ -127,6 +158,7 @@ export default function ActiveExperiment(props: ActiveExperimentProps) {
     .exhaustive();
 
   const isDisabled = experimentState.is_idle;
+  const isAnswerDisabled = isDisabled || isPaused;
 
   return (
     <>
@@ -147,10 +179,14 @@ export default function ActiveExperiment(props: ActiveExperimentProps) {
             </div>
 
             <div>
-              <h1 className={styles.title}>Question</h1>
+              <h1 className={styles.title}>
+                {isPractice ? "Practice" : "Question"}
+              </h1>
 
               <h2 className={styles.subtitle}>
-                {questionIndex}/{questionAmount}
+                {isPractice
+                  ? `${questionIndex}/${practiceCount}`
+                  : `${questionIndex - practiceCount}/${questionAmount - practiceCount}`}
               </h2>
             </div>
           </header>
@@ -161,8 +197,29 @@ export default function ActiveExperiment(props: ActiveExperimentProps) {
             <Button variant="danger" onClick={handleExit} disabled={isDisabled}>
               Exit
             </Button>
+            <Button variant="secondary" onClick={onTogglePause}>
+              {isPaused ? "Resume" : "Pause"}
+            </Button>
+            <Button
+              variant="secondary"
+              onClick={() => commands.undoAnswer()}
+              disabled={experimentState.current_index === 0}
+            >
+              Undo answer
+            </Button>
+            <Button
+              variant="secondary"
+              onClick={() => commands.repeatLater()}
+              disabled={isAnswerDisabled}
+            >
+              Repeat later
+            </Button>
             {/* <Button variant="primary">Choose B</Button> */}
-            <Button variant="primary" onClick={onSubmit} disabled={isDisabled}>
+            <Button
+              variant="primary"
+              onClick={onSubmit}
+              disabled={isAnswerDisabled}
+            >
               {confirmText}
             </Button>
           </footer>
@@ -172,10 +229,7 @@ export default function ActiveExperiment(props: ActiveExperimentProps) {
       {!isConnected && (
         <Popup title={"Reconnect"}>
           <div className={styles.popupContent}>
-            <ConnectionBox
-              qrText={connectionQrCode}
-              isConnected={isConnected}
-            />
+            <ConnectionBox qrText={connectionQrCode} clients={clients} />
 
             <Button variant="danger" onClick={handleExit}>
               Exit Experiment
@@ -21,7 +21,7 @@ export function PresetBox(props: PresetBoxProps) {
 
   const parameterStates = parameters.map((parameter) => ({
     ...parameter,
-    value: preset.parameters[parameter.key],
+    value: preset.parameters[parameter.key] ?? parameter.default,
   }));
 
   return (
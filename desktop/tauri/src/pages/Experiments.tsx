@@ -4,7 +4,14 @@ import { Layout } from "../components/Layout";
 import { PlayIcon, TrashIcon } from "@heroicons/react/24/outline";
 import { SelectInstance } from "react-select";
 import { match } from "ts-pattern";
-import { commands, Experiment, Preset, WithKey } from "../bindings.gen";
+import {
+  AfterExperiment,
+  commands,
+  Experiment,
+  Mask,
+  Preset,
+  WithKey,
+} from "../bindings.gen";
 import Button from "../components/Button";
 import { Checkbox, Input, Label, Select, TextArea } from "../components/Input";
 import Popup from "../components/Popup";
@@ -13,17 +20,19 @@ import styles from "./Experiments.module.css";
 
 interface ExperimentCardProps {
   experiment: WithKey<Experiment>;
+  presets: WithKey<Preset>[];
   onDelete: () => void;
   onStart: (
     resultName: string,
     observerId: number,
     note: string,
-    randomzie: boolean
+    randomzie: boolean,
+    afterExperiment: AfterExperiment
   ) => void;
 }
 
 function ExperimentCard(props: ExperimentCardProps) {
-  const { experiment, onDelete, onStart } = props;
+  const { experiment, presets, onDelete, onStart } = props;
 
   const [showCreatePopup, setShowCreatePopup] = useState(false);
 
@@ -31,6 +40,16 @@ function ExperimentCard(props: ExperimentCardProps) {
   const observerIdRef = useRef<HTMLInputElement>(null);
   const noteRef = useRef<HTMLTextAreaElement>(null);
   const randomizeRef = useRef<HTMLInputElement>(null);
+  const [after, setAfter] = useState<Option>(previousLiveViewOption);
+
+  const afterOptions = [
+    previousLiveViewOption,
+    idleAfterOption,
+    ...presets.map((preset) => ({
+      value: preset.key,
+      label: preset.value.name,
+    })),
+  ];
 
   useEffect(() => {
     commands.setIdleMode();
@@ -109,6 +128,15 @@ function ExperimentCard(props: ExperimentCardProps) {
               <Checkbox ref={randomizeRef} defaultChecked={false} />
               Randomize the order
             </Label>
+
+            <Label>
+              Shown after the experiment
+              <Select
+                options={afterOptions}
+                value={after}
+                onChange={(value) => setAfter(value!)}
+              />
+            </Label>
           </div>
 
           <Button
@@ -118,6 +146,12 @@ function ExperimentCard(props: ExperimentCardProps) {
               const observerId = observerIdRef.current?.value;
               const note = noteRef.current?.value;
               const randomize = randomizeRef.current?.checked ?? false;
+              const afterExperiment: AfterExperiment = match(after.value)
+                .with(previousLiveViewOption.value, () => ({
+                  kind: "previous_live_view" as const,
+                }))
+                .with(idleAfterOption.value, () => ({ kind: "idle" as const }))
+                .otherwise((preset) => ({ kind: "preset" as const, preset }));
 
               if (!resultName) {
                 alert("Experiment result name is required");
@@ -139,7 +173,8 @@ function ExperimentCard(props: ExperimentCardProps) {
                   resultName,
                   parseInt(observerId),
                   note || "",
-                  randomize
+                  randomize,
+                  afterExperiment
                 );
 
                 setShowCreatePopup(false);
@@ -175,12 +210,39 @@ const options = [
   } as const,
 ];
 
+// Preset keys are slugs, so these values cannot clash with a preset
+const noMaskOption: Option = { value: "", label: "Nothing (idle)" };
+const previousLiveViewOption: Option = {
+  value: "",
+  label: "The live view from before",
+};
+const idleAfterOption: Option = { value: "\0idle", label: "Nothing (idle)" };
+const defaultMaskOption: Option = {
+  value: "\0default",
+  label: "Default parameters",
+};
+
 function CreateExperimentPopup(props: CreateExperimentPopupProps) {
   const { onClose, presets } = props;
   const [experimentType, setExperimentType] = useState(options[0]);
 
   const experimentNameRef = useRef<HTMLInputElement>(null);
   const presetsRef = useRef<SelectInstance<Option, true>>(null);
+  const interTrialIntervalRef = useRef<HTMLInputElement>(null);
+  const [mask, setMask] = useState<Option>(noMaskOption);
+  const responseTimeoutRef = useRef<HTMLInputElement>(null);
+  const requeueRef = useRef<HTMLInputElement>(null);
+  const practicePresetsRef = useRef<SelectInstance<Option, true>>(null);
+  const dropPracticeRef = useRef<HTMLInputElement>(null);
+
+  const maskOptions = [
+    noMaskOption,
+    defaultMaskOption,
+    ...presets.map((preset) => ({
+      value: preset.key,
+      label: preset.value.name,
+    })),
+  ];
 
   function createExperiment() {
     const experimentName = experimentNameRef.current!.value;
@@ -199,27 +261,71 @@ function CreateExperimentPopup(props: CreateExperimentPopupProps) {
       return;
     }
 
+    const interTrialInterval = interTrialIntervalRef.current!.valueAsNumber;
+
+    if (!(interTrialInterval >= 0)) {
+      alert("Seconds between prompts must be zero or more");
+      return;
+    }
+
+    // An empty field means there is no time limit
+    const responseTimeout = responseTimeoutRef.current!.value
+      ? responseTimeoutRef.current!.valueAsNumber
+      : null;
+
+    if (responseTimeout !== null && !(responseTimeout > 0)) {
+      alert("Seconds to answer must be more than zero");
+      return;
+    }
+
+    const experimentMask: Mask | null = match(mask.value)
+      .with(noMaskOption.value, () => null)
+      .with(defaultMaskOption.value, () => ({ kind: "default" as const }))
+      .otherwise((preset) => ({ kind: "preset" as const, preset }));
+
+    const practicePresets = practicePresetsRef
+      .current!.getValue()
+      .map((preset) => preset.value);
+
+    if (experimentType.value === "choice" && practicePresets.length === 1) {
+      alert("At least two practice presets are required for a choice");
+      return;
+    }
+
+    const shared = {
+      name: experimentName,
+      presets: selectedPresets,
+      inter_trial_interval: interTrialInterval,
+      mask: experimentMask,
+      response_timeout: responseTimeout,
+      on_timeout: requeueRef.current!.checked
+        ? ("requeue" as const)
+        : ("skip" as const),
+      drop_practice: dropPracticeRef.current!.checked,
+    };
+
     function onSuccess(path: string) {
       onClose();
     }
 
     match(experimentType.value)
       .with("choice", () => {
-        const combinations = selectedPresets.flatMap((presetA) =>
-          selectedPresets
-            .filter((presetB) => presetA !== presetB)
-            .map((presetB) => ({
-              a: presetA,
-              b: presetB,
-            }))
-        );
+        const combinationsOf = (presets: string[]) =>
+          presets.flatMap((presetA) =>
+            presets
+              .filter((presetB) => presetA !== presetB)
+              .map((presetB) => ({
+                a: presetA,
+                b: presetB,
+              }))
+          );
 
         commands
           .createExperiment({
+            ...shared,
             experiment_type: "choice",
-            name: experimentName,
-            presets: selectedPresets,
-            choices: combinations,
+            choices: combinationsOf(selectedPresets),
+            practice_choices: combinationsOf(practicePresets),
           })
           .then(onSuccess)
           .catch(alert);
@@ -227,10 +333,10 @@ function CreateExperimentPopup(props: CreateExperimentPopupProps) {
       .with("rating", () => {
         commands
           .createExperiment({
+            ...shared,
             experiment_type: "rating",
-            name: experimentName,
-            presets: selectedPresets,
             order: selectedPresets,
+            practice_order: practicePresets,
           })
           .then(onSuccess)
           .catch(alert);
@@ -276,6 +382,57 @@ function CreateExperimentPopup(props: CreateExperimentPopupProps) {
           )}
         </Label>
 
+        <Label>
+          Seconds between prompts
+          <Input
+            ref={interTrialIntervalRef}
+            type="number"
+            min={0}
+            step={0.1}
+            defaultValue={1}
+          />
+        </Label>
+
+        <Label>
+          Shown between prompts
+          <Select
+            options={maskOptions}
+            value={mask}
+            onChange={(value) => setMask(value!)}
+          />
+        </Label>
+
+        <Label>
+          Seconds to answer, empty for no limit
+          <Input ref={responseTimeoutRef} type="number" min={0} step={1} />
+        </Label>
+
+        <Label horizontal>
+          <Checkbox ref={requeueRef} defaultChecked={false} />
+          Show prompts that were not answered in time again at the end
+        </Label>
+
+        <Label>
+          Practice presets, shown first
+          <Select
+            ref={practicePresetsRef}
+            isMulti
+            closeMenuOnSelect={false}
+            name="practicePresets"
+            options={presets.map((preset) => ({
+              value: preset.key,
+              label: preset.value.name,
+            }))}
+            className="basic-multi-select"
+            classNamePrefix="select"
+          />
+        </Label>
+
+        <Label horizontal>
+          <Checkbox ref={dropPracticeRef} defaultChecked={false} />
+          Leave the practice answers out of the result
+        </Label>
+
         <Button
           variant="primary"
           onClick={createExperiment}
@@ -323,13 +480,21 @@ export default function ExperimentsPage() {
             <ExperimentCard
               key={experiment.key}
               experiment={experiment}
-              onStart={(resultName, observerId, note, randomize) =>
+              presets={presets}
+              onStart={(
+                resultName,
+                observerId,
+                note,
+                randomize,
+                afterExperiment
+              ) =>
                 commands.startExperiment(
                   experiment.key,
                   resultName,
                   observerId,
                   note,
-                  randomize
+                  randomize,
+                  afterExperiment
                 )
               }
               onDelete={async () => {
@@ -23,7 +23,7 @@ function useLiveParameters() {
   const [parameterStates, setParameterStates] = useImmer(() =>
     parameters.map((parameter) => ({
       ...parameter,
-      value: defaultParameters[parameter.key],
+      value: defaultParameters[parameter.key] ?? parameter.default,
     })),
   );
 
@@ -33,7 +33,7 @@ function useLiveParameters() {
       parameterStates.map((parameter) => [parameter.key, parameter.value]),
     ) as Record<ParameterKey, number>;
 
-    commands.setLiveMode(parameters);
+    commands.setLiveMode(parameters, null);
   }, [parameterStates]);
 
   // Add a onChange function to each parameter state
@@ -53,7 +53,7 @@ function useLiveParameters() {
     set(parameterValues: ParameterValues) {
       setParameterStates((parameters) => {
         for (const parameter of parameters) {
-          parameter.value = parameterValues[parameter.key];
+          parameter.value = parameterValues[parameter.key] ?? parameter.default;
         }
       });
     },
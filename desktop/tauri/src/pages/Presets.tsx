@@ -2,6 +2,7 @@ import { TrashIcon } from "@heroicons/react/24/outline";
 import classNames from "classnames";
 import { useEffect, useState } from "react";
 import { commands, WithKey, type Preset } from "../bindings.gen.ts";
+import { PRESET_TRANSITION } from "../const.ts";
 import Button from "../components/Button.tsx";
 import { ContentBox } from "../components/ContentBox";
 import { Input } from "../components/Input.tsx";
@@ -33,7 +34,7 @@ function PresetPreview(props: PresetPreviewProps) {
 
   const parameterStates = parameters.map((parameter) => ({
     ...parameter,
-    value: preset.parameters[parameter.key],
+    value: preset.parameters[parameter.key] ?? parameter.default,
   }));
 
   return <SliderCollection parameters={parameterStates} />;
@@ -52,7 +53,7 @@ export default function PresetsPage() {
 
   useEffect(() => {
     if (selectedPreset) {
-      commands.setLiveMode(selectedPreset.value.parameters);
+      commands.setLiveMode(selectedPreset.value.parameters, PRESET_TRANSITION);
     }
   }, [selectedPreset]);
 
@@ -13,6 +13,10 @@ import {
   ResultWithExperiment,
   WithKey,
   ExperimentResult,
+  TimeoutChoice,
+  TimeoutRating,
+  ChoiceOutcome,
+  RatingOutcome,
 } from "../bindings.gen";
 import Button from "../components/Button";
 import { Input, Label } from "../components/Input";
@@ -21,6 +25,20 @@ import { useCommand, useFuse } from "../hooks";
 import styles from "./Results.module.css";
 import { match } from "ts-pattern";
 
+function statusText(result: ExperimentResult) {
+  const status = match(result.status ?? "complete")
+    .with("complete", () => "Complete")
+    .with("incomplete", () => "Incomplete")
+    .with("aborted", () => "Aborted")
+    .exhaustive();
+
+  return result.exit_reason ? `${status} (${result.exit_reason})` : status;
+}
+
+function timeoutText(timeout: TimeoutChoice | TimeoutRating) {
+  return timeout.requeued ? "Timed out, shown again" : "Timed out";
+}
+
 interface ResultCardProps {
   result: WithKey<ResultWithExperiment>;
   onDelete: () => void;
@@ -54,6 +72,7 @@ function ResultCard(props: ResultCardProps) {
         <p>Date: {date}</p>
         <p>Observer ID: {result.observer_id}</p>
         <p>Items: {itemCount}</p>
+        <p>Status: {statusText(result)}</p>
       </div>
       <div className={styles.resultCardBottom}>
         {/* Delete button */}
@@ -82,6 +101,77 @@ interface ResultDetailsProps {
   onClose: () => void;
 }
 
+function RatingTable(props: { ratings: RatingOutcome[] }) {
+  return (
+    <table className={styles.resultDetailTable}>
+      <thead>
+        <tr>
+          <th>Preset</th>
+          <th>Rating</th>
+          <th>Time</th>
+          <th>Duration (ms)</th>
+        </tr>
+      </thead>
+      <tbody>
+        {props.ratings.map((rating, idx) =>
+          "rank" in rating ? (
+            <tr key={idx}>
+              <td>{rating.preset}</td>
+              <td>{rating.rank}</td>
+              <td>{new Date(rating.time).toLocaleTimeString()}</td>
+              <td>{rating.duration}</td>
+            </tr>
+          ) : (
+            <tr key={idx}>
+              <td>{rating.preset}</td>
+              <td>{timeoutText(rating)}</td>
+              <td>{new Date(rating.timed_out_at).toLocaleTimeString()}</td>
+              <td>-</td>
+            </tr>
+          )
+        )}
+      </tbody>
+    </table>
+  );
+}
+
+function ChoiceTable(props: { choices: ChoiceOutcome[] }) {
+  return (
+    <table className={styles.resultDetailTable}>
+      <thead>
+        <tr>
+          <th>Option A</th>
+          <th>Option B</th>
+          <th>Selected</th>
+          <th>Time</th>
+          <th>Duration (ms)</th>
+        </tr>
+      </thead>
+      <tbody>
+        {props.choices.map((choice, idx) =>
+          "selected" in choice ? (
+            <tr key={idx}>
+              <td>{choice.a}</td>
+              <td>{choice.b}</td>
+              <td>{choice.selected}</td>
+              <td>{new Date(choice.time).toLocaleTimeString()}</td>
+              <td>{choice.duration}</td>
+            </tr>
+          ) : (
+            <tr key={idx}>
+              <td>{choice.a}</td>
+              <td>{choice.b}</td>
+              <td>{timeoutText(choice)}</td>
+              <td>{new Date(choice.timed_out_at).toLocaleTimeString()}</td>
+              <td>-</td>
+            </tr>
+          )
+        )}
+      </tbody>
+    </table>
+  );
+}
+
 function ResultDetailsPopup(props: ResultDetailsProps) {
   const { result, onClose } = props;
 
@@ -93,53 +183,27 @@ function ResultDetailsPopup(props: ResultDetailsProps) {
     .with({ experiment_type: "rating" }, (result) => (
       <>
         <h3>Rating Results</h3>
-        <table className={styles.resultDetailTable}>
-          <thead>
-            <tr>
-              <th>Preset</th>
-              <th>Rating</th>
-              <th>Time</th>
-              <th>Duration (ms)</th>
-            </tr>
-          </thead>
-          <tbody>
-            {result.ratings.map((rating, idx) => (
-              <tr key={idx}>
-                <td>{rating.preset}</td>
-                <td>{rating.rank}</td>
-                <td>{new Date(rating.time).toLocaleTimeString()}</td>
-                <td>{rating.duration}</td>
-              </tr>
-            ))}
-          </tbody>
-        </table>
+        <RatingTable ratings={result.ratings} />
+
+        {result.practice && result.practice.length > 0 && (
+          <>
+            <h3>Practice</h3>
+            <RatingTable ratings={result.practice} />
+          </>
+        )}
       </>
     ))
     .with({ experiment_type: "choice" }, (result) => (
       <>
         <h3>Choice Results</h3>
-        <table className={styles.resultDetailTable}>
-          <thead>
-            <tr>
-              <th>Option A</th>
-              <th>Option B</th>
-              <th>Selected</th>
-              <th>Time</th>
-              <th>Duration (ms)</th>
-            </tr>
-          </thead>
-          <tbody>
-            {result.choices.map((choice, idx) => (
-              <tr key={idx}>
-                <td>{choice.a}</td>
-                <td>{choice.b}</td>
-                <td>{choice.selected}</td>
-                <td>{new Date(choice.time).toLocaleTimeString()}</td>
-                <td>{choice.duration}</td>
-              </tr>
-            ))}
-          </tbody>
-        </table>
+        <ChoiceTable choices={result.choices} />
+
+        {result.practice && result.practice.length > 0 && (
+          <>
+            <h3>Practice</h3>
+            <ChoiceTable choices={result.practice} />
+          </>
+        )}
       </>
     ))
     .exhaustive();
@@ -157,6 +221,7 @@ function ResultDetailsPopup(props: ResultDetailsProps) {
             Date: {date} {time}
           </p>
           {result.value.result.note && <p>Note: {result.value.result.note}</p>}
+          <p>Status: {statusText(result.value.result)}</p>
         </div>
 
         <div className={styles.resultDetailContent}>{table}</div>
@@ -16,6 +16,8 @@ namespace NeuroViz
         [SerializeField] private Slider ratingSlider;
         [SerializeField] private Button confirmButton;
         [SerializeField] private TMPro.TMP_Text ratingText;
+        [SerializeField] private TMPro.TMP_Text countdownText;
+        [SerializeField] private GameObject practiceLabel;
 
         private CanvasGroup canvasGroup;
         private ConnectedScene connectedScene;
@@ -23,6 +25,9 @@ namespace NeuroViz
         private UnityState.Experiment state;
         private int ratingValue = 1;
 
+        // When the prompt must be answered by, in Time.time, null without a deadline
+        private float? answerBy;
+
         private void Start()
         {
             canvasGroup = GetComponent<CanvasGroup>();
@@ -59,12 +64,25 @@ namespace NeuroViz
         void HandleStateChange(UnityState state)
         {
             var isEnabled = false;
+            var wasEnabled = canvasGroup.interactable;
 
             if (state is UnityState.Experiment experiment)
             {
                 isEnabled = true;
+
+                // The deadline starts when a new prompt is shown, or when the same prompt is shown again after a pause
+                if (!wasEnabled || this.state?.Prompt.PromptId != experiment.Prompt.PromptId)
+                {
+                    var timeout = experiment.Prompt.ResponseTimeout;
+                    answerBy = timeout.HasValue ? Time.time + (float)timeout.Value : null;
+                }
+
                 this.state = experiment;
             }
+            else
+            {
+                answerBy = null;
+            }
 
             canvasGroup.interactable = isEnabled;
             canvasGroup.alpha = isEnabled ? 1 : 0;
@@ -75,6 +93,8 @@ namespace NeuroViz
             var parameters = prompt.Parameters;
             var experimentType = prompt.ExperimentType;
 
+            if (practiceLabel != null) practiceLabel.SetActive(isEnabled && prompt.Practice);
+
             switch (experimentType)
             {
                 case ExperimentType.Choice:
@@ -88,12 +108,21 @@ namespace NeuroViz
             }
         }
 
+        private void Update()
+        {
+            if (countdownText == null) return;
+
+            countdownText.text = answerBy.HasValue
+                ? Math.Max(0, Math.Ceiling(answerBy.Value - Time.time)).ToString()
+                : "";
+        }
+
         private void HandleSwapButtonClick()
         {
             if (XREALVirtualController.Singleton != null)
                 XREALVirtualController.Singleton.Controller.SendHapticImpulse(0, 0.25f, 0.15f);
 
-            StartCoroutine(connectedScene.Swap());
+            StartCoroutine(connectedScene.Swap(state.Prompt));
         }
 
         private void HandleConfirmButtonClick()
@@ -119,7 +148,7 @@ namespace NeuroViz
             if (XREALVirtualController.Singleton != null)
                 XREALVirtualController.Singleton.Controller.SendHapticImpulse(0, 0.4f, 0.25f);
 
-            StartCoroutine(connectedScene.Answer(answer));
+            StartCoroutine(connectedScene.Answer(state.Prompt, answer));
         }
 
         private void HandleRatingSliderValueChanged(float value)
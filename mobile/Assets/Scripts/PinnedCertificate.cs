@@ -0,0 +1,52 @@
+﻿using System;
+using System.Net.Http;
+using System.Security.Cryptography;
+using UnityEngine.Networking;
+
+namespace NeuroViz
+{
+    // Trusts only the certificate whose SHA-256 fingerprint matches the one from the QR code.
+    // The controller uses a self-signed certificate, so no certificate authority can vouch for it
+    public static class PinnedCertificate
+    {
+        // SHA-256 fingerprint of a DER encoded certificate as lowercase hex, like the controller shows it
+        public static string Fingerprint(byte[] certificate)
+        {
+            using var sha256 = SHA256.Create();
+            var hash = sha256.ComputeHash(certificate);
+
+            return BitConverter.ToString(hash).Replace("-", "").ToLowerInvariant();
+        }
+
+        public static bool Matches(byte[] certificate, string fingerprint)
+        {
+            return certificate != null && Fingerprint(certificate) == fingerprint.ToLowerInvariant();
+        }
+
+        // Handler for HttpClient, used by the event source
+        public static HttpMessageHandler CreateHttpHandler(string fingerprint)
+        {
+            return new HttpClientHandler
+            {
+                ServerCertificateCustomValidationCallback = (message, certificate, chain, errors) =>
+                    certificate != null && Matches(certificate.GetRawCertData(), fingerprint)
+            };
+        }
+    }
+
+    // Handler for UnityWebRequest, used for answers, swaps and display acknowledgements
+    public class PinnedCertificateHandler : CertificateHandler
+    {
+        private readonly string fingerprint;
+
+        public PinnedCertificateHandler(string fingerprint)
+        {
+            this.fingerprint = fingerprint;
+        }
+
+        protected override bool ValidateCertificate(byte[] certificateData)
+        {
+            return PinnedCertificate.Matches(certificateData, fingerprint);
+        }
+    }
+}
@@ -11,6 +11,9 @@ namespace NeuroViz.Scenes.Connected
 
         private UnityState.Experiment state;
 
+        // The prompt whose parameters are shown, null until the first state change after the scene is enabled
+        private string shownPromptId;
+
         // Opacity
         // Border
         // Xray
@@ -18,6 +21,8 @@ namespace NeuroViz.Scenes.Connected
 
         private void OnEnable()
         {
+            shownPromptId = null;
+
             if (connectedScene.State is UnityState.Experiment experiment)
             {
                 state = experiment;
@@ -39,7 +44,17 @@ namespace NeuroViz.Scenes.Connected
             }
 
             var prompt = this.state.Prompt;
-            updateParameters.SetParameters(prompt.Parameters);
+
+            // The transition animates swaps within a prompt, a new prompt is shown right away
+            var transition = prompt.PromptId == shownPromptId ? prompt.Transition : null;
+            updateParameters.SetParameters(prompt.Parameters, transition);
+
+            if (prompt.PromptId != shownPromptId)
+            {
+                StartCoroutine(connectedScene.Displayed(prompt));
+            }
+
+            shownPromptId = prompt.PromptId;
         }
     }
 }
\ No newline at end of file
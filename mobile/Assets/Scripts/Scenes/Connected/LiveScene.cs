@@ -37,7 +37,7 @@ namespace NeuroViz.Scenes.Connected
                 this.state = live;
             }
 
-            updateParameters.SetParameters(this.state.Parameters);
+            updateParameters.SetParameters(this.state.Parameters, this.state.Transition);
         }
     }
 }
\ No newline at end of file
@@ -5,6 +5,7 @@ using System.Net;
 using System.Net.Http;
 using System.Security.Authentication;
 using System.Text.Json;
+using System.Text.Json.Nodes;
 using System.Text.Json.Serialization;
 using System.Threading.Tasks;
 using EvtSource;
@@ -24,6 +25,40 @@ public class RenderParameters
     public float LightTemperature { get; set; }
 }
 
+[JsonConverter(typeof(JsonStringEnumConverter))]
+public enum Easing
+{
+    [JsonStringEnumMemberName("linear")] Linear,
+    [JsonStringEnumMemberName("ease_in")] EaseIn,
+    [JsonStringEnumMemberName("ease_out")] EaseOut,
+    [JsonStringEnumMemberName("ease_in_out")] EaseInOut
+}
+
+// How to animate from the parameters currently shown to new parameters
+public class Transition
+{
+    // Duration of the transition in seconds
+    public double Duration { get; set; }
+    public Easing Easing { get; set; } = Easing.Linear;
+
+    // Eased progress of the transition after elapsed seconds, between 0 and 1, matching the desktop
+    public float Progress(float elapsed)
+    {
+        if (Duration <= 0) return 1f;
+
+        var t = Mathf.Clamp01(elapsed / (float)Duration);
+
+        return Easing switch
+        {
+            Easing.EaseIn => t * t,
+            Easing.EaseOut => t * (2f - t),
+            Easing.EaseInOut when t < 0.5f => 2f * t * t,
+            Easing.EaseInOut => -1f + (4f - 2f * t) * t,
+            _ => t
+        };
+    }
+}
+
 [JsonConverter(typeof(JsonStringEnumConverter))]
 public enum ExperimentType
 {
@@ -33,8 +68,13 @@ public enum ExperimentType
 
 public struct ExperimentPrompt
 {
+    public string PromptId { get; set; }
+    public int TrialIndex { get; set; }
     public ExperimentType ExperimentType { get; set; }
     public RenderParameters Parameters { get; set; }
+    [CanBeNull] public Transition Transition { get; set; }
+    public double? ResponseTimeout { get; set; }
+    public bool Practice { get; set; }
 }
 
 public static class UnityStateKind
@@ -62,6 +102,7 @@ public abstract class UnityState
     {
         public override string Kind => UnityStateKind.Live;
         public RenderParameters Parameters { get; set; }
+        [CanBeNull] public Transition Transition { get; set; }
     }
 
     public sealed class Experiment : UnityState
@@ -105,6 +146,7 @@ namespace NeuroViz.Scenes
         [SerializeField] public string ip;
         [SerializeField] public int port;
         [SerializeField] public string secret;
+        [SerializeField] public string fingerprint;
 
         [SerializeField] private ScanScene scanScene;
 
@@ -120,14 +162,21 @@ namespace NeuroViz.Scenes
         private bool isDisconnected = false;
 
         public UnityState State => state;
+
+        // The controller serves HTTPS with a pinned certificate when the QR code has a fingerprint
+        private bool UsesTls => !string.IsNullOrEmpty(fingerprint);
+        private string BaseUrl => $"{(UsesTls ? "https" : "http")}://{ip}:{port}";
         public event Action<UnityState> OnStateChanged;
 
         private void OnEnable()
         {
-            // Subscribe to updates from the controller application
-            var url = $"http://{ip}:{port}/state/subscribe?secret={secret}";
-            Debug.Log($"Starting event source at: {url}");
-            eventSource = new EventSourceReader(new Uri(url));
+            // Subscribe to updates from the controller application.
+            // EventSource cannot set headers, so the secret is sent as an access token in the query
+            var url = $"{BaseUrl}/state/subscribe?access_token={Uri.EscapeDataString(secret)}";
+            Debug.Log($"Starting event source at: {BaseUrl}/state/subscribe");
+            eventSource = UsesTls
+                ? new EventSourceReader(new Uri(url), PinnedCertificate.CreateHttpHandler(fingerprint))
+                : new EventSourceReader(new Uri(url));
             eventSource.Start();
 
             eventSource.MessageReceived += (sender, e) => HandleEvent(e);
@@ -192,11 +241,36 @@ namespace NeuroViz.Scenes
             }
         }
 
-        public IEnumerator Swap()
+        // A request to the controller, authenticated with the secret and pinned to its certificate
+        private UnityWebRequest Post(string url, string json)
+        {
+            var www = UnityWebRequest.Post(url, json, "application/json");
+            www.SetRequestHeader("Authorization", $"Bearer {secret}");
+
+            if (UsesTls)
+            {
+                www.certificateHandler = new PinnedCertificateHandler(fingerprint);
+            }
+
+            return www;
+        }
+
+        // Serialize the reference to the prompt, which every answer and swap must include
+        private static JsonObject PromptReference(ExperimentPrompt prompt)
         {
-            var url = $"http://{ip}:{port}/experiment/swap?secret={secret}";
+            return new JsonObject
+            {
+                ["prompt_id"] = prompt.PromptId,
+                ["trial_index"] = prompt.TrialIndex,
+            };
+        }
 
-            using var www = UnityWebRequest.PostWwwForm(url, "");
+        public IEnumerator Swap(ExperimentPrompt prompt)
+        {
+            var url = $"{BaseUrl}/experiment/swap";
+            var json = PromptReference(prompt).ToJsonString();
+
+            using var www = Post(url, json);
             yield return www.SendWebRequest();
 
             if (www.result != UnityWebRequest.Result.Success)
@@ -209,14 +283,42 @@ namespace NeuroViz.Scenes
             }
         }
 
-        public IEnumerator Answer(ExperimentAnswer answer)
+        // Acknowledge that the prompt is visible, so response times are measured from when the observer could see it
+        public IEnumerator Displayed(ExperimentPrompt prompt)
         {
-            var url = $"http://{ip}:{port}/experiment/answer?secret={secret}";
+            // Wait until the frame with the new parameters is rendered
+            yield return new WaitForEndOfFrame();
+
+            var url = $"{BaseUrl}/experiment/displayed";
+            var body = PromptReference(prompt);
+            body["displayed_at"] = DateTimeOffset.Now.ToString("o");
+
+            var json = body.ToJsonString();
+
+            using var www = Post(url, json);
+            yield return www.SendWebRequest();
+
+            if (www.result != UnityWebRequest.Result.Success)
+            {
+                Debug.LogError(www.error);
+            }
+        }
+
+        public IEnumerator Answer(ExperimentPrompt prompt, ExperimentAnswer answer)
+        {
+            var url = $"{BaseUrl}/experiment/answer";
             var options = new JsonSerializerOptions { PropertyNamingPolicy = JsonNamingPolicy.SnakeCaseLower, };
-            var json = JsonSerializer.Serialize(answer, options);
+
+            var body = PromptReference(prompt);
+            foreach (var (key, value) in JsonSerializer.SerializeToNode(answer, options)!.AsObject())
+            {
+                body[key] = value?.DeepClone();
+            }
+
+            var json = body.ToJsonString();
 
 
-            using var www = UnityWebRequest.Post(url, json, "application/json");
+            using var www = Post(url, json);
             yield return www.SendWebRequest();
 
             if (www.result != UnityWebRequest.Result.Success)
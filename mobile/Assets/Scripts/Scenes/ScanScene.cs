@@ -9,6 +9,8 @@ namespace NeuroViz.Scenes
         public string ip;
         public int port;
         public string secret;
+        // SHA-256 fingerprint of the certificate to pin, empty when the controller uses plain HTTP
+        public string fingerprint;
     }
 
     public class ScanScene : MonoBehaviour
@@ -83,6 +85,7 @@ namespace NeuroViz.Scenes
                 connectedScene.ip = foundQrPayload.Value.ip;
                 connectedScene.port = foundQrPayload.Value.port;
                 connectedScene.secret = foundQrPayload.Value.secret;
+                connectedScene.fingerprint = foundQrPayload.Value.fingerprint;
                 foundQrPayload = null;
                 connectedScene.gameObject.SetActive(true);
             }
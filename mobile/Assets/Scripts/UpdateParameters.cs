@@ -28,17 +28,26 @@ namespace NeuroViz
 
         [SerializeField] private new Light light;
 
+        // The transition in progress, null when the parameters are not animating
+        private Transition transition;
+        private RenderParameters transitionFrom;
+        private RenderParameters transitionTo;
+        private float transitionElapsed;
 
-        public void SetParameters(RenderParameters parameters)
+        // Show the parameters, easing from the current ones when there is a transition
+        public void SetParameters(RenderParameters parameters, Transition transition = null)
         {
-            transparency = parameters.Transparency;
-            glow = parameters.Glow;
-            smoothness = parameters.Smoothness;
-            emission = parameters.Emission;
-            lightIntensity = parameters.LightIntensity;
-            lightTemperature = parameters.LightTemperature;
+            if (transition == null || transition.Duration <= 0)
+            {
+                this.transition = null;
+                ApplyParameters(parameters);
+                return;
+            }
 
-            HandlePropertiesEdited();
+            this.transition = transition;
+            transitionFrom = CurrentParameters();
+            transitionTo = parameters;
+            transitionElapsed = 0f;
         }
 
         private void OnEnable()
@@ -46,13 +55,64 @@ namespace NeuroViz
             HandlePropertiesEdited();
         }
 
-        // Update parameters while in editor mode
-#if UNITY_EDITOR
         private void Update()
         {
+            if (transition != null)
+            {
+                transitionElapsed += Time.deltaTime;
+
+                var progress = transition.Progress(transitionElapsed);
+                ApplyParameters(Interpolate(transitionFrom, transitionTo, progress));
+
+                if (transitionElapsed >= transition.Duration)
+                {
+                    transition = null;
+                }
+            }
+
+            // Update parameters while in editor mode
+#if UNITY_EDITOR
             HandlePropertiesEdited();
-        }
 #endif
+        }
+
+        private RenderParameters CurrentParameters()
+        {
+            return new RenderParameters
+            {
+                Transparency = transparency,
+                Glow = glow,
+                Smoothness = smoothness,
+                Emission = emission,
+                LightIntensity = lightIntensity,
+                LightTemperature = lightTemperature,
+            };
+        }
+
+        private static RenderParameters Interpolate(RenderParameters from, RenderParameters to, float t)
+        {
+            return new RenderParameters
+            {
+                Transparency = Mathf.Lerp(from.Transparency, to.Transparency, t),
+                Glow = Mathf.Lerp(from.Glow, to.Glow, t),
+                Smoothness = Mathf.Lerp(from.Smoothness, to.Smoothness, t),
+                Emission = Mathf.Lerp(from.Emission, to.Emission, t),
+                LightIntensity = Mathf.Lerp(from.LightIntensity, to.LightIntensity, t),
+                LightTemperature = Mathf.Lerp(from.LightTemperature, to.LightTemperature, t),
+            };
+        }
+
+        private void ApplyParameters(RenderParameters parameters)
+        {
+            transparency = parameters.Transparency;
+            glow = parameters.Glow;
+            smoothness = parameters.Smoothness;
+            emission = parameters.Emission;
+            lightIntensity = parameters.LightIntensity;
+            lightTemperature = parameters.LightTemperature;
+
+            HandlePropertiesEdited();
+        }
 
         private static float EaseInQuad(float start, float end, float value)
         {